@@ -0,0 +1,29 @@
+#!/bin/sh
+# Checks that a panic in the UI hands the terminal back as it found it:
+# runs `midir-tui --crash-test` on a fresh pseudo-terminal from `script` and
+# compares `stty -g` before and after. Needs a MIDI backend to reach the UI.
+#
+#   sh examples/crash-test.sh [path/to/midir-tui]
+set -eu
+
+bin=${1:-target/debug/midir-tui}
+[ -x "$bin" ] || cargo build --quiet
+dir=$(mktemp -d)
+trap 'rm -rf "$dir"' EXIT
+
+script -qec "stty -g > '$dir/before'; '$bin' --crash-test 2> '$dir/stderr'; \
+    echo \$? > '$dir/status'; stty -g > '$dir/after'" /dev/null > /dev/null
+
+status=$(cat "$dir/status")
+if ! grep -q "forced panic" "$dir/stderr"; then
+    echo "crash-test: midir-tui exited $status without reaching the panic:" >&2
+    cat "$dir/stderr" >&2
+    exit 1
+fi
+if ! cmp -s "$dir/before" "$dir/after"; then
+    echo "crash-test: terminal settings changed:" >&2
+    echo "  before: $(cat "$dir/before")" >&2
+    echo "  after:  $(cat "$dir/after")" >&2
+    exit 1
+fi
+echo "crash-test: panic message printed and terminal restored (exit $status)"
@@ -0,0 +1,168 @@
+//! Port enumeration, and the keys that identify a port across rescans.
+
+use anyhow::{Context, Result};
+use midir::{MidiInput, MidiOutput};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Hash)]
+pub enum MidiKind {
+    Input,
+    Output,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Hash)]
+pub struct DeviceKey {
+    pub name: String,
+    pub kind: MidiKind,
+    /// Tells identically named ports of one kind apart: 0 for the first in
+    /// backend order, 1 for the next, and so on. Keys saved before this field
+    /// existed load as 0 and so match the first port with the name.
+    #[serde(default)]
+    pub instance: usize,
+}
+
+impl DeviceKey {
+    /// Name for display, numbered when it is a duplicate.
+    pub fn label(&self) -> String {
+        if self.instance == 0 {
+            self.name.clone()
+        } else {
+            format!("{} #{}", self.name, self.instance + 1)
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct DeviceItem {
+    pub key: DeviceKey,
+    pub index: usize, // index within its kind (as provided by midir at collection time)
+}
+
+/// Where `dev` is among the current port `names` (as numbered by
+/// `collect_devices`): indices shift on hotplug, so match the name and
+/// instance first and only fall back to the cached index.
+pub fn resolve_port(names: &[String], dev: &DeviceItem) -> usize {
+    names
+        .iter()
+        .enumerate()
+        .filter(|(_, n)| **n == dev.key.name)
+        .nth(dev.key.instance)
+        .map_or(dev.index, |(i, _)| i)
+}
+
+/// Every input and output port, inputs first, each kind sorted by name.
+pub fn collect_devices() -> Result<Vec<DeviceItem>> {
+    let inp = MidiInput::new("midir-tui").context("Failed to create MidiInput")?;
+    let out = MidiOutput::new("midir-tui").context("Failed to create MidiOutput")?;
+
+    let mut items: Vec<DeviceItem> = Vec::new();
+
+    // Inputs
+    for (idx, port) in inp.ports().iter().enumerate() {
+        let name = inp
+            .port_name(port)
+            .unwrap_or_else(|_| format!("Input #{idx}"));
+        items.push(DeviceItem {
+            key: DeviceKey {
+                name,
+                kind: MidiKind::Input,
+                instance: 0,
+            },
+            index: idx,
+        });
+    }
+
+    // Outputs
+    for (idx, port) in out.ports().iter().enumerate() {
+        let name = out
+            .port_name(port)
+            .unwrap_or_else(|_| format!("Output #{idx}"));
+        items.push(DeviceItem {
+            key: DeviceKey {
+                name,
+                kind: MidiKind::Output,
+                instance: 0,
+            },
+            index: idx,
+        });
+    }
+
+    // Number duplicates in backend (index) order
+    for i in 0..items.len() {
+        items[i].key.instance = items[..i]
+            .iter()
+            .filter(|d| d.key.kind == items[i].key.kind && d.key.name == items[i].key.name)
+            .count();
+    }
+
+    sort_devices(&mut items);
+    Ok(items)
+}
+
+/// Sort by kind then name.
+pub fn sort_devices(items: &mut [DeviceItem]) {
+    items.sort_by(|a, b| match (&a.key.kind, &b.key.kind) {
+        (MidiKind::Input, MidiKind::Output) => std::cmp::Ordering::Less,
+        (MidiKind::Output, MidiKind::Input) => std::cmp::Ordering::Greater,
+        _ => a
+            .key
+            .name
+            .to_lowercase()
+            .cmp(&b.key.name.to_lowercase())
+            .then(a.key.instance.cmp(&b.key.instance)),
+    });
+}
+
+/// Finds a listed device of `kind` by exact name, falling back to a
+/// case-insensitive substring match.
+pub fn match_device<'a>(
+    devices: &'a [DeviceItem],
+    kind: &MidiKind,
+    wanted: &str,
+) -> Option<&'a DeviceItem> {
+    let lower = wanted.to_lowercase();
+    let of_kind = || devices.iter().filter(|d| &d.key.kind == kind);
+    of_kind()
+        .find(|d| d.key.name == wanted || d.key.label() == wanted)
+        .or_else(|| of_kind().find(|d| d.key.name.to_lowercase().contains(&lower)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn port(name: &str, instance: usize, index: usize) -> DeviceItem {
+        DeviceItem {
+            key: DeviceKey {
+                name: name.to_string(),
+                kind: MidiKind::Input,
+                instance,
+            },
+            index,
+        }
+    }
+
+    fn names(list: &[&str]) -> Vec<String> {
+        list.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn resolve_port_follows_names_across_reorders() {
+        // Collected as Synth, Pad, Synth, Keys; then hotplug shuffled them
+        let now = names(&["Keys", "Synth", "Pad", "Synth"]);
+        assert_eq!(resolve_port(&now, &port("Pad", 0, 1)), 2);
+        assert_eq!(resolve_port(&now, &port("Keys", 0, 3)), 0);
+        // Duplicates keep their backend order, whatever the cached index says
+        assert_eq!(resolve_port(&now, &port("Synth", 0, 0)), 1);
+        assert_eq!(resolve_port(&now, &port("Synth", 1, 2)), 3);
+    }
+
+    #[test]
+    fn resolve_port_falls_back_to_the_cached_index() {
+        let now = names(&["Keys", "Synth"]);
+        // Gone entirely, or fewer instances than remembered
+        assert_eq!(resolve_port(&now, &port("Pad", 0, 1)), 1);
+        assert_eq!(resolve_port(&now, &port("Synth", 1, 7)), 7);
+        assert_eq!(resolve_port(&[], &port("Synth", 0, 0)), 0);
+    }
+}
@@ -0,0 +1,104 @@
+//! JSON-lines stream of incoming MIDI for TCP clients (`--json-tcp`).
+//!
+//! Any number of clients may connect; each gets one JSON object per line
+//! from the moment it connects. Writes happen on a background thread so a
+//! slow dashboard can't stall the UI, and a client whose write fails or
+//! times out is dropped. Lines beyond `QUEUE_LINES` waiting for the writer
+//! are discarded rather than piling up behind a stalled client.
+
+use std::{
+    io::Write,
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+
+/// A client that takes longer than this for one line is disconnected.
+const WRITE_TIMEOUT: Duration = Duration::from_millis(250);
+/// Lines queued for the writer thread before new ones are dropped.
+const QUEUE_LINES: usize = 1024;
+
+pub struct JsonStream {
+    local_addr: SocketAddr,
+    // Connected clients, kept outside the lock for the UI to read per message
+    connected: Arc<AtomicUsize>,
+    tx: mpsc::SyncSender<String>,
+}
+
+impl JsonStream {
+    /// Listens on `addr` (`host:port`) and starts accepting clients.
+    pub fn bind(addr: &str) -> Result<Self> {
+        let listener =
+            TcpListener::bind(addr).with_context(|| format!("listen on {addr} failed"))?;
+        let local_addr = listener.local_addr()?;
+        // Accepted clients not yet taken by the writer
+        let clients: Arc<Mutex<Vec<TcpStream>>> = Arc::default();
+        let connected: Arc<AtomicUsize> = Arc::default();
+        {
+            let (clients, connected) = (Arc::clone(&clients), Arc::clone(&connected));
+            thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    if stream.set_write_timeout(Some(WRITE_TIMEOUT)).is_err() {
+                        continue;
+                    }
+                    let _ = stream.set_nodelay(true);
+                    if let Ok(mut clients) = clients.lock() {
+                        clients.push(stream);
+                        connected.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            });
+        }
+        let (tx, rx) = mpsc::sync_channel::<String>(QUEUE_LINES);
+        {
+            let connected = Arc::clone(&connected);
+            thread::spawn(move || {
+                // Written to without the lock, so a slow client never blocks
+                // the acceptor
+                let mut writing: Vec<TcpStream> = Vec::new();
+                for line in rx {
+                    if let Ok(mut clients) = clients.lock() {
+                        writing.append(&mut clients);
+                    }
+                    let before = writing.len();
+                    writing.retain_mut(|c| c.write_all(line.as_bytes()).is_ok());
+                    connected.fetch_sub(before - writing.len(), Ordering::Relaxed);
+                }
+            });
+        }
+        Ok(Self {
+            local_addr,
+            connected,
+            tx,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn client_count(&self) -> usize {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    /// Queues `record` as one line for every connected client.
+    pub fn send<T: Serialize>(&self, record: &T) -> Result<()> {
+        if self.client_count() == 0 {
+            return Ok(());
+        }
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+        match self.tx.try_send(line) {
+            Ok(()) | Err(mpsc::TrySendError::Full(_)) => Ok(()),
+            // Only once the writer thread is gone
+            Err(mpsc::TrySendError::Disconnected(_)) => Err(anyhow!("JSON stream writer stopped")),
+        }
+    }
+}
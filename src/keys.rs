@@ -0,0 +1,541 @@
+//! Key bindings for the main view: named actions mapped to key chords.
+//! Modal screens (prompts, popups, the virtual keyboard) keep fixed keys.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Result};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    SelectUp,
+    SelectDown,
+    FocusLeft,
+    FocusRight,
+    ToggleOpen,
+    Mark,
+    OpenMarked,
+    OpenPair,
+    CloseAll,
+    OpenAllInputs,
+    OpenAllOutputs,
+    UndoClose,
+    Refresh,
+    Freeze,
+    Filter,
+    Favorite,
+    HideDevice,
+    ShowHidden,
+    SortMode,
+    Recent,
+    ConfigDir,
+    AlertLearn,
+    AlertStyle,
+    MidiLearn,
+    ChannelDown,
+    ChannelUp,
+    NoteTrail,
+    MpeView,
+    ByteFilter,
+    ClearChannelFilter,
+    Group,
+    Timing,
+    Hex,
+    SplitColumns,
+    SysexDump,
+    CopyHex,
+    MuteCc,
+    UnmuteCcs,
+    HideNotes,
+    HideCc,
+    HideProgram,
+    HidePitchBend,
+    HideSysex,
+    HideRealtime,
+    InputIgnore,
+    Keyboard,
+    RouteMark,
+    RouteToggle,
+    RouteEdit,
+    LatencyProbe,
+    VelocityCurve,
+    SaveLog,
+    ExportDevices,
+    LongCapture,
+    Playback,
+    Quantize,
+    ClockOut,
+    ClockFaster,
+    ClockSlower,
+    TestSequence,
+    SendHex,
+    VirtualInput,
+    VirtualOutput,
+    ResetCounts,
+    DumpState,
+    Theme,
+    COctave,
+    LogPageUp,
+    LogPageDown,
+    LogTop,
+    LogBottom,
+    PauseLog,
+    Help,
+    Panic,
+    Quit,
+}
+
+impl Action {
+    /// Every action, in the order the help footer lists them.
+    pub const ALL: [Action; 75] = [
+        Action::SelectUp,
+        Action::SelectDown,
+        Action::FocusLeft,
+        Action::FocusRight,
+        Action::ToggleOpen,
+        Action::Mark,
+        Action::OpenMarked,
+        Action::OpenPair,
+        Action::CloseAll,
+        Action::OpenAllInputs,
+        Action::OpenAllOutputs,
+        Action::UndoClose,
+        Action::Refresh,
+        Action::Freeze,
+        Action::Filter,
+        Action::Favorite,
+        Action::HideDevice,
+        Action::ShowHidden,
+        Action::SortMode,
+        Action::Recent,
+        Action::ConfigDir,
+        Action::AlertLearn,
+        Action::AlertStyle,
+        Action::MidiLearn,
+        Action::ChannelDown,
+        Action::ChannelUp,
+        Action::NoteTrail,
+        Action::MpeView,
+        Action::ByteFilter,
+        Action::ClearChannelFilter,
+        Action::Group,
+        Action::Timing,
+        Action::Hex,
+        Action::SplitColumns,
+        Action::SysexDump,
+        Action::CopyHex,
+        Action::MuteCc,
+        Action::UnmuteCcs,
+        Action::HideNotes,
+        Action::HideCc,
+        Action::HideProgram,
+        Action::HidePitchBend,
+        Action::HideSysex,
+        Action::HideRealtime,
+        Action::InputIgnore,
+        Action::Keyboard,
+        Action::RouteMark,
+        Action::RouteToggle,
+        Action::RouteEdit,
+        Action::LatencyProbe,
+        Action::VelocityCurve,
+        Action::SaveLog,
+        Action::ExportDevices,
+        Action::LongCapture,
+        Action::Playback,
+        Action::Quantize,
+        Action::ClockOut,
+        Action::ClockFaster,
+        Action::ClockSlower,
+        Action::TestSequence,
+        Action::SendHex,
+        Action::VirtualInput,
+        Action::VirtualOutput,
+        Action::ResetCounts,
+        Action::DumpState,
+        Action::Theme,
+        Action::COctave,
+        Action::LogPageUp,
+        Action::LogPageDown,
+        Action::LogTop,
+        Action::LogBottom,
+        Action::PauseLog,
+        Action::Help,
+        Action::Panic,
+        Action::Quit,
+    ];
+
+    /// Name used for the action in `config.json`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::SelectUp => "select_up",
+            Action::SelectDown => "select_down",
+            Action::FocusLeft => "focus_left",
+            Action::FocusRight => "focus_right",
+            Action::ToggleOpen => "toggle_open",
+            Action::Mark => "mark",
+            Action::OpenMarked => "open_marked",
+            Action::OpenPair => "open_pair",
+            Action::CloseAll => "close_all",
+            Action::OpenAllInputs => "open_all_inputs",
+            Action::OpenAllOutputs => "open_all_outputs",
+            Action::UndoClose => "undo_close",
+            Action::Refresh => "refresh",
+            Action::Freeze => "freeze",
+            Action::Filter => "filter",
+            Action::Favorite => "favorite",
+            Action::HideDevice => "hide_device",
+            Action::ShowHidden => "show_hidden",
+            Action::SortMode => "sort_mode",
+            Action::Recent => "recent",
+            Action::ConfigDir => "config_dir",
+            Action::AlertLearn => "alert_learn",
+            Action::MidiLearn => "midi_learn",
+            Action::AlertStyle => "alert_style",
+            Action::ChannelDown => "channel_down",
+            Action::ChannelUp => "channel_up",
+            Action::NoteTrail => "note_trail",
+            Action::MpeView => "mpe_view",
+            Action::ByteFilter => "byte_filter",
+            Action::ClearChannelFilter => "clear_channel_filter",
+            Action::Group => "group",
+            Action::Timing => "timing",
+            Action::Hex => "hex",
+            Action::SplitColumns => "split_columns",
+            Action::SysexDump => "sysex_dump",
+            Action::CopyHex => "copy_hex",
+            Action::MuteCc => "mute_cc",
+            Action::UnmuteCcs => "unmute_ccs",
+            Action::HideNotes => "hide_notes",
+            Action::HideCc => "hide_cc",
+            Action::HideProgram => "hide_program",
+            Action::HidePitchBend => "hide_pitch_bend",
+            Action::HideSysex => "hide_sysex",
+            Action::HideRealtime => "hide_realtime",
+            Action::InputIgnore => "input_ignore",
+            Action::Keyboard => "keyboard",
+            Action::RouteMark => "route_mark",
+            Action::RouteToggle => "route_toggle",
+            Action::RouteEdit => "route_edit",
+            Action::LatencyProbe => "latency_probe",
+            Action::VelocityCurve => "velocity_curve",
+            Action::SaveLog => "save_log",
+            Action::ExportDevices => "export_devices",
+            Action::LongCapture => "long_capture",
+            Action::Playback => "playback",
+            Action::Quantize => "quantize",
+            Action::ClockOut => "clock_out",
+            Action::ClockFaster => "clock_faster",
+            Action::ClockSlower => "clock_slower",
+            Action::TestSequence => "test_sequence",
+            Action::SendHex => "send_hex",
+            Action::VirtualInput => "virtual_input",
+            Action::VirtualOutput => "virtual_output",
+            Action::ResetCounts => "reset_counts",
+            Action::DumpState => "dump_state",
+            Action::Theme => "theme",
+            Action::COctave => "c_octave",
+            Action::LogPageUp => "log_page_up",
+            Action::LogPageDown => "log_page_down",
+            Action::LogTop => "log_top",
+            Action::LogBottom => "log_bottom",
+            Action::PauseLog => "pause_log",
+            Action::Help => "help",
+            Action::Panic => "panic",
+            Action::Quit => "quit",
+        }
+    }
+
+    /// Short description for the help footer.
+    /// The action with config name `name`.
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.into_iter().find(|a| a.name() == name)
+    }
+
+    pub fn help(self) -> &'static str {
+        match self {
+            Action::SelectUp => "up",
+            Action::SelectDown => "down",
+            Action::FocusLeft => "focus-list",
+            Action::FocusRight => "focus-details",
+            Action::ToggleOpen => "open/close",
+            Action::Mark => "mark",
+            Action::OpenMarked => "open-marked",
+            Action::OpenPair => "open-pair",
+            Action::CloseAll => "close-all",
+            Action::OpenAllInputs => "open-all-in",
+            Action::OpenAllOutputs => "open-all-out",
+            Action::UndoClose => "undo-close",
+            Action::Refresh => "refresh",
+            Action::Freeze => "freeze",
+            Action::Filter => "filter",
+            Action::Favorite => "favorite",
+            Action::HideDevice => "hide-device",
+            Action::ShowHidden => "show-hidden",
+            Action::SortMode => "sort",
+            Action::Recent => "recent",
+            Action::ConfigDir => "config-dir",
+            Action::AlertLearn => "alert",
+            Action::MidiLearn => "midi-learn",
+            Action::AlertStyle => "alert-style",
+            Action::ChannelDown => "channel-",
+            Action::ChannelUp => "channel+",
+            Action::NoteTrail => "trail",
+            Action::MpeView => "mpe",
+            Action::ByteFilter => "byte-filter",
+            Action::ClearChannelFilter => "all-channels",
+            Action::Group => "group",
+            Action::Timing => "timing",
+            Action::Hex => "hex",
+            Action::SplitColumns => "split-hex",
+            Action::SysexDump => "sysex-dump",
+            Action::CopyHex => "copy-hex",
+            Action::MuteCc => "mute-cc",
+            Action::UnmuteCcs => "unmute-ccs",
+            Action::HideNotes => "hide-notes",
+            Action::HideCc => "hide-cc",
+            Action::HideProgram => "hide-program",
+            Action::HidePitchBend => "hide-bend",
+            Action::HideSysex => "hide-sysex",
+            Action::HideRealtime => "hide-clock",
+            Action::InputIgnore => "input-ignore",
+            Action::Keyboard => "keyboard",
+            Action::RouteMark => "route-mark",
+            Action::RouteToggle => "route",
+            Action::RouteEdit => "route-edit",
+            Action::LatencyProbe => "latency",
+            Action::VelocityCurve => "velocity-curve",
+            Action::SaveLog => "save-log",
+            Action::ExportDevices => "export-devices",
+            Action::LongCapture => "long-capture",
+            Action::Playback => "play/stop .mid",
+            Action::Quantize => "quantize .mid",
+            Action::ClockOut => "clock-out",
+            Action::ClockFaster => "bpm+",
+            Action::ClockSlower => "bpm-",
+            Action::TestSequence => "test-scale",
+            Action::SendHex => "send-hex",
+            Action::VirtualInput => "virtual-in",
+            Action::VirtualOutput => "virtual-out",
+            Action::ResetCounts => "reset-counts",
+            Action::DumpState => "dump-state",
+            Action::Theme => "theme",
+            Action::COctave => "middle-C",
+            Action::LogPageUp => "log-page-up",
+            Action::LogPageDown => "log-page-down",
+            Action::LogTop => "log-top",
+            Action::LogBottom => "log-live",
+            Action::PauseLog => "pause",
+            Action::Help => "help",
+            Action::Panic => "panic",
+            Action::Quit => "quit",
+        }
+    }
+
+    fn default_chords(self) -> &'static [&'static str] {
+        match self {
+            Action::SelectUp => &["Up"],
+            Action::SelectDown => &["Down"],
+            Action::FocusLeft => &["Left"],
+            Action::FocusRight => &["Right"],
+            Action::ToggleOpen => &["Enter"],
+            Action::Mark => &["s", "Insert"],
+            Action::OpenMarked => &["Alt+Enter", "e"],
+            Action::OpenPair => &["Ctrl+p"],
+            Action::CloseAll => &["C"],
+            Action::OpenAllInputs => &["o"],
+            Action::OpenAllOutputs => &["Ctrl+o"],
+            Action::UndoClose => &["Ctrl+z"],
+            Action::Refresh => &["r"],
+            Action::Freeze => &["F"],
+            Action::Filter => &["/"],
+            Action::Favorite => &["*"],
+            Action::HideDevice => &["X"],
+            Action::ShowHidden => &["H"],
+            Action::SortMode => &["Ctrl+s"],
+            Action::Recent => &["R"],
+            Action::ConfigDir => &["O"],
+            Action::AlertLearn => &["a"],
+            Action::MidiLearn => &["l"],
+            Action::AlertStyle => &["A"],
+            Action::ChannelDown => &["["],
+            Action::ChannelUp => &["]"],
+            Action::NoteTrail => &["n"],
+            Action::MpeView => &["Ctrl+e"],
+            Action::ByteFilter => &["b"],
+            Action::ClearChannelFilter => &["c"],
+            Action::Group => &["g"],
+            Action::Timing => &["d"],
+            Action::Hex => &["h"],
+            Action::SplitColumns => &["S"],
+            Action::SysexDump => &["x"],
+            Action::CopyHex => &["y"],
+            Action::MuteCc => &["u"],
+            Action::UnmuteCcs => &["U"],
+            Action::HideNotes => &["F1"],
+            Action::HideCc => &["F2"],
+            Action::HideProgram => &["F3"],
+            Action::HidePitchBend => &["F4"],
+            Action::HideSysex => &["F5"],
+            Action::HideRealtime => &["F6"],
+            Action::InputIgnore => &["I"],
+            Action::Keyboard => &["K"],
+            Action::RouteMark => &["m"],
+            Action::RouteToggle => &["t"],
+            Action::RouteEdit => &["Ctrl+t"],
+            Action::LatencyProbe => &["Ctrl+l"],
+            Action::VelocityCurve => &["Ctrl+v"],
+            Action::SaveLog => &["w"],
+            Action::ExportDevices => &["E"],
+            Action::LongCapture => &["L"],
+            Action::Playback => &["P"],
+            Action::Quantize => &["Q"],
+            Action::ClockOut => &["M"],
+            Action::ClockFaster => &["+", "="],
+            Action::ClockSlower => &["-"],
+            Action::TestSequence => &["T"],
+            Action::SendHex => &[":"],
+            Action::VirtualInput => &["v"],
+            Action::VirtualOutput => &["V"],
+            Action::ResetCounts => &["Z"],
+            Action::DumpState => &["D"],
+            Action::Theme => &["Y"],
+            Action::COctave => &["N"],
+            Action::LogPageUp => &["PageUp"],
+            Action::LogPageDown => &["PageDown"],
+            Action::LogTop => &["Home"],
+            Action::LogBottom => &["End"],
+            Action::PauseLog => &["Space"],
+            // Taken from `panic_key` in `Keymap::new`
+            Action::Help => &["?"],
+            Action::Panic => &[],
+            Action::Quit => &["q", "Esc"],
+        }
+    }
+}
+
+type Chord = (KeyCode, KeyModifiers);
+
+pub struct Keymap {
+    bindings: HashMap<Chord, Action>,
+    labels: HashMap<Action, String>,
+}
+
+impl Keymap {
+    /// Builds the map from the defaults with `overrides` (action name → chords)
+    /// replacing whole actions. Fails on unknown actions, unparsable chords and
+    /// chords bound to two actions.
+    pub fn new(overrides: &HashMap<String, Vec<String>>, panic_key: char) -> Result<Self> {
+        for name in overrides.keys() {
+            if Action::from_name(name).is_none() {
+                bail!("unknown action `{name}`");
+            }
+        }
+
+        let panic_default = [panic_key.to_string()];
+        let mut bindings = HashMap::new();
+        let mut labels = HashMap::new();
+        for action in Action::ALL {
+            let chords: Vec<&str> = match overrides.get(action.name()) {
+                Some(list) => list.iter().map(String::as_str).collect(),
+                None if action == Action::Panic => {
+                    panic_default.iter().map(String::as_str).collect()
+                }
+                None => action.default_chords().to_vec(),
+            };
+            for text in &chords {
+                let chord = parse_chord(text)?;
+                if let Some(other) = bindings.insert(chord, action) {
+                    bail!(
+                        "`{text}` is bound to both {} and {}",
+                        other.name(),
+                        action.name()
+                    );
+                }
+            }
+            labels.insert(action, chords.join("/"));
+        }
+        Ok(Self { bindings, labels })
+    }
+
+    /// The built-in bindings, with `p` as the panic key.
+    pub fn defaults() -> Self {
+        Self::new(&HashMap::new(), 'p').expect("default key bindings conflict")
+    }
+
+    pub fn action(&self, key: &KeyEvent) -> Option<Action> {
+        self.bindings
+            .get(&normalize(key.code, key.modifiers))
+            .copied()
+    }
+
+    /// The chords bound to `action` as written in the config, e.g. `q/Esc`.
+    pub fn label(&self, action: Action) -> &str {
+        self.labels.get(&action).map_or("", String::as_str)
+    }
+}
+
+/// Terminals disagree on whether a capital letter carries SHIFT, so the
+/// character alone decides for `Char` keys.
+fn normalize(code: KeyCode, mods: KeyModifiers) -> Chord {
+    match code {
+        KeyCode::Char(_) => (code, mods - KeyModifiers::SHIFT),
+        _ => (code, mods),
+    }
+}
+
+/// Parses chords like `k`, `Shift+C`, `Ctrl+r` or `PageDown`.
+fn parse_chord(text: &str) -> Result<Chord> {
+    let mut mods = KeyModifiers::NONE;
+    let mut parts: Vec<&str> = text.split('+').collect();
+    // A lone "+" (or "Ctrl++") names the plus key itself
+    if text.ends_with("++") || text == "+" {
+        parts.truncate(parts.len().saturating_sub(2));
+        parts.push("+");
+    }
+    let Some((key, prefix)) = parts.split_last() else {
+        bail!("empty key binding");
+    };
+    for m in prefix {
+        mods |= match m.to_ascii_lowercase().as_str() {
+            "ctrl" | "control" => KeyModifiers::CONTROL,
+            "alt" => KeyModifiers::ALT,
+            "shift" => KeyModifiers::SHIFT,
+            _ => bail!("unknown modifier `{m}` in `{text}`"),
+        };
+    }
+    let code = match *key {
+        "Up" => KeyCode::Up,
+        "Down" => KeyCode::Down,
+        "Left" => KeyCode::Left,
+        "Right" => KeyCode::Right,
+        "Enter" => KeyCode::Enter,
+        "Esc" => KeyCode::Esc,
+        "Tab" => KeyCode::Tab,
+        "Backspace" => KeyCode::Backspace,
+        "Delete" => KeyCode::Delete,
+        "Insert" => KeyCode::Insert,
+        "Home" => KeyCode::Home,
+        "End" => KeyCode::End,
+        "PageUp" => KeyCode::PageUp,
+        "PageDown" => KeyCode::PageDown,
+        "Space" => KeyCode::Char(' '),
+        k if k.len() > 1 && k.starts_with('F') => {
+            let n: u8 = k[1..]
+                .parse()
+                .map_err(|_| anyhow!("unknown key `{k}` in `{text}`"))?;
+            KeyCode::F(n)
+        }
+        k => {
+            let mut chars = k.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if mods.contains(KeyModifiers::SHIFT) => {
+                    KeyCode::Char(c.to_ascii_uppercase())
+                }
+                (Some(c), None) => KeyCode::Char(c),
+                _ => bail!("unknown key `{k}` in `{text}`"),
+            }
+        }
+    };
+    Ok(normalize(code, mods))
+}
@@ -0,0 +1,27 @@
+//! The MIDI side of midir-tui as a library: port enumeration, message
+//! decoding, and [`MidiMonitor`] for receiving decoded input over a channel,
+//! plus thru routes, MPE state and Standard MIDI File reading and writing.
+//!
+//! ```no_run
+//! use midir_tui::{collect_devices, MidiKind, MidiMonitor};
+//!
+//! let (mut monitor, events) = MidiMonitor::new("my-tool");
+//! for dev in collect_devices()?.iter().filter(|d| d.key.kind == MidiKind::Input) {
+//!     monitor.open(dev)?;
+//! }
+//! for ev in events {
+//!     println!("{}: {}", ev.device.label(), ev.decoded());
+//! }
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+pub mod devices;
+pub mod midi;
+pub mod monitor;
+pub mod mpe;
+pub mod route;
+pub mod smf;
+
+pub use devices::{collect_devices, DeviceItem, DeviceKey, MidiKind};
+pub use midi::decode_midi;
+pub use monitor::{MidiMonitor, MonitorEvent};
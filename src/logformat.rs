@@ -0,0 +1,122 @@
+//! Log line templates (`log_format` in `config.json`).
+//!
+//! A template is text with `{token}` fields, such as
+//! `{ts} {dev} {type} ch{ch} {data}`; `{{` and `}}` stand for literal braces.
+//! Fields with no value for a message (the channel of a Clock) render empty.
+
+use anyhow::{bail, Result};
+
+/// Today's decoded line: direction, decoded text and device name.
+pub const DEFAULT_LOG_FORMAT: &str = "{dir}  {decoded}  [{dev}]";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    /// Wall-clock time, `2024-01-31 12:00:00.000` (UTC)
+    Ts,
+    /// Milliseconds since the Unix epoch
+    UnixMs,
+    /// `IN` or `OUT`
+    Dir,
+    /// Device name
+    Dev,
+    /// Message type such as `Note On` or `CC`
+    Type,
+    /// MIDI channel 1-16
+    Ch,
+    /// Bytes as spaced hex
+    Data,
+    /// Byte count
+    Len,
+    /// The decoder's text, with device note/CC names
+    Decoded,
+}
+
+const FIELDS: [(&str, Field); 9] = [
+    ("ts", Field::Ts),
+    ("unix_ms", Field::UnixMs),
+    ("dir", Field::Dir),
+    ("dev", Field::Dev),
+    ("type", Field::Type),
+    ("ch", Field::Ch),
+    ("data", Field::Data),
+    ("len", Field::Len),
+    ("decoded", Field::Decoded),
+];
+
+#[derive(Clone, Debug)]
+enum Piece {
+    Text(String),
+    Field(Field),
+}
+
+/// A parsed template.
+#[derive(Clone, Debug)]
+pub struct LogTemplate {
+    pieces: Vec<Piece>,
+}
+
+impl Default for LogTemplate {
+    fn default() -> Self {
+        Self::parse(DEFAULT_LOG_FORMAT).expect("default log format parses")
+    }
+}
+
+impl LogTemplate {
+    pub fn parse(template: &str) -> Result<Self> {
+        let mut pieces = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let Some(end) = rest.find('}') else {
+                        bail!("unclosed {{ in \"{template}\"");
+                    };
+                    let name = &rest[..end];
+                    let Some(&(_, field)) = FIELDS.iter().find(|(n, _)| *n == name) else {
+                        let known: Vec<&str> = FIELDS.iter().map(|(n, _)| *n).collect();
+                        bail!("unknown token {{{name}}}; known: {}", known.join(", "));
+                    };
+                    if !text.is_empty() {
+                        pieces.push(Piece::Text(std::mem::take(&mut text)));
+                    }
+                    pieces.push(Piece::Field(field));
+                    chars = rest[end + 1..].chars();
+                }
+                '}' => bail!("unmatched }} in \"{template}\" (write }}}} for a brace)"),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            pieces.push(Piece::Text(text));
+        }
+        Ok(Self { pieces })
+    }
+
+    pub fn uses(&self, field: Field) -> bool {
+        self.pieces
+            .iter()
+            .any(|p| matches!(p, Piece::Field(f) if *f == field))
+    }
+
+    /// Fills in the template, asking `value` for each field it names.
+    pub fn render(&self, mut value: impl FnMut(Field) -> String) -> String {
+        let mut out = String::new();
+        for piece in &self.pieces {
+            match piece {
+                Piece::Text(text) => out.push_str(text),
+                Piece::Field(field) => out.push_str(&value(*field)),
+            }
+        }
+        out
+    }
+}
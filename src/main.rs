@@ -1,14 +1,48 @@
+mod jsonstream;
+mod keys;
+mod logformat;
+mod namematch;
+#[cfg(feature = "osc")]
+mod osc;
+#[cfg(feature = "rtpmidi")]
+mod rtpmidi;
+mod theme;
+
+use jsonstream::JsonStream;
+use keys::{Action, Keymap};
+use logformat::{Field, LogTemplate, DEFAULT_LOG_FORMAT};
+use midir_tui::{
+    collect_devices,
+    devices::{match_device, resolve_port, sort_devices},
+    midi::{
+        self, apply_velocity_curve, AlertTrigger, ClockTempo, ControlTracker, DeviceNames,
+        NoteEvent, ParamChange, ParamEvent, ParamTracker, VelocityCurve,
+    },
+    monitor::{connect_input, restore_running_status, CHANNEL_CAPACITY},
+    mpe::{self, MpeState},
+    route::Route,
+    smf, DeviceItem, DeviceKey, MidiKind, MidiMonitor,
+};
+use namematch::NamePattern;
+use theme::{Theme, ThemeName};
+
 use std::{
-    collections::{HashMap, VecDeque},
-    fs, io,
-    path::PathBuf,
-    time::{Duration, Instant},
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
+    fmt, fs,
+    io::{self, IsTerminal, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant, SystemTime},
 };
 
-use anyhow::{Context, Result};
 use anyhow::anyhow;
+use anyhow::{Context, Result};
 use crossterm::{
-    event::{self, Event, KeyCode, KeyModifiers},
+    event::{
+        self, DisableFocusChange, DisableMouseCapture, EnableFocusChange, EnableMouseCapture,
+        Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+    },
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
@@ -16,14 +50,40 @@ use directories::ProjectDirs;
 use midir::{MidiInput, MidiInputConnection, MidiOutput, MidiOutputConnection};
 use ratatui::{
     backend::CrosstermBackend,
-    layout::{Constraint, Direction, Layout, Margin, Rect},
+    layout::{Alignment, Constraint, Direction, Layout, Margin, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap},
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap},
     Terminal,
 };
 use serde::{Deserialize, Serialize};
-use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    mpsc::{self, Receiver, SyncSender, TrySendError},
+    Arc, Mutex,
+};
+
+/// Output connections are shared with input callbacks for thru routing.
+type SharedOutput = Arc<Mutex<OutputConn>>;
+/// Source input → outputs its callback forwards every message to.
+type ThruTable = Arc<Mutex<HashMap<DeviceKey, Vec<(SharedOutput, Route, VelocityCurve)>>>>;
+
+/// Anything `out_conns` can send to: a MIDI port, or a network session.
+enum OutputConn {
+    Midi(MidiOutputConnection),
+    #[cfg(feature = "rtpmidi")]
+    Rtp(rtpmidi::Session),
+}
+
+impl OutputConn {
+    fn send(&mut self, message: &[u8]) -> Result<()> {
+        match self {
+            OutputConn::Midi(conn) => conn.send(message).map_err(|e| anyhow!("{e}")),
+            #[cfg(feature = "rtpmidi")]
+            OutputConn::Rtp(session) => session.send(message),
+        }
+    }
+}
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 enum Focus {
@@ -31,28 +91,949 @@ enum Focus {
     Right,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Hash)]
-enum MidiKind {
-    Input,
-    Output,
+/// One row of the device list as drawn: a section header, or the device at
+/// this index of `App::devices`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum ListRow {
+    Header(MidiKind),
+    Device(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum LogDirection {
+    In,
+    Out,
+    Local, // generated by the app itself (status lines)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum EventKind {
+    Midi,
+    Status(String),
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Hash)]
-struct DeviceKey {
-    name: String,
-    kind: MidiKind,
+/// One line of the `--json-tcp` stream; `ts` is Unix time in milliseconds.
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    device: &'a str,
+    status: u8,
+    data: &'a [u8],
+    decoded: String,
+    ts: u64,
 }
 
+/// One entry of the live log. Display strings are derived at render time.
 #[derive(Clone, Debug)]
-struct DeviceItem {
-    key: DeviceKey,
-    index: usize, // index within its kind (as provided by midir at collection time)
+struct LoggedEvent {
+    ts: SystemTime,
+    // Same moment on the monotonic clock, for timing against our own sends
+    at: Instant,
+    stamp: u64, // midir timestamp in µs (per-connection base); 0 for status lines
+    device: Option<DeviceKey>,
+    direction: LogDirection,
+    kind: EventKind,
+    data: Vec<u8>,
+    // Part of an RPN/NRPN sequence, as read when it arrived
+    param: Option<ParamEvent>,
+    // µs since the device's previous incoming message, set when drained
+    delta_us: Option<u64>,
+}
+
+impl LoggedEvent {
+    fn status(msg: String) -> Self {
+        Self {
+            ts: SystemTime::now(),
+            at: Instant::now(),
+            stamp: 0,
+            device: None,
+            direction: LogDirection::Local,
+            kind: EventKind::Status(msg),
+            data: Vec::new(),
+            param: None,
+            delta_us: None,
+        }
+    }
+
+    fn direction_tag(&self) -> &'static str {
+        match self.direction {
+            LogDirection::In => "IN",
+            LogDirection::Out => "OUT",
+            LogDirection::Local => "··",
+        }
+    }
+
+    /// MIDI channel (0-15) of a channel voice message.
+    fn channel(&self) -> Option<u8> {
+        match self.data.first() {
+            Some(&status) if (0x80..0xF0).contains(&status) => Some(status & 0x0F),
+            _ => None,
+        }
+    }
+
+    fn is_incomplete_sysex(&self) -> bool {
+        self.data.first() == Some(&0xF0) && self.data.last() != Some(&0xF7)
+    }
+
+    fn display(&self) -> String {
+        match &self.kind {
+            EventKind::Status(msg) => format!("· {}", msg),
+            EventKind::Midi => {
+                let dir = self.direction_tag();
+                let name = self.device.as_ref().map(|d| d.name.as_str()).unwrap_or("");
+                let incomplete = if self.is_incomplete_sysex() {
+                    " (incomplete)"
+                } else {
+                    ""
+                };
+                format!(
+                    "{}  {:02X?}  (len {})  [{}]{}",
+                    dir,
+                    self.data,
+                    self.data.len(),
+                    name,
+                    incomplete
+                )
+            }
+        }
+    }
+}
+
+/// Worker threads' way into the log. It never blocks: the UI thread, the
+/// only reader, may be joining the sender, so a full channel counts as a drop
+/// like input does.
+#[derive(Clone)]
+struct StatusSender {
+    tx: SyncSender<LoggedEvent>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl StatusSender {
+    fn send(&self, msg: String) {
+        if let Err(TrySendError::Full(_)) = self.tx.try_send(LoggedEvent::status(msg)) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Raw byte matcher such as `B0 ?? 7F`: `??` matches any byte and the
+/// message length must equal the pattern length.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct BytePattern(Vec<Option<u8>>);
+
+impl BytePattern {
+    fn parse(s: &str) -> Result<Self> {
+        let bytes = s
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|t| !t.is_empty())
+            .map(|t| match t {
+                "?" | "??" => Ok(None),
+                _ => u8::from_str_radix(t, 16)
+                    .map(Some)
+                    .map_err(|_| anyhow!("not a hex byte: {t}")),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if bytes.is_empty() {
+            return Err(anyhow!("empty pattern"));
+        }
+        Ok(Self(bytes))
+    }
+
+    fn matches(&self, data: &[u8]) -> bool {
+        self.0.len() == data.len()
+            && self
+                .0
+                .iter()
+                .zip(data)
+                .all(|(p, b)| p.is_none_or(|p| p == *b))
+    }
+}
+
+/// `1 +12 3`: route number (1-based), transpose in semitones, and target
+/// channel 1-16 or `-` to keep each message's own.
+fn parse_route_edit(s: &str) -> Result<(usize, i8, Option<u8>)> {
+    let parts: Vec<&str> = s.split_whitespace().collect();
+    let [n, transpose, channel] = parts[..] else {
+        return Err(anyhow!("expected: route transpose channel"));
+    };
+    let n: usize = n
+        .parse()
+        .with_context(|| format!("bad route number: {n}"))?;
+    let transpose: i8 = transpose
+        .trim_start_matches('+')
+        .parse()
+        .ok()
+        .filter(|t: &i8| (-127..=127).contains(t))
+        .with_context(|| format!("bad transpose: {transpose}"))?;
+    let channel = match channel {
+        "-" => None,
+        c => Some(
+            c.parse::<u8>()
+                .ok()
+                .filter(|c| (1..=16).contains(c))
+                .with_context(|| format!("channel must be 1-16 or -: {c}"))?
+                - 1,
+        ),
+    };
+    Ok((n, transpose, channel))
+}
+
+/// Parses `path grid [bpm]` for quantizing, where grid is `4`, `8` or `16`
+/// (or `1/4` ...). The path may contain spaces; BPM defaults to `default_bpm`.
+fn parse_quantize(s: &str, default_bpm: f64) -> Result<(String, u64, f64)> {
+    let grid = |t: &str| match t.trim_start_matches("1/") {
+        "4" => Some(4),
+        "8" => Some(8),
+        "16" => Some(16),
+        _ => None,
+    };
+    let mut parts: Vec<&str> = s.split_whitespace().collect();
+    let last = parts.pop().context("expected: path grid [bpm]")?;
+    let (division, bpm) = match (parts.last().and_then(|t| grid(t)), last.parse::<f64>()) {
+        (Some(division), Ok(bpm)) if parts.len() > 1 => {
+            parts.pop();
+            (division, bpm)
+        }
+        _ => (
+            grid(last).with_context(|| format!("grid must be 4, 8 or 16: {last}"))?,
+            default_bpm,
+        ),
+    };
+    if !(CLOCK_BPM_RANGE.0..=CLOCK_BPM_RANGE.1).contains(&bpm) {
+        return Err(anyhow!(
+            "BPM must be {}-{}: {bpm}",
+            CLOCK_BPM_RANGE.0,
+            CLOCK_BPM_RANGE.1
+        ));
+    }
+    if parts.is_empty() {
+        return Err(anyhow!("expected: path grid [bpm]"));
+    }
+    Ok((parts.join(" "), division, bpm))
+}
+
+/// Parses hex such as `F0 7E 7F 06 01 F7` (space or comma separated).
+fn parse_hex_bytes(s: &str) -> Result<Vec<u8>> {
+    let bytes = s
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|t| !t.is_empty())
+        .map(|t| u8::from_str_radix(t, 16).map_err(|_| anyhow!("not a hex byte: {t}")))
+        .collect::<Result<Vec<_>>>()?;
+    if bytes.is_empty() {
+        return Err(anyhow!("no bytes given"));
+    }
+    Ok(bytes)
+}
+
+impl fmt::Display for BytePattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = self
+            .0
+            .iter()
+            .map(|b| b.map_or("??".to_string(), |b| format!("{b:02X}")))
+            .collect();
+        f.write_str(&parts.join(" "))
+    }
+}
+
+/// Set of message categories as bits; used for the log's hidden types.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct MsgTypes(u8);
+
+impl MsgTypes {
+    const NOTES: MsgTypes = MsgTypes(1 << 0);
+    const CC: MsgTypes = MsgTypes(1 << 1);
+    const PROGRAM: MsgTypes = MsgTypes(1 << 2);
+    const PITCH_BEND: MsgTypes = MsgTypes(1 << 3);
+    const SYSEX: MsgTypes = MsgTypes(1 << 4);
+    const REALTIME: MsgTypes = MsgTypes(1 << 5);
+    const ALL: [(MsgTypes, &'static str); 6] = [
+        (MsgTypes::NOTES, "notes"),
+        (MsgTypes::CC, "cc"),
+        (MsgTypes::PROGRAM, "program"),
+        (MsgTypes::PITCH_BEND, "bend"),
+        (MsgTypes::SYSEX, "sysex"),
+        (MsgTypes::REALTIME, "clock/realtime"),
+    ];
+
+    /// Category of a message by its status byte; aftertouch and system
+    /// common messages have none and are never hidden.
+    fn of(data: &[u8]) -> Option<MsgTypes> {
+        let status = *data.first()?;
+        match status {
+            0x80..=0x9F => Some(MsgTypes::NOTES),
+            0xB0..=0xBF => Some(MsgTypes::CC),
+            0xC0..=0xCF => Some(MsgTypes::PROGRAM),
+            0xE0..=0xEF => Some(MsgTypes::PITCH_BEND),
+            0xF0 | 0xF7 => Some(MsgTypes::SYSEX),
+            0xF8..=0xFF => Some(MsgTypes::REALTIME),
+            _ => None,
+        }
+    }
+
+    fn contains(self, other: MsgTypes) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    fn toggle(&mut self, other: MsgTypes) {
+        self.0 ^= other.0;
+    }
+
+    fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    fn name(self) -> &'static str {
+        MsgTypes::ALL
+            .iter()
+            .find(|(t, _)| *t == self)
+            .map_or("?", |(_, name)| name)
+    }
+}
+
+impl fmt::Display for MsgTypes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = MsgTypes::ALL
+            .iter()
+            .filter(|(t, _)| self.contains(*t))
+            .map(|(_, name)| *name)
+            .collect();
+        f.write_str(&names.join(" "))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PromptKind {
+    BytePattern,
+    PlayFile,
+    VirtualInput,
+    VirtualOutput,
+    SendHex,
+    RouteEdit,
+    Quantize,
+}
+
+impl PromptKind {
+    fn title(self) -> &'static str {
+        match self {
+            PromptKind::BytePattern => " Byte pattern (e.g. B0 ?? 7F; empty clears) ",
+            PromptKind::PlayFile => " Play .mid file to the selected output (path) ",
+            PromptKind::VirtualInput => " New virtual input port (name) ",
+            PromptKind::VirtualOutput => " New virtual output port (name) ",
+            PromptKind::SendHex => " Send hex bytes to the selected output (↑/↓ history) ",
+            PromptKind::RouteEdit => {
+                " Route number, transpose, channel (e.g. 1 +12 3; - keeps the channel) "
+            }
+            PromptKind::Quantize => {
+                " Quantize .mid file: path, grid 4/8/16, BPM (e.g. take.mid 16 120) "
+            }
+        }
+    }
+}
+
+/// A pending yes/no question; `y` performs the action, `n`/Esc cancels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Confirm {
+    Quit,
+    /// Close every open port; carries the count shown in the question.
+    CloseAll(usize),
+}
+
+impl Confirm {
+    fn question(self) -> String {
+        match self {
+            Confirm::Quit => "Quit? (y/n)".to_string(),
+            Confirm::CloseAll(n) => format!("Close all {n} ports? (y/n)"),
+        }
+    }
+}
+
+/// A MIDI file streaming to an output on a background thread.
+struct Playback {
+    target: DeviceKey,
+    cancel: Arc<AtomicBool>,
+    handle: thread::JoinHandle<()>,
+}
+
+/// Draw durations over a session, reported on exit by `--stress`.
+#[derive(Debug, Default)]
+struct FrameTimes {
+    count: u32,
+    total: Duration,
+    min: Option<Duration>,
+    max: Duration,
+}
+
+impl FrameTimes {
+    fn record(&mut self, d: Duration) {
+        self.count += 1;
+        self.total += d;
+        self.min = Some(self.min.map_or(d, |m| m.min(d)));
+        self.max = self.max.max(d);
+    }
+}
+
+impl fmt::Display for FrameTimes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(min) = self.min else {
+            return write!(f, "no frames drawn");
+        };
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        write!(
+            f,
+            "{} frames, min {:.2} ms, avg {:.2} ms, max {:.2} ms",
+            self.count,
+            ms(min),
+            ms(self.total / self.count),
+            ms(self.max)
+        )
+    }
+}
+
+/// Round-trip measurement from an output to an input cabled back to it:
+/// one probe note at a time, timed from just before the send to the midir
+/// stamp of its arrival. Stamps count from a per-connection base, which is
+/// placed on the monotonic clock by the input's earliest stamp-to-callback
+/// offset, the one least delayed by scheduling.
+struct LatencyProbe {
+    output: DeviceKey,
+    input: DeviceKey,
+    // When each returned note was sent, and its arrival stamp
+    samples: Vec<(Instant, u64)>,
+    lost: usize,
+    // Note in flight and when it was sent
+    pending: Option<(u8, Instant)>,
+    next_at: Instant,
+    // Monotonic time of the input's stamp 0, as best seen so far
+    stamp_base: Option<Instant>,
+}
+
+/// Probe notes go out on channel 16 at velocity 1, quietly out of the way.
+const PROBE_STATUS: u8 = 0x9F;
+const PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+const PROBE_GAP: Duration = Duration::from_millis(20);
+
+impl LatencyProbe {
+    fn done(&self, goal: usize) -> bool {
+        self.samples.len() + self.lost >= goal
+    }
+
+    /// Narrows the input's stamp base with one of its messages.
+    fn observe(&mut self, ev: &LoggedEvent) {
+        let Some(base) = ev.at.checked_sub(Duration::from_micros(ev.stamp)) else {
+            return;
+        };
+        self.stamp_base = Some(self.stamp_base.map_or(base, |b| b.min(base)));
+    }
+
+    fn report(&self) -> String {
+        let n = self.samples.len();
+        let (Some(base), 1..) = (self.stamp_base, n) else {
+            return format!("no probe notes came back ({} lost)", self.lost);
+        };
+        let ms: Vec<f64> = self
+            .samples
+            .iter()
+            .map(|&(sent, stamp)| {
+                let arrived = base + Duration::from_micros(stamp);
+                arrived.saturating_duration_since(sent).as_secs_f64() * 1000.0
+            })
+            .collect();
+        let min = ms.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = ms.iter().copied().fold(0.0, f64::max);
+        let avg = ms.iter().sum::<f64>() / n as f64;
+        // Jitter as the standard deviation
+        let jitter = (ms.iter().map(|m| (m - avg).powi(2)).sum::<f64>() / n as f64).sqrt();
+        format!(
+            "min {min:.2} ms, avg {avg:.2} ms, max {max:.2} ms, jitter {jitter:.2} ms over {n} samples ({} lost)",
+            self.lost
+        )
+    }
+}
+
+/// MIDI clock generated on a background thread for one output.
+struct ClockOut {
+    target: DeviceKey,
+    // f64 tempo as bits, so +/- take effect from the next pulse
+    bpm: Arc<AtomicU64>,
+    cancel: Arc<AtomicBool>,
+    handle: thread::JoinHandle<()>,
+}
+
+/// What can be told about a port beyond its display name.
+#[derive(Clone, Debug, Default)]
+struct PortMeta {
+    /// Client (device or application) part of the name, when the backend
+    /// encodes one, e.g. ALSA's "Client:Port 20:0".
+    client: Option<String>,
+    port: String,
+    /// Backend address such as ALSA's "20:0".
+    address: Option<String>,
+    backend: &'static str,
+}
+
+impl PortMeta {
+    fn parse(name: &str) -> Self {
+        let (label, address) = match name.rsplit_once(' ') {
+            Some((label, addr))
+                if addr.split_once(':').is_some_and(|(c, p)| {
+                    !c.is_empty()
+                        && !p.is_empty()
+                        && c.chars().chain(p.chars()).all(|ch| ch.is_ascii_digit())
+                }) =>
+            {
+                (label, Some(addr.to_string()))
+            }
+            _ => (name, None),
+        };
+        let (client, port) = match label.split_once(':') {
+            Some((client, port)) if address.is_some() => {
+                (Some(client.to_string()), port.to_string())
+            }
+            _ => (None, label.to_string()),
+        };
+        Self {
+            client,
+            port,
+            address,
+            backend: midi_backend(),
+        }
+    }
+}
+
+/// The midir backend compiled in for this platform.
+fn midi_backend() -> &'static str {
+    if cfg!(target_os = "linux") {
+        "ALSA"
+    } else if cfg!(target_os = "macos") || cfg!(target_os = "ios") {
+        "CoreMIDI"
+    } else if cfg!(windows) {
+        "WinMM"
+    } else if cfg!(target_arch = "wasm32") {
+        "Web MIDI"
+    } else {
+        "unknown"
+    }
+}
+
+/// Reads the port's current name from the backend and splits it up.
+fn port_metadata(kind: MidiKind, index: usize) -> PortMeta {
+    let name = match kind {
+        MidiKind::Input => MidiInput::new("midir-tui-meta").ok().and_then(|inp| {
+            let port = inp.ports().into_iter().nth(index)?;
+            inp.port_name(&port).ok()
+        }),
+        MidiKind::Output => MidiOutput::new("midir-tui-meta").ok().and_then(|out| {
+            let port = out.ports().into_iter().nth(index)?;
+            out.port_name(&port).ok()
+        }),
+    };
+    name.map(|n| PortMeta::parse(&n)).unwrap_or(PortMeta {
+        backend: midi_backend(),
+        ..PortMeta::default()
+    })
+}
+
+/// Incoming message counter for one input, plus arrival times inside
+/// `RATE_WINDOW` for the live rate.
+#[derive(Debug, Default)]
+struct DeviceStats {
+    total: u64,
+    window: VecDeque<Instant>,
+}
+
+impl DeviceStats {
+    fn record(&mut self, now: Instant) {
+        self.total += 1;
+        self.window.push_back(now);
+        while self
+            .window
+            .front()
+            .is_some_and(|t| now.duration_since(*t) > RATE_WINDOW)
+        {
+            self.window.pop_front();
+        }
+    }
+
+    /// Messages per second over the last `RATE_WINDOW`.
+    fn rate(&self) -> f64 {
+        let recent = self
+            .window
+            .iter()
+            .filter(|t| t.elapsed() <= RATE_WINDOW)
+            .count();
+        recent as f64 / RATE_WINDOW.as_secs_f64()
+    }
+}
+
+/// Single-line text entry shown as a modal; submitted with Enter.
+struct Prompt {
+    kind: PromptKind,
+    input: String,
+    // Entry of `send_history` being shown (0 = latest); None while typing
+    history_pos: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+enum AlertStyle {
+    #[default]
+    Bell,
+    Flash,
+}
+
+/// Device list order; inputs always come before outputs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum SortMode {
+    /// Favorites first, then by name
+    #[default]
+    Name,
+    /// Inputs that sent something most recently first
+    Activity,
+}
+
+impl SortMode {
+    fn next(self) -> Self {
+        match self {
+            SortMode::Name => SortMode::Activity,
+            SortMode::Activity => SortMode::Name,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            SortMode::Name => "name",
+            SortMode::Activity => "recently active",
+        }
+    }
 }
 
 #[derive(Default, Serialize, Deserialize)]
 struct Persisted {
     last_device: Option<DeviceKey>,
     last_focus: Option<Focus>,
+    #[serde(default)]
+    alert_trigger: Option<AlertTrigger>,
+    #[serde(default)]
+    alert_style: AlertStyle,
+    #[serde(default)]
+    out_channel: u8,
+    #[serde(default)]
+    recent_devices: Vec<DeviceKey>,
+    #[serde(default)]
+    open_devices: Vec<DeviceKey>,
+    // Input first
+    #[serde(default)]
+    linked_pairs: Vec<(DeviceKey, DeviceKey)>,
+    #[serde(default)]
+    favorites: HashSet<DeviceKey>,
+    #[serde(default)]
+    hidden_devices: HashSet<DeviceKey>,
+    #[serde(default)]
+    sort_mode: SortMode,
+    // Log filters and display modes
+    #[serde(default)]
+    hidden_types: MsgTypes,
+    #[serde(default)]
+    channel_filter: Option<u8>,
+    #[serde(default)]
+    byte_filter: Option<String>,
+    #[serde(default)]
+    show_raw: bool,
+    #[serde(default)]
+    split_columns: bool,
+    #[serde(default)]
+    group_simultaneous: bool,
+    #[serde(default)]
+    show_timing: bool,
+    #[serde(default)]
+    muted_ccs: BTreeSet<u8>,
+    #[serde(default)]
+    midi_bindings: Vec<MidiBinding>,
+    #[serde(
+        default,
+        serialize_with = "serialize_key_map",
+        deserialize_with = "deserialize_key_map"
+    )]
+    view_states: HashMap<DeviceKey, ViewState>,
+}
+
+/// Log view settings remembered per device and restored on reselecting it.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct ViewState {
+    show_timing: bool,
+    hidden_types: MsgTypes,
+    channel_filter: Option<u8>,
+    byte_filter: Option<String>,
+    log_scroll: usize,
+}
+
+/// JSON object keys must be strings, so maps keyed by `DeviceKey` are
+/// stored as a list of `[key, value]` pairs.
+fn serialize_key_map<V: Serialize, S: serde::Serializer>(
+    map: &HashMap<DeviceKey, V>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.collect_seq(map)
+}
+
+fn deserialize_key_map<'de, V: Deserialize<'de>, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<HashMap<DeviceKey, V>, D::Error> {
+    Vec::<(DeviceKey, V)>::deserialize(deserializer).map(|pairs| pairs.into_iter().collect())
+}
+
+/// A controller or pad that triggers an action; `status` is a Note On or CC
+/// status byte including the channel.
+#[derive(Debug, Serialize, Deserialize)]
+struct MidiBinding {
+    status: u8,
+    data1: u8,
+    action: String,
+}
+
+/// MIDI learn steps: first the key of the action, then the control for it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MidiLearn {
+    Key,
+    Control(Action),
+}
+
+/// Snapshot of `App` for bug reports; live connections are reduced to keys.
+#[derive(Serialize)]
+struct StateDump<'a> {
+    generated_at: String,
+    devices: &'a [DeviceItem],
+    selected: usize,
+    focus: Focus,
+    open_inputs: Vec<&'a DeviceKey>,
+    open_outputs: Vec<&'a DeviceKey>,
+    virtual_ports: Vec<&'a DeviceKey>,
+    routes: &'a [Route],
+    linked_pairs: Vec<(&'a DeviceKey, &'a DeviceKey)>,
+    out_channel: u8,
+    alert_trigger: Option<AlertTrigger>,
+    alert_style: AlertStyle,
+    recent: &'a [DeviceKey],
+    favorites: &'a HashSet<DeviceKey>,
+    hidden_devices: &'a HashSet<DeviceKey>,
+    message_counts: Vec<(&'a DeviceKey, u64)>,
+    hidden_types: String,
+    muted_ccs: &'a BTreeSet<u8>,
+    midi_bindings: Vec<String>,
+    view_states: Vec<(&'a DeviceKey, &'a ViewState)>,
+    dropped: u64,
+    log_len: usize,
+    pending_sysex: Vec<&'a DeviceKey>,
+    config: &'a Config,
+}
+
+const MAX_RECENT: usize = 10;
+
+/// Tracker-style layout: the home row plays white keys, the row above sharps.
+/// Values are semitones above the keyboard's base note.
+const KEYBOARD_MAP: [(char, u8); 17] = [
+    ('a', 0),
+    ('w', 1),
+    ('s', 2),
+    ('e', 3),
+    ('d', 4),
+    ('f', 5),
+    ('t', 6),
+    ('g', 7),
+    ('y', 8),
+    ('h', 9),
+    ('u', 10),
+    ('j', 11),
+    ('k', 12),
+    ('o', 13),
+    ('l', 14),
+    ('p', 15),
+    (';', 16),
+];
+const TRAIL_WINDOW: Duration = Duration::from_secs(4);
+const TRAIL_ROWS: usize = 8;
+const INDEX_HIGHLIGHT: Duration = Duration::from_secs(3);
+const RATE_WINDOW: Duration = Duration::from_secs(2);
+/// A second digit this soon after `1` picks channels 10-16.
+const CHANNEL_DIGIT_WINDOW: Duration = Duration::from_millis(800);
+/// How long a Data Entry MSB waits for its LSB before it is shown alone.
+const PARAM_LSB_WAIT: Duration = Duration::from_millis(50);
+const VELOCITY_BINS: usize = 8;
+const CLOCK_BPM_RANGE: (f64, f64) = (20.0, 300.0);
+/// How long after a close Ctrl+Z can still reopen the ports.
+const UNDO_WINDOW: Duration = Duration::from_secs(30);
+/// Hex strings remembered by the send prompt.
+const SEND_HISTORY: usize = 20;
+/// Log capacity while a long capture is toggled on.
+const LONG_CAPTURE: usize = 100_000;
+/// Events taken off the channel per tick, so a flood can't stall rendering.
+const DRAIN_PER_TICK: usize = 500;
+/// Without MIDI or key input for this long, the UI polls at the slow tick.
+const IDLE_AFTER: Duration = Duration::from_secs(3);
+
+/// Message classes dropped by midir before they reach us, in the order the
+/// toggle cycles through them.
+const IGNORE_MODES: [(midir::Ignore, &str); 8] = [
+    (midir::Ignore::None, "nothing"),
+    (midir::Ignore::ActiveSense, "active sensing"),
+    (
+        midir::Ignore::TimeAndActiveSense,
+        "clock/timing + active sensing",
+    ),
+    (midir::Ignore::Time, "clock/timing"),
+    (midir::Ignore::Sysex, "SysEx"),
+    (midir::Ignore::SysexAndActiveSense, "SysEx + active sensing"),
+    (midir::Ignore::SysexAndTime, "SysEx + clock/timing"),
+    (midir::Ignore::All, "SysEx + clock/timing + active sensing"),
+];
+
+/// User-editable settings, read from `config.json` next to `state.json`.
+/// Missing keys fall back to the defaults below.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+struct Config {
+    /// Flush a partial SysEx as "(incomplete)" after this long without its 0xF7.
+    sysex_timeout_ms: u64,
+    /// Keep the CC 98-101 rows of RPN/NRPN sequences in the log, and the raw
+    /// CC after each `NRPN ch1 param=… value=…` row.
+    show_param_ccs: bool,
+    /// Layout of decoded MIDI log lines, also used for `--echo` and saved
+    /// logs. Tokens: `{ts}` `{unix_ms}` `{dir}` `{dev}` `{type}` `{ch}`
+    /// `{data}` `{len}` `{decoded}`; `{{` and `}}` are literal braces.
+    /// Without `{ts}`, echoed and saved lines get the time prefixed.
+    log_format: String,
+    /// Entries kept in the log before the oldest are dropped.
+    log_capacity: usize,
+    /// Per-direction caps on log entries. They apply inside the global log
+    /// cap: whichever limit is reached first evicts, and a direction at its
+    /// cap evicts its own oldest entries rather than the other direction's.
+    /// Such evictions are batched, so a direction can briefly hold up to a
+    /// sixteenth more than its cap.
+    log_capacity_in: usize,
+    log_capacity_out: usize,
+    /// Key that sends all-notes-off to every open output.
+    panic_key: char,
+    /// Send a panic when the terminal reports losing focus (alt-tab away).
+    auto_panic_on_focus_loss: bool,
+    /// Tuning reference for note frequency readouts.
+    a4_hz: f64,
+    /// Semitones covered by a full pitch bend, for the cents readout.
+    pitch_bend_range: f64,
+    /// Start with held notes shown per MPE channel, with their bend, pressure
+    /// and slide.
+    mpe_view: bool,
+    /// Starting tempo for generated MIDI clock.
+    clock_bpm: f64,
+    /// Octave number of middle C (note 60) in note names: 3, 4 or 5.
+    c_octave: i8,
+    /// OSC addresses used by `--osc-out`.
+    #[cfg(feature = "osc")]
+    osc: osc::OscConfig,
+    /// Ask "Quit? (y/n)" before q/Esc exits (Ctrl+C always exits at once).
+    confirm_quit: bool,
+    /// Note length for the virtual keyboard; terminals report no key-up, so a
+    /// note is released this long after its key stops repeating.
+    keyboard_note_ms: u64,
+    /// Note On velocity sent by the virtual keyboard.
+    keyboard_velocity: u8,
+    /// Probe notes sent per latency measurement.
+    latency_samples: usize,
+    /// Messages from one device closer together than this are "simultaneous".
+    group_window_us: u64,
+    /// Labels for specific gear, keyed by device name.
+    device_names: HashMap<String, DeviceNames>,
+    /// Ports the TUI opens as soon as they appear, by name: globs like `nanoKEY*`
+    /// or `/^Launchpad/` regexes.
+    auto_open: Vec<String>,
+    /// Ports left out of the list, auto-open and open-all, by name pattern
+    /// like `auto_open`: e.g. `Midi Through*`.
+    hidden: Vec<String>,
+    /// Shell commands run when a note or CC is pressed on any open input.
+    macros: Vec<MidiMacro>,
+    /// Applied to Note On velocities sent through routes and by the virtual
+    /// keyboard: `linear`, `exponential`, `compressed` or `{"fixed": 100}`.
+    velocity_curve: VelocityCurve,
+    /// Color palette: `dark`, `light` or `high_contrast`.
+    theme: ThemeName,
+    /// How often the device list is re-enumerated to catch hotplug.
+    refresh_interval_ms: u64,
+    /// UI wake-up interval while MIDI or keys are active, and once idle; a
+    /// longer idle tick saves power but delays the first message after a
+    /// quiet spell by up to that long.
+    tick_min_ms: u64,
+    tick_max_ms: u64,
+    /// Main-view key bindings: action name → chords such as `"k"` or
+    /// `"Ctrl+r"`. Listed actions replace their defaults; `panic` defaults to
+    /// `panic_key`.
+    keybindings: HashMap<String, Vec<String>>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            sysex_timeout_ms: 2000,
+            show_param_ccs: false,
+            log_format: DEFAULT_LOG_FORMAT.to_string(),
+            log_capacity: 1024,
+            log_capacity_in: 1024,
+            log_capacity_out: 512,
+            panic_key: 'p',
+            auto_panic_on_focus_loss: false,
+            a4_hz: 440.0,
+            pitch_bend_range: 2.0,
+            mpe_view: false,
+            clock_bpm: 120.0,
+            c_octave: 4,
+            #[cfg(feature = "osc")]
+            osc: osc::OscConfig::default(),
+            confirm_quit: false,
+            device_names: HashMap::new(),
+            auto_open: Vec::new(),
+            hidden: Vec::new(),
+            macros: Vec::new(),
+            group_window_us: 1000,
+            keyboard_note_ms: 400,
+            keyboard_velocity: 100,
+            latency_samples: 50,
+            velocity_curve: VelocityCurve::Linear,
+            theme: ThemeName::Dark,
+            refresh_interval_ms: 5000,
+            tick_min_ms: 50,
+            tick_max_ms: 500,
+            keybindings: HashMap::new(),
+        }
+    }
+}
+
+/// One `macros` entry: `{"note": 36, "channel": 10, "command": "obs-cli
+/// start-record"}`. Set `note` or `cc`; without `channel` any channel
+/// matches. A CC presses when its value rises through 64.
+#[derive(Debug, Serialize, Deserialize)]
+struct MidiMacro {
+    note: Option<u8>,
+    cc: Option<u8>,
+    /// 1-16
+    channel: Option<u8>,
+    command: String,
+    /// Presses closer together than this after a run are ignored.
+    #[serde(default = "default_macro_interval")]
+    min_interval_ms: u64,
+}
+
+fn default_macro_interval() -> u64 {
+    500
+}
+
+impl MidiMacro {
+    fn matches(&self, status: u8, data1: u8) -> bool {
+        let number = match status & 0xF0 {
+            0x90 => self.note,
+            0xB0 => self.cc,
+            _ => None,
+        };
+        number == Some(data1) && self.channel.is_none_or(|ch| ch == (status & 0x0F) + 1)
+    }
 }
 
 struct App {
@@ -60,27 +1041,247 @@ struct App {
     selected: usize,
     focus: Focus,
     last_refresh: Instant,
+    // Device to select once it appears, kept while the list is empty
+    wanted_selection: Option<DeviceKey>,
+    // Skip all enumeration so indices never move mid-performance
+    list_frozen: bool,
+    sort_mode: SortMode,
+    // Last message from each input, for `SortMode::Activity`
+    last_seen: HashMap<DeviceKey, Instant>,
+    index_changed: HashMap<DeviceKey, Instant>,
+    // Case-insensitive name filter for the list; `selected` stays an index
+    // into `devices` and is kept on a visible row
+    device_filter: String,
+    filter_editing: bool,
+    // Listed first within their kind; may name devices that are not present
+    favorites: HashSet<DeviceKey>,
+    // Hidden with HideDevice or by a `hidden` rule; listed again (dimmed)
+    // while `show_hidden` is on, but never auto-opened or opened by open-all
+    hidden_devices: HashSet<DeviceKey>,
+    hidden_rules: Vec<NamePattern>,
+    show_hidden: bool,
+    // Devices picked with Mark for one OpenMarked
+    marked: HashSet<DeviceKey>,
 
     // Persistence
     persist_path: Option<PathBuf>,
+    config_path: Option<PathBuf>,
+    config: Config,
+    keys: Keymap,
+    theme_name: ThemeName,
+    theme: Theme,
+    velocity_curve: VelocityCurve,
+    log_format: LogTemplate,
+    // Middle-C octave for note names; starts from config, cycled live
+    c_octave: i8,
 
     // Multiple open connections, keyed by device
     in_conns: HashMap<DeviceKey, MidiInputConnection<()>>,
-    out_conns: HashMap<DeviceKey, MidiOutputConnection>,
+    out_conns: HashMap<DeviceKey, SharedOutput>,
+    // Ports we created for other apps; midir hands back ordinary connections
+    // for them, kept in the maps above. Listed until closed.
+    virtual_ports: HashSet<DeviceKey>,
+    // Input/output pairs opened together, stored in both directions
+    linked: HashMap<DeviceKey, DeviceKey>,
+    // Index into IGNORE_MODES applied to every input we open
+    input_ignore: usize,
+    // Backend metadata for the selected port, re-read when the selection moves
+    port_meta: Option<(DeviceKey, PortMeta)>,
+
+    // Thru routing (input → output); `thru` is the callback-side view of the
+    // routes whose endpoints are both open, rebuilt by `sync_thru`
+    routes: Vec<Route>,
+    route_mark: Option<DeviceKey>,
+    latency_probe: Option<LatencyProbe>,
+    thru: ThruTable,
 
     // Live log (for input devices)
-    log: VecDeque<String>,
-    tx: Sender<String>,
-    rx: Receiver<String>,
+    log: VecDeque<LoggedEvent>,
+    // Display stops following new entries; they are still logged
+    log_paused: bool,
+    // Current global cap; `config.log_capacity` unless raised for a long capture
+    log_capacity: usize,
+    log_counts: HashMap<LogDirection, usize>,
+    device_stats: HashMap<DeviceKey, DeviceStats>,
+    // Compiled `config.auto_open` rules
+    auto_open: Vec<NamePattern>,
+    // Messages received since start; unlike `device_stats` never reset
+    received_total: u64,
+    // Velocity of each currently held note per open input (0 = up), all
+    // channels merged
+    held_notes: HashMap<DeviceKey, [u8; 128]>,
+    // Per-channel note and expression state per open input, for the MPE view
+    mpe: HashMap<DeviceKey, MpeState>,
+    clock_tempo: HashMap<DeviceKey, ClockTempo>,
+    // When each open port was opened, for the duration logged on close
+    opened_at: HashMap<DeviceKey, Instant>,
+    // Note-on velocity counts per input, in `VELOCITY_BINS` equal bins of 1-127
+    velocity_hist: HashMap<DeviceKey, [u64; VELOCITY_BINS]>,
+    tx: SyncSender<LoggedEvent>,
+    rx: Receiver<LoggedEvent>,
+    // Incoming messages discarded because the channel was full
+    dropped: Arc<AtomicU64>,
+
+    // SysEx split across callbacks, waiting for its 0xF7 (last fragment time, partial)
+    sysex_partial: HashMap<DeviceKey, (Instant, LoggedEvent)>,
+    // RPN/NRPN selection per input
+    param_trackers: HashMap<DeviceKey, ParamTracker>,
+    // Stamp of each open input's latest message, for the timing delta
+    last_stamp: HashMap<DeviceKey, u64>,
+    // When a device's trackers first held a Data Entry MSB, for the LSB wait
+    param_held_at: HashMap<DeviceKey, Instant>,
+
+    // Alert on a learned message
+    alert_trigger: Option<AlertTrigger>,
+    alert_style: AlertStyle,
+    alert_learning: bool,
+    flash_until: Option<Instant>,
+    // Last MIDI or terminal input, for the adaptive tick
+    last_activity: Instant,
+
+    // MIDI channel (0-15) targeted by everything we send to outputs
+    out_channel: u8,
+
+    // Most recently opened devices, newest first; Some(i) while the popup is up
+    recent: Vec<DeviceKey>,
+    recent_popup: Option<usize>,
+
+    // Piano-roll strip of recent notes for the selected input (costly, so opt-in)
+    show_note_trail: bool,
+    mpe_view: bool,
+
+    // Line editor modal, and the raw byte-pattern log filter it can set
+    prompt: Option<Prompt>,
+    // Hex strings sent from the prompt, latest first
+    send_history: Vec<String>,
+    byte_filter: Option<BytePattern>,
+    // Collapse chords / MSB+LSB pairs into one bracketed log line
+    group_simultaneous: bool,
+    // Show raw hex instead of decoded text
+    show_raw: bool,
+    // For inputs, show raw hex and decoded text side by side
+    split_columns: bool,
+    // Prefix rows with the midir timestamp and the gap since the device's previous message
+    show_timing: bool,
+    // Log view offset in rows from the newest entry; 0 follows live
+    log_scroll: usize,
+    // Log view settings per device, and the device they currently belong to
+    view_states: HashMap<DeviceKey, ViewState>,
+    view_device: Option<DeviceKey>,
+    // Only show messages on this channel (0-15)
+    channel_filter: Option<u8>,
+    // When `1` was typed, as the possible start of channels 10-16
+    channel_digit_at: Option<Instant>,
+    // Message categories left out of the log view
+    hidden_types: MsgTypes,
+    // Controller numbers whose CC messages are kept out of the log view
+    muted_ccs: BTreeSet<u8>,
+    // MIDI learn: (status, note/controller) → action, the learn step in
+    // progress, and triggered actions waiting for run_app to dispatch them
+    midi_bindings: HashMap<(u8, u8), Action>,
+    midi_learn: Option<MidiLearn>,
+    pending_actions: VecDeque<Action>,
+    // Last value per (status, controller), for CC presses crossing 64
+    controls: ControlTracker,
+    // When each `config.macros` entry (by index) last ran
+    macro_fired: HashMap<usize, Instant>,
+
+    // Virtual keyboard: target output (Some while the mode is active), base
+    // note, and sounding notes with their release deadlines
+    kb_target: Option<DeviceKey>,
+    kb_base: u8,
+    kb_held: HashMap<u8, Instant>,
+
+    playback: Option<Playback>,
+    clock_out: Option<ClockOut>,
+    // Ports shut by the latest close, for undo (virtual ports can't come back)
+    last_closed: Option<(Instant, Vec<DeviceKey>)>,
+    // Most recent failed open with its full error chain, until an open works
+    last_error: Option<(DeviceKey, String)>,
+    // Every port whose latest open failed, often because another app holds
+    // it; cleared by an open that works, a manual refresh or unplugging
+    open_failures: HashMap<DeviceKey, String>,
+    confirm: Option<Confirm>,
+
+    // (channel, note) of the last Note On per input, and the pitch bend last
+    // seen on each of its channels (-8192..=8191), for the detail pane
+    last_note: HashMap<DeviceKey, (u8, u8)>,
+    channel_bend: HashMap<DeviceKey, [i16; 16]>,
+    // Headless mode: print every log entry as it is added (MIDI to stdout,
+    // status lines to stderr)
+    echo_stdout: bool,
+    // `--osc-out` destination for incoming notes, CCs and pitch bends
+    #[cfg(feature = "osc")]
+    osc_out: Option<osc::OscSender>,
+    // `--json-tcp` listener streaming incoming MIDI to its clients
+    json_out: Option<JsonStream>,
+    // `--rtpmidi` remote sessions, listed as outputs
+    #[cfg(feature = "rtpmidi")]
+    rtp_peers: Vec<String>,
+    // Invites still waiting on their peer
+    #[cfg(feature = "rtpmidi")]
+    rtp_invites: Vec<(DeviceKey, Receiver<Result<rtpmidi::Session>>)>,
+    // Hex dump modal of one SysEx message, with its scroll offset in rows
+    sysex_view: Option<(Vec<u8>, usize)>,
+    // Key binding overlay, with its scroll offset
+    help_view: Option<usize>,
 }
 
 impl App {
+    /// The TUI's app: loads the saved state, reopens the ports left open last
+    /// session and applies the `auto_open` rules. Headless mode and the
+    /// selftest start from [`App::with_state`] and open only what they name.
     fn new() -> Result<Self> {
         let persist_path = persist_file_path();
         let persisted = load_persisted(&persist_path).unwrap_or_default();
-
+        let config_path = config_file_path();
+        let config = load_config(&config_path).unwrap_or_default();
         let devices = collect_devices()?;
-        let (tx, rx) = mpsc::channel::<String>();
+        let mut app = Self::with_state(config, persisted, devices, true);
+        app.persist_path = persist_path;
+        app.config_path = config_path;
+        Ok(app)
+    }
+
+    /// Builds the app from already loaded settings and a device list, without
+    /// touching the files `new` reads; nothing is saved until the paths are set.
+    fn with_state(
+        config: Config,
+        persisted: Persisted,
+        mut devices: Vec<DeviceItem>,
+        reopen_previous: bool,
+    ) -> Self {
+        let log_capacity = config.log_capacity.max(1);
+        let (log_format, format_error) = match LogTemplate::parse(&config.log_format) {
+            Ok(t) => (t, None),
+            Err(e) => (LogTemplate::default(), Some(e)),
+        };
+        let mut rule_errors = Vec::new();
+        let auto_open = config
+            .auto_open
+            .iter()
+            .filter_map(|rule| {
+                NamePattern::parse(rule)
+                    .map_err(|e| rule_errors.push(format!("Auto-open rule ignored: {e:#}")))
+                    .ok()
+            })
+            .collect();
+        let hidden_rules = config
+            .hidden
+            .iter()
+            .filter_map(|rule| {
+                NamePattern::parse(rule)
+                    .map_err(|e| rule_errors.push(format!("Hide rule ignored: {e:#}")))
+                    .ok()
+            })
+            .collect();
+        let (keys, keys_error) = match Keymap::new(&config.keybindings, config.panic_key) {
+            Ok(keys) => (keys, None),
+            Err(e) => (Keymap::defaults(), Some(e)),
+        };
+
+        sort_favorites_first(&mut devices, &persisted.favorites);
+        let (tx, rx) = mpsc::sync_channel::<LoggedEvent>(CHANNEL_CAPACITY);
 
         // Restore selection by last_device if possible
         let mut selected = 0usize;
@@ -90,158 +1291,3241 @@ impl App {
             }
         }
 
-        Ok(Self {
+        let wanted_selection = if devices.is_empty() {
+            persisted.last_device.clone()
+        } else {
+            None
+        };
+
+        let mut app = Self {
             devices,
             selected,
+            wanted_selection,
             focus: persisted.last_focus.unwrap_or(Focus::Left),
             last_refresh: Instant::now(),
-            persist_path,
+            list_frozen: false,
+            sort_mode: persisted.sort_mode,
+            last_seen: HashMap::new(),
+            index_changed: HashMap::new(),
+            device_filter: String::new(),
+            filter_editing: false,
+            favorites: persisted.favorites,
+            hidden_devices: persisted.hidden_devices,
+            hidden_rules,
+            show_hidden: false,
+            marked: HashSet::new(),
+            persist_path: None,
+            config_path: None,
+            theme_name: config.theme,
+            c_octave: config.c_octave.clamp(3, 5),
+            theme: config.theme.palette(),
+            velocity_curve: config.velocity_curve,
+            log_format,
+            mpe_view: config.mpe_view,
+            config,
+            keys,
             in_conns: HashMap::new(),
             out_conns: HashMap::new(),
-            log: VecDeque::with_capacity(1024),
+            virtual_ports: HashSet::new(),
+            linked: HashMap::new(),
+            input_ignore: 0,
+            port_meta: None,
+            routes: Vec::new(),
+            route_mark: None,
+            latency_probe: None,
+            thru: Arc::new(Mutex::new(HashMap::new())),
+            log: VecDeque::new(),
+            log_capacity,
+            log_counts: HashMap::new(),
+            device_stats: HashMap::new(),
+            auto_open,
+            received_total: 0,
+            log_paused: false,
+            held_notes: HashMap::new(),
+            mpe: HashMap::new(),
+            clock_tempo: HashMap::new(),
+            opened_at: HashMap::new(),
+            velocity_hist: HashMap::new(),
             tx,
             rx,
-        })
-    }
-
-    fn refresh_devices(&mut self) {
-        if let Ok(devs) = collect_devices() {
-            let old_key = self.devices.get(self.selected).map(|d| d.key.clone());
-            self.devices = devs;
-            if let Some(key) = old_key {
-                if let Some(pos) = self.devices.iter().position(|d| d.key == key) {
-                    self.selected = pos;
-                } else {
-                    self.selected = 0;
+            dropped: Arc::new(AtomicU64::new(0)),
+            sysex_partial: HashMap::new(),
+            param_trackers: HashMap::new(),
+            last_stamp: HashMap::new(),
+            param_held_at: HashMap::new(),
+            alert_trigger: persisted.alert_trigger,
+            alert_style: persisted.alert_style,
+            alert_learning: false,
+            flash_until: None,
+            last_activity: Instant::now(),
+            out_channel: persisted.out_channel.min(15),
+            recent: persisted.recent_devices,
+            recent_popup: None,
+            show_note_trail: false,
+            prompt: None,
+            send_history: Vec::new(),
+            // A pattern that no longer parses is dropped rather than fatal
+            byte_filter: persisted
+                .byte_filter
+                .as_deref()
+                .and_then(|p| BytePattern::parse(p).ok()),
+            group_simultaneous: persisted.group_simultaneous,
+            show_timing: persisted.show_timing,
+            show_raw: persisted.show_raw,
+            split_columns: persisted.split_columns,
+            log_scroll: 0,
+            view_states: persisted.view_states,
+            view_device: None,
+            channel_filter: persisted.channel_filter.filter(|ch| *ch < 16),
+            channel_digit_at: None,
+            hidden_types: persisted.hidden_types,
+            muted_ccs: persisted.muted_ccs,
+            midi_bindings: persisted
+                .midi_bindings
+                .iter()
+                .filter_map(|b| Some(((b.status, b.data1), Action::from_name(&b.action)?)))
+                .collect(),
+            midi_learn: None,
+            controls: ControlTracker::default(),
+            macro_fired: HashMap::new(),
+            pending_actions: VecDeque::new(),
+            kb_target: None,
+            kb_base: 60,
+            kb_held: HashMap::new(),
+            playback: None,
+            clock_out: None,
+            last_closed: None,
+            last_error: None,
+            open_failures: HashMap::new(),
+            confirm: None,
+            last_note: HashMap::new(),
+            channel_bend: HashMap::new(),
+            echo_stdout: false,
+            #[cfg(feature = "osc")]
+            osc_out: None,
+            json_out: None,
+            #[cfg(feature = "rtpmidi")]
+            rtp_peers: Vec::new(),
+            #[cfg(feature = "rtpmidi")]
+            rtp_invites: Vec::new(),
+            sysex_view: None,
+            help_view: None,
+        };
+        if let Some(e) = keys_error {
+            app.push_status(format!("Key bindings ignored, using defaults: {e:#}"));
+        }
+        for e in rule_errors {
+            app.push_status(e);
+        }
+        if let Some(e) = format_error {
+            app.push_status(format!("Log format ignored, using the default: {e:#}"));
+        }
+        if reopen_previous {
+            app.reopen_devices(&persisted.open_devices);
+            for (input, output) in persisted.linked_pairs {
+                if app.in_conns.contains_key(&input) && app.out_conns.contains_key(&output) {
+                    app.link(input, output);
                 }
-            } else {
-                self.selected = 0;
             }
-            self.last_refresh = Instant::now();
+            app.auto_open_new(&[]);
         }
+        app.keep_selection_visible();
+        app
     }
 
-    fn select_up(&mut self) {
-        if self.devices.is_empty() {
-            return;
+    /// Opens the ports named on the command line and selects the first.
+    fn open_startup_devices(&mut self, keys: &[DeviceKey]) {
+        for key in keys {
+            let already_open = self.in_conns.contains_key(key) || self.out_conns.contains_key(key);
+            if !already_open {
+                self.reopen_devices(std::slice::from_ref(key));
+            }
         }
-        if self.selected == 0 {
-            self.selected = self.devices.len() - 1;
-        } else {
-            self.selected -= 1;
+        if let Some(pos) = keys
+            .first()
+            .and_then(|k| self.devices.iter().position(|d| &d.key == k))
+        {
+            self.selected = pos;
         }
     }
 
-    fn select_down(&mut self) {
-        if self.devices.is_empty() {
+    /// Reopens ports that were open last session; failures are only logged.
+    fn reopen_devices(&mut self, keys: &[DeviceKey]) {
+        for key in keys {
+            if self.is_hidden(key) {
+                self.push_status(format!("Not reopened (hidden): {}", key.label()));
+                continue;
+            }
+            let Some(dev) = self.devices.iter().find(|d| &d.key == key).cloned() else {
+                self.push_status(format!("Not reopened (not present): {}", key.name));
+                continue;
+            };
+            let res = match dev.key.kind {
+                MidiKind::Input => self.open_input(&dev),
+                MidiKind::Output => self.open_output(&dev),
+            };
+            if let Err(e) = res {
+                self.push_status(format!("Reopen failed: {e:#}"));
+            }
+        }
+    }
+
+    fn refresh_devices(&mut self) {
+        if self.list_frozen {
+            self.last_refresh = Instant::now();
             return;
         }
-        self.selected = (self.selected + 1) % self.devices.len();
+        if let Ok(mut devs) = collect_devices() {
+            devs.extend(self.virtual_items());
+            devs.extend(self.rtp_items());
+            self.apply_device_list(devs);
+            self.last_refresh = Instant::now();
+        }
     }
 
-    fn toggle_open_selected(&mut self) -> Result<()> {
-        if self.devices.is_empty() {
-            return Ok(());
+    /// Swaps in a freshly collected port list, keeping the selection on the
+    /// same device where it still exists.
+    fn apply_device_list(&mut self, mut devs: Vec<DeviceItem>) {
+        sort_devices(&mut devs);
+        sort_favorites_first(&mut devs, &self.favorites);
+        if self.sort_mode == SortMode::Activity {
+            sort_recently_active(&mut devs, &self.last_seen);
+        }
+        let old_key = self
+            .devices
+            .get(self.selected)
+            .map(|d| d.key.clone())
+            .or_else(|| self.wanted_selection.take());
+        let old_devices = std::mem::replace(&mut self.devices, devs);
+        self.note_index_changes(&old_devices);
+        self.note_hotplug(&old_devices);
+        self.auto_open_new(&old_devices);
+        self.port_meta = None;
+        match old_key {
+            Some(key) => match self.devices.iter().position(|d| d.key == key) {
+                Some(pos) => self.selected = pos,
+                None => {
+                    self.selected = 0;
+                    // With nothing else to select, wait for it to come back
+                    if self.devices.is_empty() {
+                        self.wanted_selection = Some(key);
+                    }
+                }
+            },
+            None => self.selected = 0,
         }
-        let dev = self.devices[self.selected].clone();
+        self.keep_selection_visible();
+    }
 
-        match dev.key.kind {
-            MidiKind::Input => {
-                if self.in_conns.remove(&dev.key).is_some() {
-                    self.push_status(format!("Closed input: {}", dev.key.name));
-                } else {
-                    self.open_input(&dev)?;
+    /// midir indices are positional, so hotplug can shift them under a device
+    /// that is still present. Log each shift and highlight the row briefly.
+    fn note_index_changes(&mut self, old_devices: &[DeviceItem]) {
+        let mut changed = Vec::new();
+        for dev in &self.devices {
+            if let Some(old) = old_devices.iter().find(|o| o.key == dev.key) {
+                if old.index != dev.index {
+                    changed.push((dev.key.clone(), old.index, dev.index));
                 }
             }
-            MidiKind::Output => {
-                if self.out_conns.remove(&dev.key).is_some() {
-                    self.push_status(format!("Closed output: {}", dev.key.name));
-                } else {
-                    self.open_output(&dev)?;
-                }
+        }
+        let now = Instant::now();
+        self.index_changed
+            .retain(|_, t| now.duration_since(*t) < INDEX_HIGHLIGHT);
+        for (key, from, to) in changed {
+            self.push_status(format!("Index changed: {} (#{from} → #{to})", key.name));
+            self.index_changed.insert(key, now);
+        }
+    }
+
+    /// Logs devices that appeared or vanished since the last refresh and
+    /// drops connections to vanished ones so the open counts stay true.
+    fn note_hotplug(&mut self, old_devices: &[DeviceItem]) {
+        let added: Vec<DeviceKey> = self
+            .devices
+            .iter()
+            .filter(|d| !old_devices.iter().any(|o| o.key == d.key))
+            .map(|d| d.key.clone())
+            .collect();
+        let removed: Vec<DeviceKey> = old_devices
+            .iter()
+            .filter(|o| !self.devices.iter().any(|d| d.key == o.key))
+            .map(|o| o.key.clone())
+            .collect();
+        for key in added {
+            self.push_status(format!("Device added: {}", key.name));
+        }
+        let mut pruned = false;
+        for key in removed {
+            self.open_failures.remove(&key);
+            let was_open =
+                self.in_conns.remove(&key).is_some() | self.out_conns.remove(&key).is_some();
+            if was_open {
+                let open_for = self.open_duration(&key);
+                self.push_status(format!("Device disconnected: {}{open_for}", key.name));
+                self.forget_disconnected(&key);
+            } else {
+                self.push_status(format!("Device removed: {}", key.name));
             }
+            pruned |= was_open;
+        }
+        if pruned {
+            self.sync_thru();
         }
-        Ok(())
     }
 
-    fn close_all(&mut self) {
-        let in_count = self.in_conns.len();
+    /// Opens ports not in `old_devices` that match an `auto_open` rule and
+    /// aren't open yet.
+    fn auto_open_new(&mut self, old_devices: &[DeviceItem]) {
+        if self.auto_open.is_empty() {
+            return;
+        }
+        let matched: Vec<(DeviceItem, String)> = self
+            .devices
+            .iter()
+            .filter(|d| !old_devices.iter().any(|o| o.key == d.key))
+            .filter(|d| !self.virtual_ports.contains(&d.key))
+            .filter(|d| !self.is_hidden(&d.key))
+            .filter(|d| !self.in_conns.contains_key(&d.key) && !self.out_conns.contains_key(&d.key))
+            .filter_map(|d| {
+                let rule = self.auto_open.iter().find(|r| r.matches(&d.key.name))?;
+                Some((d.clone(), rule.source().to_string()))
+            })
+            .collect();
+        for (dev, rule) in matched {
+            let res = match dev.key.kind {
+                MidiKind::Input => self.open_input(&dev),
+                MidiKind::Output => self.open_output(&dev),
+            };
+            match res {
+                Ok(()) => {
+                    self.push_status(format!("Auto-opened {} (rule {rule})", dev.key.label()))
+                }
+                Err(e) => self.push_status(format!("Auto-open failed (rule {rule}): {e:#}")),
+            }
+        }
+    }
+
+    /// Tears down everything that pointed at an open port that went away.
+    fn forget_disconnected(&mut self, key: &DeviceKey) {
+        let before = self.routes.len();
+        self.routes.retain(|r| r.src != *key && r.dst != *key);
+        let dropped = before - self.routes.len();
+        if dropped > 0 {
+            self.push_status(format!("Removed {dropped} route(s) via {}", key.name));
+        }
+        if self.route_mark.as_ref() == Some(key) {
+            self.route_mark = None;
+        }
+        self.stop_users_of(key, "output disconnected");
+        self.held_notes.remove(key);
+        self.last_stamp.remove(key);
+        self.mpe.remove(key);
+        self.last_seen.remove(key);
+        self.param_trackers.remove(key);
+        self.param_held_at.remove(key);
+        self.clock_tempo.remove(key);
+        self.sysex_partial.remove(key);
+        if let Some(partner) = self.unlink(key) {
+            if self.close_port(&partner) {
+                self.push_status(format!("Closed linked port: {}", partner.label()));
+            }
+        }
+    }
+
+    /// The status bar: open ports, session total, tempo and what is running.
+    fn status_spans(&self, theme: &Theme) -> Vec<Span<'static>> {
+        let label = Style::default().fg(theme.label);
+        let sep = || Span::styled(" │ ", Style::default().fg(theme.muted));
+        let mut spans = vec![
+            Span::styled(" In ", label),
+            Span::raw(self.in_conns.len().to_string()),
+            Span::styled("  Out ", label),
+            Span::raw(self.out_conns.len().to_string()),
+            sep(),
+            Span::styled("Received ", label),
+            Span::raw(self.received_total.to_string()),
+        ];
+        // The selected input's tempo, else any input's that has one
+        let selected = self.selected_device().map(|d| &d.key);
+        let bpm = selected
+            .and_then(|k| self.clock_tempo.get(k))
+            .and_then(ClockTempo::bpm)
+            .or_else(|| self.clock_tempo.values().find_map(ClockTempo::bpm));
+        if let Some(bpm) = bpm {
+            spans.extend([
+                sep(),
+                Span::styled("Tempo ", label),
+                Span::raw(format!("{bpm:.1} BPM")),
+            ]);
+        }
+        if let Some(clock) = &self.clock_out {
+            spans.extend([
+                sep(),
+                Span::styled(
+                    format!(
+                        "♩{:.0} → {}",
+                        self.clock_bpm().unwrap_or_default(),
+                        clock.target.label()
+                    ),
+                    Style::default().fg(theme.accent),
+                ),
+            ]);
+        }
+        if let Some(probe) = &self.latency_probe {
+            spans.extend([
+                sep(),
+                Span::styled(
+                    format!(
+                        "⏱ Latency {}/{}",
+                        probe.samples.len() + probe.lost,
+                        self.config.latency_samples.max(1)
+                    ),
+                    Style::default().fg(theme.accent),
+                ),
+            ]);
+        }
+        if let Some(pb) = &self.playback {
+            spans.extend([
+                sep(),
+                Span::styled(
+                    format!("▶ Playing to {}", pb.target.label()),
+                    Style::default().fg(theme.accent),
+                ),
+            ]);
+        }
+        if self.velocity_curve != VelocityCurve::Linear
+            || !self.routes.is_empty()
+            || self.kb_target.is_some()
+        {
+            spans.extend([
+                sep(),
+                Span::styled("Velocity ", label),
+                Span::raw(self.velocity_curve.name()),
+            ]);
+        }
+        if self.log_capacity > self.config.log_capacity.max(1) {
+            spans.extend([
+                sep(),
+                Span::styled("● Long capture", Style::default().fg(theme.alert)),
+            ]);
+        }
+        spans
+    }
+
+    fn index_recently_changed(&self, key: &DeviceKey) -> bool {
+        self.index_changed
+            .get(key)
+            .is_some_and(|t| t.elapsed() < INDEX_HIGHLIGHT)
+    }
+
+    fn cycle_sort_mode(&mut self) {
+        self.sort_mode = self.sort_mode.next();
+        let selected = self.selected_device().map(|d| d.key.clone());
+        sort_devices(&mut self.devices);
+        sort_favorites_first(&mut self.devices, &self.favorites);
+        self.reselect(selected);
+        self.keep_active_first();
+        self.push_status(format!("Device order: {}", self.sort_mode.name()));
+    }
+
+    /// In activity mode, moves inputs up as they send; the selection stays on
+    /// the same device while its row moves.
+    fn keep_active_first(&mut self) {
+        if self.sort_mode != SortMode::Activity || self.list_frozen {
+            return;
+        }
+        let selected = self.selected_device().map(|d| d.key.clone());
+        sort_recently_active(&mut self.devices, &self.last_seen);
+        self.reselect(selected);
+    }
+
+    fn reselect(&mut self, key: Option<DeviceKey>) {
+        if let Some(pos) = key.and_then(|k| self.devices.iter().position(|d| d.key == k)) {
+            self.selected = pos;
+        }
+    }
+
+    fn toggle_list_frozen(&mut self) {
+        self.list_frozen = !self.list_frozen;
+        if self.list_frozen {
+            self.push_status("Device list frozen: refresh disabled".to_string());
+        } else {
+            self.push_status("Device list unfrozen".to_string());
+            self.refresh_devices();
+        }
+    }
+
+    /// Indices into `devices` of the rows passing the name filter, in list order.
+    fn visible_devices(&self) -> Vec<usize> {
+        let query = self.device_filter.to_lowercase();
+        self.devices
+            .iter()
+            .enumerate()
+            .filter(|(_, d)| query.is_empty() || d.key.name.to_lowercase().contains(&query))
+            .filter(|(_, d)| self.show_hidden || !self.is_hidden(&d.key))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    fn is_hidden(&self, key: &DeviceKey) -> bool {
+        self.hidden_devices.contains(key) || self.hidden_rules.iter().any(|r| r.matches(&key.name))
+    }
+
+    /// Hides the selected device, or unhides it while hidden ones are shown.
+    fn toggle_hidden(&mut self) {
+        let Some(key) = self.selected_device().map(|d| d.key.clone()) else {
+            return;
+        };
+        if self.hidden_devices.remove(&key) {
+            self.push_status(format!("Unhidden: {}", key.label()));
+        } else if let Some(rule) = self.hidden_rules.iter().find(|r| r.matches(&key.name)) {
+            self.push_status(format!(
+                "{} is hidden by config rule {}; edit `hidden` to show it",
+                key.label(),
+                rule.source()
+            ));
+            return;
+        } else {
+            self.hidden_devices.insert(key.clone());
+            // Nothing open should be out of sight
+            self.close_port(&key);
+            let hint = if self.show_hidden {
+                String::new()
+            } else {
+                format!(
+                    " ({} shows hidden devices)",
+                    self.keys.label(Action::ShowHidden)
+                )
+            };
+            self.push_status(format!("Hidden: {}{hint}", key.label()));
+        }
+        self.keep_selection_visible();
+    }
+
+    fn toggle_show_hidden(&mut self) {
+        self.show_hidden = !self.show_hidden;
+        let count = self
+            .devices
+            .iter()
+            .filter(|d| self.is_hidden(&d.key))
+            .count();
+        if self.show_hidden {
+            self.push_status(format!("Showing {count} hidden device(s)"));
+        } else {
+            self.push_status(format!("Hiding {count} device(s)"));
+            self.keep_selection_visible();
+        }
+    }
+
+    /// Row of the selection within the filtered list, if it is shown.
+    fn list_position(&self) -> Option<usize> {
+        self.visible_devices()
+            .iter()
+            .position(|&i| i == self.selected)
+    }
+
+    /// Visible devices with a header before each kind's section. Headers
+    /// are never selected; navigation walks `visible_devices` instead.
+    fn list_rows(&self) -> Vec<ListRow> {
+        let mut rows = Vec::new();
+        let mut section = None;
+        for i in self.visible_devices() {
+            let kind = &self.devices[i].key.kind;
+            if section != Some(kind) {
+                rows.push(ListRow::Header(kind.clone()));
+                section = Some(kind);
+            }
+            rows.push(ListRow::Device(i));
+        }
+        rows
+    }
+
+    /// Drawn row of the selection, counting headers.
+    fn selected_row(&self) -> Option<usize> {
+        self.list_rows()
+            .iter()
+            .position(|r| *r == ListRow::Device(self.selected))
+    }
+
+    /// The selected device, or None when the filter hides every row.
+    fn selected_device(&self) -> Option<&DeviceItem> {
+        self.list_position()?;
+        self.devices.get(self.selected)
+    }
+
+    /// Moves the selection to the first visible row if the filter hid it.
+    fn keep_selection_visible(&mut self) {
+        let visible = self.visible_devices();
+        if !visible.contains(&self.selected) {
+            if let Some(&first) = visible.first() {
+                self.selected = first;
+            }
+        }
+    }
+
+    fn start_device_filter(&mut self) {
+        self.filter_editing = true;
+    }
+
+    fn device_filter_key(&mut self, code: KeyCode) {
+        match code {
+            KeyCode::Esc => {
+                self.device_filter.clear();
+                self.filter_editing = false;
+            }
+            KeyCode::Enter => self.filter_editing = false,
+            KeyCode::Backspace => {
+                self.device_filter.pop();
+            }
+            KeyCode::Char(c) => self.device_filter.push(c),
+            KeyCode::Up => self.select_up(),
+            KeyCode::Down => self.select_down(),
+            _ => {}
+        }
+        self.keep_selection_visible();
+    }
+
+    fn update_port_meta(&mut self) {
+        let Some(dev) = self.selected_device() else {
+            self.port_meta = None;
+            return;
+        };
+        if self.port_meta.as_ref().is_some_and(|(k, _)| *k == dev.key) {
+            return;
+        }
+        let meta = if self.virtual_ports.contains(&dev.key) {
+            PortMeta {
+                port: dev.key.name.clone(),
+                backend: midi_backend(),
+                ..PortMeta::default()
+            }
+        } else if self.rtp_target(&dev.key).is_some() {
+            PortMeta {
+                port: dev.key.name.clone(),
+                backend: "RTP-MIDI (AppleMIDI)",
+                ..PortMeta::default()
+            }
+        } else {
+            port_metadata(dev.key.kind.clone(), dev.index)
+        };
+        self.port_meta = Some((dev.key.clone(), meta));
+    }
+
+    /// A prompt, dialog or popup owns the keyboard.
+    fn modal_open(&self) -> bool {
+        self.confirm.is_some()
+            || self.prompt.is_some()
+            || self.sysex_view.is_some()
+            || self.help_view.is_some()
+            || self.recent_popup.is_some()
+            || self.kb_target.is_some()
+            || self.filter_editing
+    }
+
+    /// Click a device to select it, click it again to open/close it; the
+    /// wheel moves the selection over the list and scrolls over the log.
+    /// Ignored while a modal is up. `list_offset` is the list's first drawn row.
+    fn handle_mouse(
+        &mut self,
+        mouse: MouseEvent,
+        list_rect: Rect,
+        list_offset: usize,
+        log_rect: Rect,
+    ) {
+        if self.modal_open() {
+            return;
+        }
+        let inside = |r: Rect| {
+            mouse.column >= r.x
+                && mouse.column < r.x + r.width
+                && mouse.row >= r.y
+                && mouse.row < r.y + r.height
+        };
+        match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left) if inside(list_rect) => {
+                self.focus = Focus::Left;
+                // Skip the top border
+                let Some(row) = mouse.row.checked_sub(list_rect.y + 1) else {
+                    return;
+                };
+                let rows = self.list_rows();
+                let Some(&ListRow::Device(idx)) = rows.get(list_offset + row as usize) else {
+                    return;
+                };
+                if idx == self.selected {
+                    if let Err(e) = self.toggle_open_selected() {
+                        self.push_status(format!("Error: {e:#}"));
+                    }
+                } else {
+                    self.selected = idx;
+                }
+            }
+            MouseEventKind::Down(MouseButton::Left) if inside(log_rect) => {
+                self.focus = Focus::Right;
+            }
+            MouseEventKind::ScrollUp if inside(log_rect) => self.scroll_log(3),
+            MouseEventKind::ScrollDown if inside(log_rect) => self.scroll_log(-3),
+            MouseEventKind::ScrollUp if inside(list_rect) => self.select_up(),
+            MouseEventKind::ScrollDown if inside(list_rect) => self.select_down(),
+            _ => {}
+        }
+    }
+
+    fn cycle_c_octave(&mut self) {
+        self.c_octave = if self.c_octave >= 5 {
+            3
+        } else {
+            self.c_octave + 1
+        };
+        self.push_status(format!(
+            "Note 60 is now C{} (set \"c_octave\" in config.json to keep it)",
+            self.c_octave
+        ));
+    }
+
+    fn cycle_theme(&mut self) {
+        self.theme_name = self.theme_name.next();
+        self.theme = self.theme_name.palette();
+        self.push_status(format!(
+            "Theme: {} (set \"theme\" in config.json to keep it)",
+            self.theme_name.name()
+        ));
+    }
+
+    fn cycle_velocity_curve(&mut self) {
+        self.velocity_curve = self.velocity_curve.next();
+        self.sync_thru();
+        self.push_status(format!(
+            "Velocity curve: {} (set \"velocity_curve\" in config.json to keep it)",
+            self.velocity_curve.name()
+        ));
+    }
+
+    fn toggle_favorite(&mut self) {
+        let Some(key) = self.selected_device().map(|d| d.key.clone()) else {
+            return;
+        };
+        if self.favorites.remove(&key) {
+            self.push_status(format!("Unfavorited: {}", key.name));
+        } else {
+            self.push_status(format!("Favorited: {}", key.name));
+            self.favorites.insert(key.clone());
+        }
+        sort_favorites_first(&mut self.devices, &self.favorites);
+        if self.sort_mode == SortMode::Activity {
+            sort_recently_active(&mut self.devices, &self.last_seen);
+        }
+        if let Some(pos) = self.devices.iter().position(|d| d.key == key) {
+            self.selected = pos;
+        }
+    }
+
+    /// Marks or unmarks the selected device and moves on to the next one.
+    fn toggle_mark(&mut self) {
+        let Some(key) = self.selected_device().map(|d| d.key.clone()) else {
+            return;
+        };
+        if !self.marked.remove(&key) {
+            self.marked.insert(key);
+        }
+        self.select_down();
+    }
+
+    /// Opens every marked device that isn't open yet, then clears the marks.
+    fn open_marked(&mut self) {
+        if self.marked.is_empty() {
+            self.push_status(format!(
+                "No devices marked ({} marks one)",
+                self.keys.label(Action::Mark)
+            ));
+            return;
+        }
+        let pending: Vec<DeviceItem> = self
+            .devices
+            .iter()
+            .filter(|d| self.marked.contains(&d.key))
+            .filter(|d| !self.in_conns.contains_key(&d.key) && !self.out_conns.contains_key(&d.key))
+            .cloned()
+            .collect();
+        let mut opened = 0;
+        for dev in &pending {
+            let res = match dev.key.kind {
+                MidiKind::Input => self.open_input(dev),
+                MidiKind::Output => self.open_output(dev),
+            };
+            match res {
+                Ok(()) => opened += 1,
+                Err(e) => self.push_status(format!("Open failed: {e:#}")),
+            }
+        }
+        self.push_status(format!(
+            "Opened {opened} of {} marked devices ({} already open or gone)",
+            self.marked.len(),
+            self.marked.len() - pending.len()
+        ));
+        self.marked.clear();
+    }
+
+    fn select_up(&mut self) {
+        let visible = self.visible_devices();
+        if visible.is_empty() {
+            return;
+        }
+        let pos = self.list_position().unwrap_or(0);
+        self.selected = visible[pos.checked_sub(1).unwrap_or(visible.len() - 1)];
+    }
+
+    fn select_down(&mut self) {
+        let visible = self.visible_devices();
+        if visible.is_empty() {
+            return;
+        }
+        let next = self
+            .list_position()
+            .map_or(0, |pos| (pos + 1) % visible.len());
+        self.selected = visible[next];
+    }
+
+    fn toggle_open_selected(&mut self) -> Result<()> {
+        let Some(dev) = self.selected_device().cloned() else {
+            return Ok(());
+        };
+
+        if self.close_port(&dev.key) {
+            let mut closed = vec![dev.key.clone()];
+            if let Some(partner) = self.unlink(&dev.key) {
+                if self.close_port(&partner) {
+                    closed.push(partner);
+                }
+            }
+            self.remember_closed(closed);
+            return Ok(());
+        }
+        match dev.key.kind {
+            MidiKind::Input => self.open_input(&dev),
+            MidiKind::Output => self.open_output(&dev),
+        }
+    }
+
+    /// Closes one open port and what hangs off it; false if it wasn't open.
+    fn close_port(&mut self, key: &DeviceKey) -> bool {
+        match key.kind {
+            MidiKind::Input => {
+                if self.in_conns.remove(key).is_none() {
+                    return false;
+                }
+                self.held_notes.remove(key);
+                self.mpe.remove(key);
+                self.last_seen.remove(key);
+                self.param_trackers.remove(key);
+                self.last_stamp.remove(key);
+                self.param_held_at.remove(key);
+                self.clock_tempo.remove(key);
+                let open_for = self.open_duration(key);
+                self.push_status(format!("Closed input: {}{open_for}", key.name));
+            }
+            MidiKind::Output => {
+                if self.out_conns.remove(key).is_none() {
+                    return false;
+                }
+                self.sync_thru();
+                self.stop_users_of(key, "output closed");
+                let open_for = self.open_duration(key);
+                self.push_status(format!("Closed output: {}{open_for}", key.name));
+            }
+        }
+        if self.virtual_ports.contains(key) {
+            self.drop_virtual_ports(std::slice::from_ref(key));
+        }
+        true
+    }
+
+    /// The port of the other kind with the same base name, such as the
+    /// output next to an input.
+    fn pair_partner(&self, key: &DeviceKey) -> Option<&DeviceItem> {
+        self.devices.iter().find(|d| {
+            d.key.kind != key.kind
+                && d.key.instance == key.instance
+                && base_name(&d.key.name) == base_name(&key.name)
+        })
+    }
+
+    /// Opens both sides of the selected device and links them, so closing
+    /// either one closes the other.
+    fn open_pair(&mut self) {
+        let Some(dev) = self.selected_device().cloned() else {
+            return;
+        };
+        let Some(partner) = self.pair_partner(&dev.key).cloned() else {
+            let other = match dev.key.kind {
+                MidiKind::Input => "output",
+                MidiKind::Output => "input",
+            };
+            self.push_status(format!("No {other} to pair with {}", dev.key.label()));
+            return;
+        };
+        let (input, output) = match dev.key.kind {
+            MidiKind::Input => (dev, partner),
+            MidiKind::Output => (partner, dev),
+        };
+        let input_was_open = self.in_conns.contains_key(&input.key);
+        if !input_was_open {
+            if let Err(e) = self.open_input(&input) {
+                self.push_status(format!("Open pair failed: {e:#}"));
+                return;
+            }
+        }
+        if !self.out_conns.contains_key(&output.key) {
+            if let Err(e) = self.open_output(&output) {
+                // Don't leave half a pair behind
+                if !input_was_open {
+                    self.close_port(&input.key);
+                }
+                self.push_status(format!("Open pair failed: {e:#}"));
+                return;
+            }
+        }
+        self.unlink(&input.key);
+        self.unlink(&output.key);
+        self.link(input.key.clone(), output.key.clone());
+        self.push_status(format!(
+            "Linked {} ⇄ {}; closing either closes both",
+            input.key.label(),
+            output.key.label()
+        ));
+    }
+
+    fn link(&mut self, input: DeviceKey, output: DeviceKey) {
+        self.linked.insert(input.clone(), output.clone());
+        self.linked.insert(output, input);
+    }
+
+    /// Drops the link of `key`, returning its former partner.
+    fn unlink(&mut self, key: &DeviceKey) -> Option<DeviceKey> {
+        let partner = self.linked.remove(key)?;
+        self.linked.remove(&partner);
+        Some(partner)
+    }
+
+    /// Each link once, as (input, output).
+    fn linked_pairs(&self) -> impl Iterator<Item = (&DeviceKey, &DeviceKey)> {
+        self.linked
+            .iter()
+            .filter(|(k, _)| k.kind == MidiKind::Input)
+    }
+
+    /// Opens every listed port of `kind` that isn't open yet.
+    fn open_all(&mut self, kind: MidiKind) {
+        let pending: Vec<DeviceItem> = self
+            .devices
+            .iter()
+            .filter(|d| d.key.kind == kind)
+            .filter(|d| !self.is_hidden(&d.key))
+            .filter(|d| !self.in_conns.contains_key(&d.key) && !self.out_conns.contains_key(&d.key))
+            .cloned()
+            .collect();
+        let mut opened = 0;
+        for dev in &pending {
+            let res = match kind {
+                MidiKind::Input => self.open_input(dev),
+                MidiKind::Output => self.open_output(dev),
+            };
+            match res {
+                Ok(()) => opened += 1,
+                Err(e) => self.push_status(format!("Open failed: {e:#}")),
+            }
+        }
+        let what = match kind {
+            MidiKind::Input => "inputs",
+            MidiKind::Output => "outputs",
+        };
+        self.push_status(format!(
+            "Opened {opened} of {} closed {what}",
+            pending.len()
+        ));
+    }
+
+    fn close_all(&mut self) {
+        let in_count = self.in_conns.len();
         let out_count = self.out_conns.len();
+        let closed: Vec<DeviceKey> = self
+            .in_conns
+            .keys()
+            .chain(self.out_conns.keys())
+            .cloned()
+            .collect();
+        for key in &closed {
+            let open_for = self.open_duration(key);
+            let kind = match key.kind {
+                MidiKind::Input => "input",
+                MidiKind::Output => "output",
+            };
+            self.push_status(format!("Closed {kind}: {}{open_for}", key.name));
+        }
+        self.remember_closed(closed);
+        self.linked.clear();
         self.in_conns.clear(); // drop closes
+        self.held_notes.clear();
+        self.last_stamp.clear();
+        self.mpe.clear();
+        self.last_seen.clear();
+        self.param_trackers.clear();
+        self.param_held_at.clear();
+        self.clock_tempo.clear();
+        // Their threads hold an output too
+        let outputs: Vec<DeviceKey> = self.out_conns.keys().cloned().collect();
+        for key in &outputs {
+            self.stop_users_of(key, "output closed");
+        }
         self.out_conns.clear(); // drop closes
+        self.sync_thru(); // drops the callbacks' clones too
+        let virtual_keys: Vec<DeviceKey> = self.virtual_ports.iter().cloned().collect();
+        self.drop_virtual_ports(&virtual_keys);
         self.push_status(format!(
             "Closed all ports (inputs: {in_count}, outputs: {out_count})"
         ));
     }
 
-    fn open_input(&mut self, dev: &DeviceItem) -> Result<()> {
-        let mut inp = MidiInput::new("midir-tui-input").context("create MidiInput failed")?;
-        inp.ignore(midir::Ignore::None);
+    /// Reopening for a new ignore mode keeps the original open time.
+    fn mark_opened(&mut self, key: &DeviceKey) {
+        self.opened_at
+            .entry(key.clone())
+            .or_insert_with(Instant::now);
+    }
+
+    /// " (open HH:MM:SS)" for a port being closed; forgets its open time.
+    fn open_duration(&mut self, key: &DeviceKey) -> String {
+        self.opened_at
+            .remove(key)
+            .map(|t| format!(" (open {})", format_hms(t.elapsed())))
+            .unwrap_or_default()
+    }
+
+    #[cfg(feature = "osc")]
+    fn connect_osc(&mut self, target: &str) -> Result<()> {
+        let sender = osc::OscSender::connect(target, self.config.osc.clone())?;
+        self.osc_out = Some(sender);
+        self.push_status(format!(
+            "Forwarding notes, CCs and pitch bend to OSC {target}"
+        ));
+        Ok(())
+    }
+
+    #[cfg(not(feature = "osc"))]
+    fn connect_osc(&mut self, _target: &str) -> Result<()> {
+        Err(anyhow!("--osc-out needs a build with the `osc` feature"))
+    }
+
+    fn listen_json(&mut self, addr: &str) -> Result<()> {
+        let stream = JsonStream::bind(addr)?;
+        self.push_status(format!(
+            "Streaming incoming MIDI as JSON lines on tcp://{}",
+            stream.local_addr()
+        ));
+        self.json_out = Some(stream);
+        Ok(())
+    }
+
+    /// Sends one incoming message to the `--json-tcp` clients, if any.
+    fn stream_json(&mut self, ev: &LoggedEvent) {
+        let Some(stream) = self.json_out.as_ref().filter(|s| s.client_count() > 0) else {
+            return;
+        };
+        let record = JsonRecord {
+            device: ev.device.as_ref().map_or("", |d| d.name.as_str()),
+            status: ev.data.first().copied().unwrap_or(0),
+            data: &ev.data,
+            decoded: self.describe(ev),
+            ts: ev
+                .ts
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |d| d.as_millis() as u64),
+        };
+        if let Err(e) = stream.send(&record) {
+            self.json_out = None;
+            self.push_status(format!("JSON stream stopped: {e:#}"));
+        }
+    }
+
+    /// Lists each `--rtpmidi` target as an output; opening one invites it.
+    #[cfg(feature = "rtpmidi")]
+    fn add_rtp_peers(&mut self, targets: &[String]) {
+        self.rtp_peers.extend_from_slice(targets);
+        self.refresh_devices();
+    }
+
+    #[cfg(not(feature = "rtpmidi"))]
+    fn add_rtp_peers(&mut self, _targets: &[String]) {}
+
+    #[cfg(feature = "rtpmidi")]
+    fn rtp_items(&self) -> Vec<DeviceItem> {
+        self.rtp_peers
+            .iter()
+            .map(|target| DeviceItem {
+                key: rtp_key(target),
+                index: 0,
+            })
+            .collect()
+    }
+
+    #[cfg(not(feature = "rtpmidi"))]
+    fn rtp_items(&self) -> Vec<DeviceItem> {
+        Vec::new()
+    }
+
+    /// The `host:port` behind a remote session's list row.
+    #[cfg(feature = "rtpmidi")]
+    fn rtp_target(&self, key: &DeviceKey) -> Option<&str> {
+        self.rtp_peers
+            .iter()
+            .find(|t| rtp_key(t) == *key)
+            .map(String::as_str)
+    }
+
+    #[cfg(not(feature = "rtpmidi"))]
+    fn rtp_target(&self, _key: &DeviceKey) -> Option<&str> {
+        None
+    }
+
+    /// Stops what sends to output `key`: playback or the test sequence in the
+    /// playback slot, clock out and the virtual keyboard. `why` ends their
+    /// status lines.
+    fn stop_users_of(&mut self, key: &DeviceKey, why: &str) {
+        if let Some(pb) = self.playback.as_ref().filter(|pb| &pb.target == key) {
+            // The thread sees the flag between messages; reap_playback joins it
+            pb.cancel.store(true, Ordering::Relaxed);
+            self.push_status(format!("Playback stopped: {why}"));
+        }
+        if self.clock_out.as_ref().is_some_and(|c| &c.target == key) {
+            self.stop_clock();
+        }
+        if self.kb_target.as_ref() == Some(key) {
+            self.kb_target = None;
+            self.kb_held.clear();
+            self.push_status(format!("Virtual keyboard off: {why}"));
+        }
+    }
+
+    fn remember_closed(&mut self, mut keys: Vec<DeviceKey>) {
+        keys.retain(|k| !self.virtual_ports.contains(k));
+        self.last_closed = (!keys.is_empty()).then(|| (Instant::now(), keys));
+    }
+
+    /// Reopens the ports shut by the most recent close, if still recent.
+    fn undo_close(&mut self) {
+        let Some((at, keys)) = self.last_closed.take() else {
+            self.push_status("Nothing to undo".to_string());
+            return;
+        };
+        if at.elapsed() > UNDO_WINDOW {
+            self.push_status("Nothing to undo (last close was too long ago)".to_string());
+            return;
+        }
+        self.reopen_devices(&keys);
+        let reopened: Vec<String> = keys
+            .iter()
+            .filter(|k| self.in_conns.contains_key(k) || self.out_conns.contains_key(k))
+            .map(|k| k.label())
+            .collect();
+        self.push_status(format!(
+            "Reopened {} port(s): {}",
+            reopened.len(),
+            reopened.join(", ")
+        ));
+    }
+
+    fn open_input(&mut self, dev: &DeviceItem) -> Result<()> {
+        let res = self.connect_input(dev);
+        self.note_open_result(&dev.key, res)
+    }
+
+    /// Keeps the error of a failed open for the details pane; a successful
+    /// open clears it.
+    fn note_open_result(&mut self, key: &DeviceKey, res: Result<()>) -> Result<()> {
+        match &res {
+            Ok(()) => {
+                self.last_error = None;
+                self.open_failures.remove(key);
+            }
+            Err(e) => {
+                self.last_error = Some((key.clone(), format!("{e:#}")));
+                self.open_failures.insert(key.clone(), format!("{e:#}"));
+            }
+        }
+        res
+    }
+
+    fn connect_input(&mut self, dev: &DeviceItem) -> Result<()> {
+        let mut inp = MidiInput::new("midir-tui-input").context("create MidiInput failed")?;
+        inp.ignore(IGNORE_MODES[self.input_ignore].0);
+        let callback = self.input_callback(dev.key.clone());
+        let (conn, index) = connect_input(inp, dev, "midir-tui-in", callback)?;
+        self.note_port_index(&dev.key, index);
+
+        self.in_conns.insert(dev.key.clone(), conn);
+        self.mark_opened(&dev.key);
+        self.remember_recent(&dev.key);
+        self.push_status(format!("Opened input: {}", dev.key.label()));
+        Ok(())
+    }
+
+    /// Steps to the next ignore mode. midir only applies the flags when a
+    /// port is connected, so open inputs are reopened; virtual inputs keep
+    /// theirs, since recreating them would disconnect their clients.
+    fn cycle_input_ignore(&mut self) {
+        self.input_ignore = (self.input_ignore + 1) % IGNORE_MODES.len();
+        let reopen: Vec<DeviceItem> = self
+            .devices
+            .iter()
+            .filter(|d| self.in_conns.contains_key(&d.key) && !self.virtual_ports.contains(&d.key))
+            .cloned()
+            .collect();
+        for dev in &reopen {
+            self.in_conns.remove(&dev.key);
+            if let Err(e) = self.open_input(dev) {
+                self.push_status(format!("Reopen failed: {e:#}"));
+            }
+        }
+        self.push_status(format!(
+            "Inputs ignore {} ({} reopened)",
+            IGNORE_MODES[self.input_ignore].1,
+            reopen.len()
+        ));
+    }
+
+    /// Input callback shared by real and virtual inputs: forwards to thru
+    /// routes, then hands the message to the log pipeline. The library's
+    /// `connect_input`/`restore_running_status` call it once per message.
+    fn input_callback(&self, key: DeviceKey) -> impl FnMut(u64, &[u8]) + Send + 'static {
+        let tx = self.tx.clone();
+        let thru = Arc::clone(&self.thru);
+        let dropped = Arc::clone(&self.dropped);
+        move |stamp, message| {
+            // Forward first: thru latency matters more than logging
+            if let Ok(table) = thru.lock() {
+                for (out, route, curve) in table.get(&key).into_iter().flatten() {
+                    if let Ok(mut out) = out.lock() {
+                        if route.is_plain(*curve) {
+                            let _ = out.send(message);
+                        } else {
+                            let _ = out.send(&route.process(message, *curve));
+                        }
+                    }
+                }
+            }
+            // Never block the MIDI thread: if the UI is behind, count and drop
+            let ev = LoggedEvent {
+                ts: SystemTime::now(),
+                at: Instant::now(),
+                stamp,
+                device: Some(key.clone()),
+                direction: LogDirection::In,
+                kind: EventKind::Midi,
+                data: message.to_vec(),
+                param: None,
+                delta_us: None,
+            };
+            if let Err(TrySendError::Full(_)) = tx.try_send(ev) {
+                dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// `--stress`: feeds `rate` synthetic messages per second into the same
+    /// channel as the input callbacks, as from a device that isn't listed.
+    /// The thread ends when the app drops the receiver.
+    fn start_stress(&mut self, rate: u32) {
+        let tx = self.tx.clone();
+        let dropped = Arc::clone(&self.dropped);
+        let key = DeviceKey {
+            name: "stress (synthetic)".to_string(),
+            kind: MidiKind::Input,
+            instance: 0,
+        };
+        thread::spawn(move || {
+            let start = Instant::now();
+            let mut sent = 0u64;
+            loop {
+                let due = (start.elapsed().as_secs_f64() * rate as f64) as u64;
+                while sent < due {
+                    // Alternate notes and a CC sweep
+                    let n = (sent % 128) as u8;
+                    let data = match sent % 3 {
+                        0 => vec![0x90, n, 100],
+                        1 => vec![0x80, n, 0],
+                        _ => vec![0xB0, 1, n],
+                    };
+                    let ev = LoggedEvent {
+                        ts: SystemTime::now(),
+                        at: Instant::now(),
+                        stamp: start.elapsed().as_micros() as u64,
+                        device: Some(key.clone()),
+                        direction: LogDirection::In,
+                        kind: EventKind::Midi,
+                        data,
+                        param: None,
+                        delta_us: None,
+                    };
+                    match tx.try_send(ev) {
+                        Ok(()) => {}
+                        Err(TrySendError::Full(_)) => {
+                            dropped.fetch_add(1, Ordering::Relaxed);
+                        }
+                        Err(TrySendError::Disconnected(_)) => return,
+                    }
+                    sent += 1;
+                }
+                thread::sleep(Duration::from_millis(1));
+            }
+        });
+        self.push_status(format!("Stress: {rate} synthetic messages/s"));
+    }
+
+    /// List rows for our virtual ports; the index is unused for them.
+    fn virtual_items(&self) -> Vec<DeviceItem> {
+        self.virtual_ports
+            .iter()
+            .map(|key| DeviceItem {
+                key: key.clone(),
+                index: 0,
+            })
+            .collect()
+    }
+
+    #[cfg(unix)]
+    fn create_virtual_port(&mut self, kind: MidiKind, name: &str) -> Result<()> {
+        use midir::os::unix::{VirtualInput, VirtualOutput};
+
+        let key = DeviceKey {
+            name: format!("{name} (virtual)"),
+            kind,
+            instance: 0,
+        };
+        if self.virtual_ports.contains(&key) {
+            return Err(anyhow!("Virtual port already exists: {}", key.name));
+        }
+        match key.kind {
+            MidiKind::Input => {
+                let mut inp =
+                    MidiInput::new("midir-tui-virtual").context("create MidiInput failed")?;
+                inp.ignore(IGNORE_MODES[self.input_ignore].0);
+                let conn = inp
+                    .create_virtual(
+                        name,
+                        restore_running_status(self.input_callback(key.clone())),
+                        (),
+                    )
+                    .map_err(|e| anyhow!("Failed to create virtual input: {name}: {e}"))?;
+                self.in_conns.insert(key.clone(), conn);
+                self.mark_opened(&key);
+            }
+            MidiKind::Output => {
+                let out =
+                    MidiOutput::new("midir-tui-virtual").context("create MidiOutput failed")?;
+                let conn = out
+                    .create_virtual(name)
+                    .map_err(|e| anyhow!("Failed to create virtual output: {name}: {e}"))?;
+                self.out_conns
+                    .insert(key.clone(), Arc::new(Mutex::new(OutputConn::Midi(conn))));
+                self.mark_opened(&key);
+                self.sync_thru();
+            }
+        }
+        self.push_status(format!("Created virtual port: {}", key.name));
+        self.virtual_ports.insert(key.clone());
+        self.devices.push(DeviceItem {
+            key: key.clone(),
+            index: 0,
+        });
+        sort_devices(&mut self.devices);
+        sort_favorites_first(&mut self.devices, &self.favorites);
+        if self.sort_mode == SortMode::Activity {
+            sort_recently_active(&mut self.devices, &self.last_seen);
+        }
+        if let Some(pos) = self.devices.iter().position(|d| d.key == key) {
+            self.selected = pos;
+        }
+        self.keep_selection_visible();
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn create_virtual_port(&mut self, _kind: MidiKind, _name: &str) -> Result<()> {
+        Err(anyhow!("Virtual ports are not supported on this platform"))
+    }
+
+    /// Closing a virtual port destroys it, so its row goes too.
+    fn drop_virtual_ports(&mut self, keys: &[DeviceKey]) {
+        if keys.is_empty() {
+            return;
+        }
+        let current = self.devices.get(self.selected).map(|d| d.key.clone());
+        for key in keys {
+            self.virtual_ports.remove(key);
+        }
+        self.devices.retain(|d| !keys.contains(&d.key));
+        self.selected = current
+            .and_then(|k| self.devices.iter().position(|d| d.key == k))
+            .unwrap_or(0)
+            .min(self.devices.len().saturating_sub(1));
+        self.keep_selection_visible();
+    }
+
+    fn open_output(&mut self, dev: &DeviceItem) -> Result<()> {
+        let res = self.attach_output(dev);
+        self.note_open_result(&dev.key, res)
+    }
+
+    fn attach_output(&mut self, dev: &DeviceItem) -> Result<()> {
+        if let Some(target) = self.rtp_target(&dev.key) {
+            let target = target.to_string();
+            return self.invite_rtp(&dev.key, target);
+        }
+        let conn = OutputConn::Midi(self.connect_midi_output(dev)?);
+        self.add_output_conn(&dev.key, conn);
+        Ok(())
+    }
+
+    fn add_output_conn(&mut self, key: &DeviceKey, conn: OutputConn) {
+        self.out_conns
+            .insert(key.clone(), Arc::new(Mutex::new(conn)));
+        self.mark_opened(key);
+        self.sync_thru();
+        self.remember_recent(key);
+        self.push_status(format!("Opened output: {}", key.label()));
+    }
+
+    /// Invites can wait on a silent peer for seconds, so they run on a thread;
+    /// `poll_rtp_invites` opens the row once the session is accepted.
+    #[cfg(feature = "rtpmidi")]
+    fn invite_rtp(&mut self, key: &DeviceKey, target: String) -> Result<()> {
+        if self.rtp_invites.iter().any(|(k, _)| k == key) {
+            self.push_status(format!("Still inviting {target}"));
+            return Ok(());
+        }
+        self.push_status(format!("Inviting RTP-MIDI peer {target}…"));
+        let status = self.status_sender();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let res = rtpmidi::Session::connect(&target, "midir-tui")
+                .with_context(|| format!("RTP-MIDI session with {target} failed"));
+            if let Err(e) = &res {
+                status.send(format!("{e:#}"));
+            }
+            let _ = tx.send(res);
+        });
+        self.rtp_invites.push((key.clone(), rx));
+        Ok(())
+    }
+
+    #[cfg(not(feature = "rtpmidi"))]
+    fn invite_rtp(&mut self, _key: &DeviceKey, _target: String) -> Result<()> {
+        Err(anyhow!("RTP-MIDI needs a build with the `rtpmidi` feature"))
+    }
+
+    /// Takes up sessions whose invite finished since the last tick.
+    #[cfg(feature = "rtpmidi")]
+    fn poll_rtp_invites(&mut self) {
+        let mut finished = Vec::new();
+        self.rtp_invites.retain(|(key, rx)| match rx.try_recv() {
+            Ok(res) => {
+                finished.push((key.clone(), res));
+                false
+            }
+            Err(mpsc::TryRecvError::Empty) => true,
+            Err(mpsc::TryRecvError::Disconnected) => false,
+        });
+        for (key, res) in finished {
+            let res = res.map(|session| self.add_output_conn(&key, OutputConn::Rtp(session)));
+            let _ = self.note_open_result(&key, res);
+        }
+    }
+
+    #[cfg(not(feature = "rtpmidi"))]
+    fn poll_rtp_invites(&mut self) {}
+
+    fn connect_midi_output(&mut self, dev: &DeviceItem) -> Result<MidiOutputConnection> {
+        let out = MidiOutput::new("midir-tui-output").context("create MidiOutput failed")?;
+        let ports = out.ports();
+        let names: Vec<String> = ports
+            .iter()
+            .enumerate()
+            .map(|(i, p)| out.port_name(p).unwrap_or_else(|_| format!("Output #{i}")))
+            .collect();
+        let index = resolve_port(&names, dev);
+        let port = ports.get(index).context("output port index out of range")?;
+        let port_name = names[index].clone();
+        if port_name != dev.key.name {
+            return Err(anyhow!(
+                "Output #{index} is now {port_name:?}; refresh the list (r)"
+            ));
+        }
+        self.note_port_index(&dev.key, index);
+
+        out.connect(port, "midir-tui-out")
+            .map_err(|e| anyhow!("Failed to open output: {port_name}: {e}"))
+    }
+
+    /// Keeps the list's index in step with a port found somewhere else.
+    fn note_port_index(&mut self, key: &DeviceKey, index: usize) {
+        if let Some(d) = self.devices.iter_mut().find(|d| d.key == *key) {
+            d.index = index;
+        }
+    }
+
+    fn remember_recent(&mut self, key: &DeviceKey) {
+        self.recent.retain(|k| k != key);
+        self.recent.insert(0, key.clone());
+        self.recent.truncate(MAX_RECENT);
+    }
+
+    fn open_recent_popup(&mut self) {
+        if self.recent.is_empty() {
+            self.push_status("No recently opened devices yet".to_string());
+        } else {
+            self.recent_popup = Some(0);
+        }
+    }
+
+    fn jump_to_recent(&mut self, i: usize) {
+        let Some(key) = self.recent.get(i).cloned() else {
+            return;
+        };
+        match self.devices.iter().position(|d| d.key == key) {
+            Some(pos) => {
+                self.selected = pos;
+                if self.list_position().is_none() {
+                    self.device_filter.clear();
+                }
+            }
+            None => self.push_status(format!("Not currently available: {}", key.name)),
+        }
+    }
+
+    fn status_sender(&self) -> StatusSender {
+        StatusSender {
+            tx: self.tx.clone(),
+            dropped: Arc::clone(&self.dropped),
+        }
+    }
+
+    fn push_status(&mut self, msg: String) {
+        self.push_log(LoggedEvent::status(msg));
+    }
+
+    fn push_log(&mut self, ev: LoggedEvent) {
+        if self.echo_stdout {
+            let line = self.timed_log_line(&ev);
+            match ev.kind {
+                EventKind::Midi => println!("{line}"),
+                EventKind::Status(_) => eprintln!("{line}"),
+            }
+        }
+        // Keep a scrolled-up or paused view anchored on what the user is reading
+        if (self.log_scroll > 0 || self.log_paused) && self.log_visible(&ev) {
+            self.log_scroll += 1;
+        }
+        let dir = ev.direction;
+        let dir_cap = match dir {
+            LogDirection::In => Some(self.scaled_cap(self.config.log_capacity_in)),
+            LogDirection::Out => Some(self.scaled_cap(self.config.log_capacity_out)),
+            LogDirection::Local => None,
+        };
+        if let Some(cap) = dir_cap.map(|cap| cap.max(1)) {
+            // Taking one entry out of the middle shifts the rest, so a direction
+            // may run a little over its cap and is cut back in a single pass
+            let count = self.log_counts.get(&dir).copied().unwrap_or(0);
+            if count >= cap + evict_slack(cap) {
+                let mut excess = count + 1 - cap;
+                self.log.retain(|e| {
+                    let evict = excess > 0 && e.direction == dir;
+                    excess -= evict as usize;
+                    !evict
+                });
+                self.log_counts.insert(dir, cap - 1);
+            }
+        }
+        self.trim_log(self.log_capacity - 1);
+        self.log.push_back(ev);
+        *self.log_counts.entry(dir).or_insert(0) += 1;
+        // Never past the oldest entry, which evictions keep moving
+        self.log_scroll = self.log_scroll.min(self.log.len().saturating_sub(1));
+    }
+
+    /// A per-direction cap grown in proportion while the global cap is raised
+    /// above the configured one.
+    fn scaled_cap(&self, cap: usize) -> usize {
+        let base = self.config.log_capacity.max(1);
+        if self.log_capacity <= base {
+            return cap;
+        }
+        cap.saturating_mul(self.log_capacity) / base
+    }
+
+    /// Switches between the configured log capacity and a large one for long
+    /// captures, reporting the rough memory cost.
+    fn toggle_long_capture(&mut self) {
+        let base = self.config.log_capacity.max(1);
+        self.log_capacity = if self.log_capacity > base {
+            base
+        } else {
+            LONG_CAPTURE.max(base)
+        };
+        self.trim_log(self.log_capacity);
+        // Event plus a short message and the device name on the heap
+        let per_entry = std::mem::size_of::<LoggedEvent>() + 48;
+        let mb = (self.log_capacity * per_entry) as f64 / (1024.0 * 1024.0);
+        self.push_status(format!(
+            "Log capacity {} entries (up to ~{mb:.1} MB, more with long SysEx)",
+            self.log_capacity
+        ));
+    }
+
+    /// Drops the oldest entries until at most `max` remain. The bound is our
+    /// own field: a VecDeque's allocated capacity may exceed what was asked.
+    fn trim_log(&mut self, max: usize) {
+        while self.log.len() > max {
+            let Some(old) = self.log.pop_front() else {
+                break;
+            };
+            self.count_evicted(old.direction);
+        }
+    }
+
+    fn count_evicted(&mut self, dir: LogDirection) {
+        if let Some(n) = self.log_counts.get_mut(&dir) {
+            *n = n.saturating_sub(1);
+        }
+    }
+
+    fn drain_rx(&mut self) {
+        self.poll_rtp_invites();
+        for _ in 0..DRAIN_PER_TICK {
+            let Ok(ev) = self.rx.try_recv() else {
+                break;
+            };
+            self.last_activity = Instant::now();
+            if ev.kind != EventKind::Midi {
+                self.push_log(ev);
+                continue;
+            }
+            let Some(mut ev) = self.reassemble_sysex(ev) else {
+                continue;
+            };
+            if let (LogDirection::In, Some(key)) = (ev.direction, &ev.device) {
+                ev.param = self.track_params(key, &ev.data);
+                ev.delta_us = self
+                    .last_stamp
+                    .insert(key.clone(), ev.stamp)
+                    .map(|prev| ev.stamp.saturating_sub(prev));
+                self.device_stats
+                    .entry(key.clone())
+                    .or_default()
+                    .record(Instant::now());
+                self.received_total += 1;
+                self.last_seen.insert(key.clone(), Instant::now());
+                self.check_latency_probe(&ev);
+                self.check_midi_binding(&ev.data);
+                self.run_macros(&ev.data);
+                self.controls.update(&ev.data);
+                self.track_last_note(key, &ev.data);
+                self.track_held_notes(key.clone(), &ev.data);
+                self.mpe.entry(key.clone()).or_default().update(&ev.data);
+                self.track_clock(key, ev.stamp, &ev.data);
+                #[cfg(feature = "osc")]
+                if let Some(Err(e)) = self.osc_out.as_ref().map(|o| o.send_midi(&ev.data)) {
+                    self.push_status(format!("Error: {e:#}"));
+                }
+                self.stream_json(&ev);
+                self.count_velocities(key, &ev.data);
+            }
+            self.check_alert(&ev.data);
+            self.push_log(ev);
+        }
+        self.flush_stale_sysex();
+        self.flush_held_params(Instant::now());
+    }
+
+    /// The packet's part in a parameter sequence; held CC 6 rows it ends
+    /// show their change.
+    fn track_params(&mut self, key: &DeviceKey, packet: &[u8]) -> Option<ParamEvent> {
+        let (param, ended) = self
+            .param_trackers
+            .entry(key.clone())
+            .or_default()
+            .update_packet(packet);
+        for change in ended {
+            self.show_held_param(key, change);
+        }
+        if param == Some(ParamEvent::Held) {
+            self.param_held_at
+                .entry(key.clone())
+                .or_insert_with(Instant::now);
+        }
+        param
+    }
+
+    /// Shows Data Entry MSBs that no LSB followed by `now`.
+    fn flush_held_params(&mut self, now: Instant) {
+        let due: Vec<DeviceKey> = self
+            .param_held_at
+            .iter()
+            .filter(|(_, at)| now.duration_since(**at) >= PARAM_LSB_WAIT)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in due {
+            self.param_held_at.remove(&key);
+            let changes = self
+                .param_trackers
+                .get_mut(&key)
+                .map(ParamTracker::flush)
+                .unwrap_or_default();
+            for change in changes {
+                self.show_held_param(&key, change);
+            }
+        }
+    }
+
+    /// Turns the held CC 6 row into the parameter change it made.
+    fn show_held_param(&mut self, key: &DeviceKey, change: ParamChange) {
+        let Some(ev) = self.log.iter_mut().rev().find(|e| {
+            e.param == Some(ParamEvent::Held)
+                && e.device.as_ref() == Some(key)
+                && e.data.first().map(|s| s & 0x0F) == Some(change.channel)
+        }) else {
+            return;
+        };
+        ev.param = Some(ParamEvent::Value(change));
+        if self.echo_stdout {
+            let ev = ev.clone();
+            println!("{}", self.timed_log_line(&ev));
+        }
+    }
+
+    fn count_velocities(&mut self, key: &DeviceKey, data: &[u8]) {
+        for message in midi::split_messages(data) {
+            if let Some(NoteEvent::On { velocity, .. }) = midi::note_event(&message) {
+                let bin = (velocity as usize - 1) * VELOCITY_BINS / 127;
+                self.velocity_hist.entry(key.clone()).or_default()[bin] += 1;
+            }
+        }
+    }
+
+    fn track_last_note(&mut self, key: &DeviceKey, data: &[u8]) {
+        for message in midi::split_messages(data) {
+            if let [status, lsb, msb] = *message {
+                if status & 0xF0 == 0xE0 {
+                    let bends = self.channel_bend.entry(key.clone()).or_insert([0; 16]);
+                    bends[(status & 0x0F) as usize] = ((msb as i16) << 7 | lsb as i16) - 8192;
+                    continue;
+                }
+            }
+            if let Some(on @ NoteEvent::On { .. }) = midi::note_event(&message) {
+                self.last_note
+                    .insert(key.clone(), (on.channel(), on.note()));
+            }
+        }
+    }
+
+    /// Most recent Note On from `device` and the pitch bend (in cents) last
+    /// seen on its channel.
+    fn last_note_with_bend(&self, device: &DeviceKey) -> Option<(u8, f64)> {
+        let &(ch, note) = self.last_note.get(device)?;
+        let bend = self.channel_bend.get(device).map_or(0, |b| b[ch as usize]);
+        Some((
+            note,
+            bend as f64 / 8192.0 * self.config.pitch_bend_range * 100.0,
+        ))
+    }
+
+    fn track_clock(&mut self, key: &DeviceKey, stamp: u64, data: &[u8]) {
+        match data.first() {
+            Some(0xF8) => self
+                .clock_tempo
+                .entry(key.clone())
+                .or_default()
+                .record(stamp, Instant::now()),
+            // Start, continue, stop
+            Some(0xFA..=0xFC) => {
+                if let Some(tempo) = self.clock_tempo.get_mut(key) {
+                    tempo.reset();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn track_held_notes(&mut self, key: DeviceKey, data: &[u8]) {
+        let held = self.held_notes.entry(key).or_insert([0; 128]);
+        for message in midi::split_messages(data) {
+            let (Some(&status), Some(&d1)) = (message.first(), message.get(1)) else {
+                continue;
+            };
+            match midi::note_event(&message) {
+                Some(NoteEvent::On { note, velocity, .. }) => {
+                    held[(note & 0x7F) as usize] = velocity
+                }
+                Some(NoteEvent::Off { note, .. }) => held[(note & 0x7F) as usize] = 0,
+                // All Sound Off / All Notes Off
+                None if status & 0xF0 == 0xB0 && (d1 == 120 || d1 == 123) => *held = [0; 128],
+                None => {}
+            }
+        }
+    }
+
+    fn reset_device_stats(&mut self) {
+        self.device_stats.clear();
+        self.velocity_hist.clear();
+        self.dropped.store(0, Ordering::Relaxed);
+        self.push_status("Message counters and velocity histograms reset".to_string());
+    }
+
+    /// Buffers a SysEx split across callbacks until its 0xF7 arrives. Returns
+    /// the event to log, or None while the message is still incomplete.
+    fn reassemble_sysex(&mut self, ev: LoggedEvent) -> Option<LoggedEvent> {
+        let (Some(key), Some(&first)) = (ev.device.clone(), ev.data.first()) else {
+            return Some(ev);
+        };
+        // Realtime bytes may legally interleave with a SysEx dump
+        if first >= 0xF8 {
+            return Some(ev);
+        }
+        if let Some((_, mut partial)) = self.sysex_partial.remove(&key) {
+            if first < 0x80 || first == 0xF7 {
+                partial.data.extend_from_slice(&ev.data);
+                if partial.data.last() == Some(&0xF7) {
+                    return Some(partial);
+                }
+                self.sysex_partial.insert(key, (Instant::now(), partial));
+                return None;
+            }
+            // Any other status byte cuts the dump short
+            self.push_log(partial);
+        }
+        if ev.is_incomplete_sysex() {
+            self.sysex_partial.insert(key, (Instant::now(), ev));
+            return None;
+        }
+        Some(ev)
+    }
+
+    fn flush_stale_sysex(&mut self) {
+        let timeout = Duration::from_millis(self.config.sysex_timeout_ms);
+        let stale: Vec<DeviceKey> = self
+            .sysex_partial
+            .iter()
+            .filter(|(_, (last, _))| last.elapsed() >= timeout)
+            .map(|(k, _)| k.clone())
+            .collect();
+        for key in stale {
+            if let Some((_, partial)) = self.sysex_partial.remove(&key) {
+                self.push_log(partial);
+            }
+        }
+    }
+
+    /// Arms learn mode, or clears the trigger if one is already set.
+    fn toggle_alert_learn(&mut self) {
+        if self.alert_learning {
+            self.alert_learning = false;
+            self.push_status("Alert learn cancelled".to_string());
+        } else if let Some(t) = self.alert_trigger.take() {
+            self.push_status(format!("Alert cleared ({})", t.describe()));
+        } else {
+            self.alert_learning = true;
+            self.push_status("Alert learn: send the message to watch for".to_string());
+        }
+    }
+
+    /// Starts MIDI learn, or cancels it when already learning.
+    fn toggle_midi_learn(&mut self) {
+        if self.midi_learn.take().is_some() {
+            self.push_status("MIDI learn cancelled".to_string());
+            return;
+        }
+        self.midi_learn = Some(MidiLearn::Key);
+        self.push_status(
+            "MIDI learn: press the key of the action to bind (Backspace clears all, Esc cancels)"
+                .to_string(),
+        );
+    }
+
+    /// Learn step one: the key picks the action, or clears or cancels.
+    fn midi_learn_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Esc => {
+                self.midi_learn = None;
+                self.push_status("MIDI learn cancelled".to_string());
+            }
+            KeyCode::Backspace => {
+                self.midi_learn = None;
+                let n = self.midi_bindings.len();
+                self.midi_bindings.clear();
+                self.push_status(format!("Cleared {n} MIDI binding(s)"));
+            }
+            _ => match self.keys.action(&key) {
+                Some(Action::MidiLearn) => {
+                    self.midi_learn = None;
+                    self.push_status("MIDI learn cancelled".to_string());
+                }
+                Some(action) => {
+                    self.midi_learn = Some(MidiLearn::Control(action));
+                    self.push_status(format!(
+                        "MIDI learn: move a knob or press a pad for {}",
+                        action.help()
+                    ));
+                }
+                None => self.push_status("That key has no action; try another".to_string()),
+            },
+        }
+    }
+
+    /// Learn step two, and afterwards firing bound actions, for each message
+    /// of a packed packet.
+    fn check_midi_binding(&mut self, packet: &[u8]) {
+        for message in midi::split_messages(packet) {
+            if let Some(MidiLearn::Control(action)) = self.midi_learn {
+                let control = match *message {
+                    [s, d1, v] if s & 0xF0 == 0x90 && v > 0 => (s, d1),
+                    [s, d1, _] if s & 0xF0 == 0xB0 => (s, d1),
+                    _ => continue,
+                };
+                self.midi_learn = None;
+                self.midi_bindings.insert(control, action);
+                let what = midi::decode_midi(&message, self.c_octave);
+                self.push_status(format!("Bound {what} to {}", action.help()));
+                return;
+            }
+            let Some(&action) = self
+                .controls
+                .pressed(&message)
+                .and_then(|c| self.midi_bindings.get(&c))
+            else {
+                continue;
+            };
+            // Headless mode has no dispatcher to drain the queue, and a control
+            // shouldn't act behind a prompt or dialog the keys are bound to
+            if !self.echo_stdout && !self.modal_open() {
+                self.pending_actions.push_back(action);
+            }
+        }
+    }
+
+    /// Spawns the shell command of each `macros` entry a message of the
+    /// packet presses, unless that macro ran less than its `min_interval_ms` ago.
+    fn run_macros(&mut self, packet: &[u8]) {
+        for message in midi::split_messages(packet) {
+            let Some((status, data1)) = self.controls.pressed(&message) else {
+                continue;
+            };
+            let now = Instant::now();
+            let due: Vec<usize> = (0..self.config.macros.len())
+                .filter(|i| self.config.macros[*i].matches(status, data1))
+                .filter(|i| {
+                    let interval = Duration::from_millis(self.config.macros[*i].min_interval_ms);
+                    self.macro_fired.get(i).is_none_or(|t| now - *t >= interval)
+                })
+                .collect();
+            for i in due {
+                self.macro_fired.insert(i, now);
+                let command = self.config.macros[i].command.clone();
+                let what = midi::decode_midi(&message, self.c_octave);
+                let msg = match spawn_macro(&command, self.status_sender()) {
+                    Ok(()) => format!("Macro ({what}): {command}"),
+                    Err(e) => format!("Macro ({what}) failed: {e:#}"),
+                };
+                self.push_status(msg);
+            }
+        }
+    }
+
+    fn cycle_alert_style(&mut self) {
+        self.alert_style = match self.alert_style {
+            AlertStyle::Bell => AlertStyle::Flash,
+            AlertStyle::Flash => AlertStyle::Bell,
+        };
+        self.push_status(format!("Alert style: {:?}", self.alert_style));
+    }
+
+    fn check_alert(&mut self, message: &[u8]) {
+        if self.alert_learning {
+            if let Some(t) = AlertTrigger::from_message(message) {
+                self.alert_learning = false;
+                self.alert_trigger = Some(t);
+                self.push_status(format!("Alert set on {}", t.describe()));
+            }
+            return;
+        }
+        if !self.alert_trigger.is_some_and(|t| t.matches(message)) {
+            return;
+        }
+        match self.alert_style {
+            // Headless stdout is the log, not a terminal
+            AlertStyle::Bell if !self.echo_stdout => {
+                let mut out = io::stdout();
+                let _ = out.write_all(b"\x07");
+                let _ = out.flush();
+            }
+            AlertStyle::Bell => {}
+            AlertStyle::Flash => {
+                self.flash_until = Some(Instant::now() + Duration::from_millis(300));
+            }
+        }
+    }
+
+    fn mark_for_route(&mut self) {
+        let Some(key) = self.selected_device().map(|d| d.key.clone()) else {
+            return;
+        };
+        self.push_status(format!(
+            "Marked {} for routing; select the other end and press t",
+            key.name
+        ));
+        self.route_mark = Some(key);
+    }
+
+    /// Starts measuring from the marked output to the selected input (either
+    /// way round), or stops a running measurement.
+    fn toggle_latency_probe(&mut self) {
+        if let Some(probe) = self.latency_probe.take() {
+            if let Some((note, _)) = probe.pending {
+                let _ = self.send_to(&probe.output, &[PROBE_STATUS & 0xEF, note, 0]);
+            }
+            self.push_status(format!("Latency probe stopped: {}", probe.report()));
+            return;
+        }
+        let (Some(mark), Some(dev)) = (self.route_mark.clone(), self.selected_device()) else {
+            self.push_status("Mark the output or input with m first".to_string());
+            return;
+        };
+        let (output, input) = match (&mark.kind, &dev.key.kind) {
+            (MidiKind::Output, MidiKind::Input) => (mark, dev.key.clone()),
+            (MidiKind::Input, MidiKind::Output) => (dev.key.clone(), mark),
+            _ => {
+                self.push_status("The probe needs one output and one input".to_string());
+                return;
+            }
+        };
+        if !self.out_conns.contains_key(&output) || !self.in_conns.contains_key(&input) {
+            self.push_status("Open both the output and the input first".to_string());
+            return;
+        }
+        self.push_status(format!(
+            "Latency probe: {} → {}, {} notes on ch16",
+            output.name,
+            input.name,
+            self.config.latency_samples.max(1)
+        ));
+        self.route_mark = None;
+        self.latency_probe = Some(LatencyProbe {
+            output,
+            input,
+            samples: Vec::new(),
+            lost: 0,
+            pending: None,
+            next_at: Instant::now(),
+            stamp_base: None,
+        });
+    }
+
+    /// Sends the next probe note when due, and gives up on a lost one.
+    fn step_latency_probe(&mut self) {
+        let goal = self.config.latency_samples.max(1);
+        let Some(probe) = &mut self.latency_probe else {
+            return;
+        };
+        let now = Instant::now();
+        if let Some((note, sent)) = probe.pending {
+            if sent.elapsed() < PROBE_TIMEOUT {
+                return;
+            }
+            probe.pending = None;
+            probe.lost += 1;
+            probe.next_at = now;
+            let off = [PROBE_STATUS & 0xEF, note, 0];
+            let output = probe.output.clone();
+            let _ = self.send_to(&output, &off);
+        }
+        let Some(probe) = &mut self.latency_probe else {
+            return;
+        };
+        if probe.done(goal) {
+            let msg = format!(
+                "Latency {} → {}: {}",
+                probe.output.name,
+                probe.input.name,
+                probe.report()
+            );
+            self.latency_probe = None;
+            self.push_status(msg);
+            return;
+        }
+        if now < probe.next_at {
+            return;
+        }
+        let note = ((probe.samples.len() + probe.lost) % 128) as u8;
+        let output = probe.output.clone();
+        probe.pending = Some((note, Instant::now()));
+        if let Err(e) = self.send_to(&output, &[PROBE_STATUS, note, 1]) {
+            self.latency_probe = None;
+            self.push_status(format!("Latency probe stopped: {e:#}"));
+        }
+    }
+
+    /// Times an incoming probe note by its arrival stamp.
+    fn check_latency_probe(&mut self, ev: &LoggedEvent) {
+        let Some(probe) = self
+            .latency_probe
+            .as_mut()
+            .filter(|p| ev.device.as_ref() == Some(&p.input))
+        else {
+            return;
+        };
+        probe.observe(ev);
+        let Some((note, sent)) = probe.pending else {
+            return;
+        };
+        if ev.data[..] != [PROBE_STATUS, note, 1] {
+            return;
+        }
+        probe.samples.push((sent, ev.stamp));
+        probe.pending = None;
+        probe.next_at = Instant::now() + PROBE_GAP;
+        let output = probe.output.clone();
+        let _ = self.send_to(&output, &[PROBE_STATUS & 0xEF, note, 0]);
+    }
+
+    /// Adds (or removes, if present) a route between the marked device and the
+    /// selected one; one end must be an input and the other an output.
+    fn toggle_route(&mut self) {
+        let (Some(mark), Some(dev)) = (self.route_mark.clone(), self.selected_device()) else {
+            self.push_status("Mark a device with m first".to_string());
+            return;
+        };
+        let (src, dst) = match (&mark.kind, &dev.key.kind) {
+            (MidiKind::Input, MidiKind::Output) => (mark, dev.key.clone()),
+            (MidiKind::Output, MidiKind::Input) => (dev.key.clone(), mark),
+            _ => {
+                self.push_status("A route needs one input and one output".to_string());
+                return;
+            }
+        };
+        let desc = format!("{} → {}", src.name, dst.name);
+        if let Some(pos) = self
+            .routes
+            .iter()
+            .position(|r| r.src == src && r.dst == dst)
+        {
+            self.routes.remove(pos);
+            self.push_status(format!("Route removed: {desc}"));
+        } else {
+            self.routes.push(Route::new(src, dst));
+            self.push_status(format!("Route added: {desc}"));
+        }
+        self.route_mark = None;
+        self.sync_thru();
+    }
+
+    fn sync_thru(&mut self) {
+        let mut table: HashMap<DeviceKey, Vec<(SharedOutput, Route, VelocityCurve)>> =
+            HashMap::new();
+        for route in &self.routes {
+            if let Some(out) = self.out_conns.get(&route.dst) {
+                table.entry(route.src.clone()).or_default().push((
+                    Arc::clone(out),
+                    route.clone(),
+                    self.velocity_curve,
+                ));
+            }
+        }
+        if let Ok(mut thru) = self.thru.lock() {
+            *thru = table;
+        }
+    }
+
+    fn route_active(&self, route: &Route) -> bool {
+        self.in_conns.contains_key(&route.src) && self.out_conns.contains_key(&route.dst)
+    }
+
+    /// Opens the route editor on the first route through the selected device.
+    fn start_route_edit(&mut self) {
+        if self.routes.is_empty() {
+            self.push_status("No routes to edit (m marks one end, t adds)".to_string());
+            return;
+        }
+        let selected = self.selected_device().map(|d| d.key.clone());
+        let n = self
+            .routes
+            .iter()
+            .position(|r| Some(&r.src) == selected.as_ref() || Some(&r.dst) == selected.as_ref())
+            .unwrap_or(0);
+        let route = &self.routes[n];
+        let channel = route
+            .channel_map
+            .map_or("-".to_string(), |ch| (ch + 1).to_string());
+        self.prompt = Some(Prompt {
+            kind: PromptKind::RouteEdit,
+            input: format!("{} {:+} {channel}", n + 1, route.transpose),
+            history_pos: None,
+        });
+    }
+
+    fn edit_route(&mut self, input: &str) {
+        let res = parse_route_edit(input).and_then(|(n, transpose, channel)| {
+            let route = self
+                .routes
+                .get_mut(n.wrapping_sub(1))
+                .with_context(|| format!("no route {n}"))?;
+            route.transpose = transpose;
+            route.channel_map = channel;
+            Ok(format!(
+                "Route {n}: {} → {}{}",
+                route.src.name,
+                route.dst.name,
+                route.describe_processing()
+            ))
+        });
+        match res {
+            Ok(msg) => {
+                self.sync_thru();
+                self.push_status(msg);
+            }
+            Err(e) => self.push_status(format!("Route not changed: {e:#}")),
+        }
+    }
+
+    /// Sends to an open output and records the message in the log.
+    fn send_to(&mut self, key: &DeviceKey, message: &[u8]) -> Result<()> {
+        let conn = self
+            .out_conns
+            .get(key)
+            .with_context(|| format!("output not open: {}", key.name))?;
+        conn.lock()
+            .map_err(|_| anyhow!("output connection poisoned: {}", key.name))?
+            .send(message)
+            .map_err(|e| anyhow!("Send to {} failed: {e}", key.name))?;
+        self.push_log(LoggedEvent {
+            ts: SystemTime::now(),
+            at: Instant::now(),
+            stamp: 0,
+            device: Some(key.clone()),
+            direction: LogDirection::Out,
+            kind: EventKind::Midi,
+            data: message.to_vec(),
+            param: None,
+            delta_us: None,
+        });
+        Ok(())
+    }
+
+    fn toggle_keyboard(&mut self) {
+        if self.kb_target.is_some() {
+            self.release_keyboard_notes(true);
+            self.kb_target = None;
+            self.push_status("Virtual keyboard off".to_string());
+            return;
+        }
+        match self.selected_device().map(|d| d.key.clone()) {
+            Some(key) if self.out_conns.contains_key(&key) => {
+                self.push_status(format!("Virtual keyboard → {} (Esc to leave)", key.name));
+                self.kb_target = Some(key);
+            }
+            _ => self.push_status("Select an open output to use the keyboard".to_string()),
+        }
+    }
+
+    fn keyboard_key(&mut self, c: char) {
+        let Some(target) = self.kb_target.clone() else {
+            return;
+        };
+        let Some(&(_, offset)) = KEYBOARD_MAP.iter().find(|(k, _)| *k == c) else {
+            return;
+        };
+        let Some(note) = self.kb_base.checked_add(offset).filter(|n| *n < 128) else {
+            return;
+        };
+        let release_at = Instant::now() + Duration::from_millis(self.config.keyboard_note_ms);
+        // Key auto-repeat only extends a sounding note
+        if self.kb_held.insert(note, release_at).is_none() {
+            let velocity = apply_velocity_curve(self.config.keyboard_velocity, self.velocity_curve);
+            let msg = [0x90 | self.out_channel, note, velocity];
+            if let Err(e) = self.send_to(&target, &msg) {
+                self.push_status(format!("Error: {e:#}"));
+            }
+        }
+    }
+
+    /// Sends Note Off for notes past their deadline (or all of them).
+    fn release_keyboard_notes(&mut self, all: bool) {
+        let Some(target) = self.kb_target.clone() else {
+            return;
+        };
+        let now = Instant::now();
+        let due: Vec<u8> = self
+            .kb_held
+            .iter()
+            .filter(|(_, t)| all || **t <= now)
+            .map(|(n, _)| *n)
+            .collect();
+        for note in due {
+            self.kb_held.remove(&note);
+            let _ = self.send_to(&target, &[0x80 | self.out_channel, note, 0]);
+        }
+    }
+
+    fn shift_keyboard_octave(&mut self, delta: i8) {
+        let base = (self.kb_base as i16 + delta as i16 * 12).clamp(0, 108) as u8;
+        if base != self.kb_base {
+            self.release_keyboard_notes(true);
+            self.kb_base = base;
+        }
+        self.push_status(format!(
+            "Keyboard base: {}",
+            midi::note_name(self.kb_base, self.c_octave)
+        ));
+    }
+
+    /// All Notes Off + All Sound Off on every channel of every open output.
+    /// The ~4k messages per output go out on a worker thread so a focus-loss
+    /// panic doesn't stall the UI; the result comes back as a status line.
+    fn panic_all(&mut self) {
+        let targets: Vec<(String, SharedOutput)> = self
+            .out_conns
+            .iter()
+            .map(|(key, conn)| (key.name.clone(), Arc::clone(conn)))
+            .collect();
+        // Everything is silenced, so the virtual keyboard has nothing to release
+        self.kb_held.clear();
+        if targets.is_empty() {
+            self.push_status("Panic: no open outputs".to_string());
+            return;
+        }
+        let status = self.status_sender();
+        thread::spawn(move || {
+            let mut sent = 0;
+            let mut lines = Vec::new();
+            for (name, conn) in &targets {
+                // Locked per message, so thru and playback keep their turns
+                let send = |msg: &[u8]| conn.lock().is_ok_and(|mut c| c.send(msg).is_ok());
+                let mut ok = true;
+                for ch in 0..16u8 {
+                    for cc in [123u8, 120] {
+                        ok &= send(&[0xB0 | ch, cc, 0]);
+                    }
+                    // Not every synth honours the channel-mode CCs
+                    for note in 0..128u8 {
+                        ok &= send(&[0x80 | ch, note, 0]);
+                    }
+                }
+                if ok {
+                    sent += 1;
+                } else {
+                    lines.push(format!("Panic failed on: {name}"));
+                }
+            }
+            lines.insert(
+                0,
+                format!("Panic sent to {sent} output(s): CC 123/120 and note-offs on 16 channels"),
+            );
+            for line in lines {
+                status.send(line);
+            }
+        });
+    }
+
+    fn open_prompt(&mut self, kind: PromptKind) {
+        let input = match kind {
+            PromptKind::BytePattern => self
+                .byte_filter
+                .as_ref()
+                .map(|p| p.to_string())
+                .unwrap_or_default(),
+            PromptKind::PlayFile
+            | PromptKind::VirtualInput
+            | PromptKind::VirtualOutput
+            | PromptKind::SendHex
+            | PromptKind::RouteEdit => String::new(),
+            PromptKind::Quantize => "capture.mid 16".to_string(),
+        };
+        self.prompt = Some(Prompt {
+            kind,
+            input,
+            history_pos: None,
+        });
+    }
+
+    fn submit_prompt(&mut self) {
+        let Some(prompt) = self.prompt.take() else {
+            return;
+        };
+        match prompt.kind {
+            PromptKind::BytePattern => self.set_byte_filter(prompt.input.trim()),
+            PromptKind::PlayFile => self.start_playback(prompt.input.trim()),
+            PromptKind::VirtualInput | PromptKind::VirtualOutput => {
+                let kind = if prompt.kind == PromptKind::VirtualInput {
+                    MidiKind::Input
+                } else {
+                    MidiKind::Output
+                };
+                let name = prompt.input.trim();
+                if name.is_empty() {
+                    return;
+                }
+                if let Err(e) = self.create_virtual_port(kind, name) {
+                    self.push_status(format!("Error: {e:#}"));
+                }
+            }
+            PromptKind::SendHex => self.send_hex(prompt.input.trim()),
+            PromptKind::RouteEdit => self.edit_route(&prompt.input),
+            PromptKind::Quantize => self.quantize_file(&prompt.input),
+        }
+    }
+
+    fn start_send_hex(&mut self) {
+        match self.selected_device() {
+            Some(dev) if self.out_conns.contains_key(&dev.key) => {
+                self.open_prompt(PromptKind::SendHex)
+            }
+            _ => self.push_status("Select an open output to send bytes to".to_string()),
+        }
+    }
+
+    fn send_hex(&mut self, input: &str) {
+        if input.is_empty() {
+            return;
+        }
+        let Some(key) = self.selected_device().map(|d| d.key.clone()) else {
+            return;
+        };
+        self.send_history.retain(|h| h != input);
+        self.send_history.insert(0, input.to_string());
+        self.send_history.truncate(SEND_HISTORY);
+        let res = parse_hex_bytes(input).and_then(|bytes| self.send_to(&key, &bytes));
+        if let Err(e) = res {
+            self.push_status(format!("Send failed: {e:#}"));
+        }
+    }
+
+    /// Quantizes the note starts of a .mid file (a `--record` capture, say)
+    /// and writes the event list to a text file, one `bar.beat.tick` per line.
+    fn quantize_file(&mut self, input: &str) {
+        let res = parse_quantize(input, self.config.clock_bpm).and_then(|(path, division, bpm)| {
+            let events = smf::read_smf(Path::new(&path))?;
+            let ticks = smf::ticks_at_bpm(&events, bpm);
+            let grid = smf::TICKS_PER_QUARTER as u64 * 4 / division;
+            let quantized = smf::quantize(&ticks, grid);
+            let moved = ticks
+                .iter()
+                .zip(&quantized)
+                .filter(|((a, _), (b, _))| a != b)
+                .count();
+            let tpq = smf::TICKS_PER_QUARTER as u64;
+            let mut text = format!("# {path} quantized to 1/{division} at {bpm:.0} BPM (4/4)\n");
+            for (tick, msg) in &quantized {
+                text.push_str(&format!(
+                    "{}.{}.{:03}  {}  {}\n",
+                    tick / (tpq * 4) + 1,
+                    tick / tpq % 4 + 1,
+                    tick % tpq,
+                    format_hex(msg),
+                    midi::decode_midi(msg, self.c_octave)
+                ));
+            }
+            let out = PathBuf::from(format!(
+                "midir-tui-quantized-{}.txt",
+                file_stamp(SystemTime::now())
+            ));
+            fs::write(&out, text).with_context(|| format!("write {}", out.display()))?;
+            Ok(format!(
+                "Quantized {path} to 1/{division} at {bpm:.0} BPM ({moved} of {} events moved); wrote {}",
+                quantized.len(),
+                out.display()
+            ))
+        });
+        match res {
+            Ok(msg) => self.push_status(msg),
+            Err(e) => self.push_status(format!("Quantize failed: {e:#}")),
+        }
+    }
+
+    /// Prompts for a file to play, or stops the playback in progress.
+    fn toggle_playback(&mut self) {
+        if let Some(pb) = &self.playback {
+            pb.cancel.store(true, Ordering::Relaxed);
+            return;
+        }
+        match self.selected_device() {
+            Some(dev) if self.out_conns.contains_key(&dev.key) => {
+                self.open_prompt(PromptKind::PlayFile)
+            }
+            _ => self.push_status("Select an open output to play a file to".to_string()),
+        }
+    }
+
+    fn start_playback(&mut self, path: &str) {
+        let Some(key) = self.selected_device().map(|d| d.key.clone()) else {
+            return;
+        };
+        let Some(conn) = self.out_conns.get(&key).cloned() else {
+            self.push_status(format!("Output not open: {}", key.name));
+            return;
+        };
+        let path = PathBuf::from(path);
+        let cancel = Arc::new(AtomicBool::new(false));
+        let status = self.status_sender();
+        let thread_cancel = Arc::clone(&cancel);
+        let handle = thread::spawn(move || {
+            if let Err(e) = play_smf(&path, &conn, &thread_cancel, &status) {
+                status.send(format!("Playback failed: {e:#}"));
+            }
+        });
+        self.playback = Some(Playback {
+            target: key,
+            cancel,
+            handle,
+        });
+    }
+
+    /// Plays a C major scale on the selected open output, on `out_channel`.
+    /// Shares the playback slot, so P stops it too.
+    fn start_test_sequence(&mut self) {
+        if self.playback.is_some() {
+            self.push_status("Playback in progress (P to stop)".to_string());
+            return;
+        }
+        let Some(key) = self.selected_device().map(|d| d.key.clone()) else {
+            return;
+        };
+        let Some(conn) = self.out_conns.get(&key).cloned() else {
+            self.push_status("Select an open output for the test sequence".to_string());
+            return;
+        };
+        let channel = self.out_channel;
+        let cancel = Arc::new(AtomicBool::new(false));
+        let status = self.status_sender();
+        let thread_cancel = Arc::clone(&cancel);
+        let name = key.label();
+        let handle = thread::spawn(move || {
+            status.send(format!("Test sequence → {name} ch {}", channel + 1));
+            let msg = match send_test_sequence(&conn, channel, &thread_cancel) {
+                Ok(true) => "Test sequence finished".to_string(),
+                Ok(false) => "Test sequence stopped".to_string(),
+                Err(e) => format!("Test sequence failed: {e:#}"),
+            };
+            status.send(msg);
+        });
+        self.playback = Some(Playback {
+            target: key,
+            cancel,
+            handle,
+        });
+    }
+
+    /// Forgets a playback whose thread has ended.
+    fn reap_playback(&mut self) {
+        if self
+            .playback
+            .as_ref()
+            .is_some_and(|pb| pb.handle.is_finished())
+        {
+            if let Some(pb) = self.playback.take() {
+                let _ = pb.handle.join();
+            }
+        }
+    }
+
+    fn stop_playback(&mut self) {
+        if let Some(pb) = self.playback.take() {
+            pb.cancel.store(true, Ordering::Relaxed);
+            let _ = pb.handle.join();
+        }
+    }
+
+    /// Starts MIDI clock on the selected open output, or stops the running one.
+    fn toggle_clock(&mut self) {
+        if self.clock_out.is_some() {
+            self.stop_clock();
+            return;
+        }
+        let Some(key) = self.selected_device().map(|d| d.key.clone()) else {
+            return;
+        };
+        let Some(conn) = self.out_conns.get(&key).cloned() else {
+            self.push_status("Select an open output to send clock to".to_string());
+            return;
+        };
+        let start_bpm = self
+            .config
+            .clock_bpm
+            .clamp(CLOCK_BPM_RANGE.0, CLOCK_BPM_RANGE.1);
+        let bpm = Arc::new(AtomicU64::new(start_bpm.to_bits()));
+        let cancel = Arc::new(AtomicBool::new(false));
+        let status = self.status_sender();
+        let (thread_bpm, thread_cancel) = (Arc::clone(&bpm), Arc::clone(&cancel));
+        let handle = thread::spawn(move || {
+            if let Err(e) = send_clock(&conn, &thread_bpm, &thread_cancel) {
+                status.send(format!("Clock out failed: {e:#}"));
+            }
+        });
+        self.push_status(format!("Clock out → {} at {start_bpm:.0} BPM", key.label()));
+        self.clock_out = Some(ClockOut {
+            target: key,
+            bpm,
+            cancel,
+            handle,
+        });
+    }
+
+    fn stop_clock(&mut self) {
+        if let Some(clock) = self.clock_out.take() {
+            clock.cancel.store(true, Ordering::Relaxed);
+            let _ = clock.handle.join();
+            self.push_status(format!("Clock out stopped: {}", clock.target.label()));
+        }
+    }
+
+    fn clock_bpm(&self) -> Option<f64> {
+        let clock = self.clock_out.as_ref()?;
+        Some(f64::from_bits(clock.bpm.load(Ordering::Relaxed)))
+    }
+
+    fn change_clock_bpm(&mut self, delta: f64) {
+        let Some(clock) = &self.clock_out else {
+            self.push_status("Clock out is not running".to_string());
+            return;
+        };
+        let bpm = (f64::from_bits(clock.bpm.load(Ordering::Relaxed)) + delta)
+            .clamp(CLOCK_BPM_RANGE.0, CLOCK_BPM_RANGE.1);
+        clock.bpm.store(bpm.to_bits(), Ordering::Relaxed);
+        self.push_status(format!("Clock out: {bpm:.0} BPM"));
+    }
+
+    fn set_byte_filter(&mut self, input: &str) {
+        if input.is_empty() {
+            self.byte_filter = None;
+            self.push_status("Byte pattern filter cleared".to_string());
+            return;
+        }
+        match BytePattern::parse(input) {
+            Ok(p) => {
+                self.push_status(format!("Byte pattern filter: {p}"));
+                self.byte_filter = Some(p);
+            }
+            Err(e) => self.push_status(format!("Invalid byte pattern: {e:#}")),
+        }
+    }
+
+    /// Decoded text for a MIDI entry, preferring the device's own note/CC
+    /// names from config over the standard ones.
+    fn describe(&self, ev: &LoggedEvent) -> String {
+        if let Some(ParamEvent::Value(change)) = ev.param {
+            if !self.config.show_param_ccs {
+                return change.to_string();
+            }
+            let raw = midi::decode_midi(&ev.data, self.c_octave);
+            return format!("{change}  ← {raw}");
+        }
+        let names = ev
+            .device
+            .as_ref()
+            .and_then(|d| self.config.device_names.get(&d.name));
+        match names {
+            Some(names) => midi::decode_midi_named(&ev.data, Some(names), self.c_octave),
+            None => midi::decode_midi(&ev.data, self.c_octave),
+        }
+    }
+
+    fn log_line(&self, ev: &LoggedEvent) -> String {
+        if ev.kind != EventKind::Midi || self.show_raw {
+            return ev.display();
+        }
+        self.log_format.render(|field| match field {
+            Field::Ts => format_log_time(ev.ts),
+            Field::UnixMs => ev
+                .ts
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |d| d.as_millis())
+                .to_string(),
+            Field::Dir => ev.direction_tag().to_string(),
+            Field::Dev => ev
+                .device
+                .as_ref()
+                .map(|d| d.name.clone())
+                .unwrap_or_default(),
+            Field::Type => midi::message_type(&ev.data).to_string(),
+            Field::Ch => ev
+                .channel()
+                .map(|c| (c + 1).to_string())
+                .unwrap_or_default(),
+            Field::Data => format_hex(&ev.data),
+            Field::Len => ev.data.len().to_string(),
+            Field::Decoded => self.describe(ev),
+        })
+    }
+
+    /// A log line for stdout or a file, with the time in front unless the
+    /// log format already places it.
+    fn timed_log_line(&self, ev: &LoggedEvent) -> String {
+        let line = self.log_line(ev);
+        let placed =
+            ev.kind == EventKind::Midi && !self.show_raw && self.log_format.uses(Field::Ts);
+        if placed {
+            line
+        } else {
+            format!("{}  {line}", format_log_time(ev.ts))
+        }
+    }
+
+    fn toggle_raw(&mut self) {
+        self.show_raw = !self.show_raw;
+        let mode = if self.show_raw { "raw hex" } else { "decoded" };
+        self.push_status(format!("Log shows {mode} messages"));
+    }
+
+    /// Opens the hex dump of the SysEx at the highlighted log row (the newest
+    /// row while following).
+    fn open_sysex_view(&mut self) {
+        let clusters = self.recent_clusters(self.log_scroll + 1);
+        let sysex = clusters
+            .get(self.log_scroll)
+            .and_then(|c| c.iter().find(|e| e.data.first() == Some(&0xF0)))
+            .map(|e| e.data.clone());
+        match sysex {
+            Some(data) => self.sysex_view = Some((data, 0)),
+            None => self.push_status("Highlighted log entry is not a SysEx message".to_string()),
+        }
+    }
+
+    /// Hides the controller of the CC at the highlighted log row (the newest
+    /// row while following).
+    fn mute_highlighted_cc(&mut self) {
+        let clusters = self.recent_clusters(self.log_scroll + 1);
+        let cc = clusters.get(self.log_scroll).and_then(|c| {
+            c.iter().find_map(|e| match e.data[..] {
+                [status, cc, ..] if status & 0xF0 == 0xB0 => Some(cc),
+                _ => None,
+            })
+        });
+        let Some(cc) = cc else {
+            self.push_status("Highlighted log entry is not a CC message".to_string());
+            return;
+        };
+        self.muted_ccs.insert(cc);
+        self.log_scroll = 0;
+        self.push_status(format!(
+            "Muted CC {cc} in the log (U unmutes all; still recorded)"
+        ));
+    }
+
+    /// Copies the highlighted log row's bytes as hex (one line per message
+    /// of a grouped row); without a clipboard they go to a temp file.
+    fn copy_highlighted_hex(&mut self) {
+        let clusters = self.recent_clusters(self.log_scroll + 1);
+        let lines: Vec<String> = clusters
+            .get(self.log_scroll)
+            .into_iter()
+            .flatten()
+            .rev()
+            .filter(|e| e.kind == EventKind::Midi)
+            .map(|e| format_hex(&e.data))
+            .collect();
+        if lines.is_empty() {
+            self.push_status("Highlighted log entry has no MIDI bytes".to_string());
+            return;
+        }
+        let text = lines.join("\n");
+        match copy_to_clipboard(&text) {
+            Ok(tool) => self.push_status(format!("Copied {text} ({tool})")),
+            Err(e) => {
+                let path = std::env::temp_dir().join(format!(
+                    "midir-tui-copy-{}.txt",
+                    file_stamp(SystemTime::now())
+                ));
+                match fs::write(&path, format!("{text}\n")) {
+                    Ok(()) => self.push_status(format!(
+                        "No clipboard ({e:#}); wrote the hex to {}",
+                        path.display()
+                    )),
+                    Err(io) => self.push_status(format!("Copy failed: {e:#}; {io}")),
+                }
+            }
+        }
+    }
+
+    fn unmute_ccs(&mut self) {
+        if self.muted_ccs.is_empty() {
+            self.push_status("No CCs are muted".to_string());
+            return;
+        }
+        self.muted_ccs.clear();
+        self.push_status("All CCs shown again".to_string());
+    }
+
+    /// Rows of the help overlay: every bound action, then the fixed keys.
+    fn help_lines(&self) -> Vec<(String, &'static str)> {
+        let mut rows: Vec<(String, &'static str)> = Action::ALL
+            .iter()
+            .filter(|a| !self.keys.label(**a).is_empty())
+            .map(|a| (self.keys.label(*a).to_string(), a.help()))
+            .collect();
+        rows.push(("0-9".to_string(), "channel-filter (0 = ch 10, 10-16 typed)"));
+        rows.push(("Ctrl+C".to_string(), "quit at once"));
+        rows.push(("mouse".to_string(), "click select/open, wheel scroll"));
+        rows
+    }
+
+    fn toggle_split_columns(&mut self) {
+        self.split_columns = !self.split_columns;
+        let state = if self.split_columns { "on" } else { "off" };
+        self.push_status(format!("Split hex/decoded columns for inputs: {state}"));
+    }
+
+    /// Latest-first display lines for the log pane, after filtering and
+    /// (when enabled) grouping of simultaneous messages, with their style.
+    fn recent_lines(&self, max: usize) -> Vec<(String, Style)> {
+        self.recent_clusters(max)
+            .iter()
+            .map(|c| {
+                let line = format!("{}{}", self.timing_columns(c), self.cluster_line(c));
+                (line, self.cluster_style(c))
+            })
+            .collect()
+    }
+
+    /// Colors a row by its source device once several inputs are open.
+    fn cluster_style(&self, cluster: &[&LoggedEvent]) -> Style {
+        match cluster.first() {
+            Some(LoggedEvent {
+                kind: EventKind::Midi,
+                direction: LogDirection::In,
+                device: Some(key),
+                ..
+            }) if self.in_conns.len() > 1 => Style::default().fg(device_color(&self.theme, key)),
+            _ => Style::default(),
+        }
+    }
+
+    /// "■ name" per open input in its log color, when colors are in use.
+    fn device_legend(&self) -> Vec<Span<'static>> {
+        if self.in_conns.len() < 2 {
+            return Vec::new();
+        }
+        let mut keys: Vec<&DeviceKey> = self.in_conns.keys().collect();
+        keys.sort_by_key(|k| k.label());
+        keys.into_iter()
+            .map(|k| {
+                Span::styled(
+                    format!("■ {} ", k.label()),
+                    Style::default().fg(device_color(&self.theme, k)),
+                )
+            })
+            .collect()
+    }
+
+    /// "stamp ms, +delta ms" columns for a row when timing display is on;
+    /// blank padding for rows without a stamp so the columns stay aligned.
+    fn timing_columns(&self, cluster: &[&LoggedEvent]) -> String {
+        if !self.show_timing {
+            return String::new();
+        }
+        // Rows are latest-first; time the row by its first arrival
+        let Some(ev) = cluster.last() else {
+            return String::new();
+        };
+        if ev.kind != EventKind::Midi || ev.direction != LogDirection::In {
+            return format!("{:24}", "");
+        }
+        let delta = match ev.delta_us {
+            Some(d) => format!("+{:.3}", d as f64 / 1000.0),
+            None => "-".to_string(),
+        };
+        format!("{:>11.3} {:>10}  ", ev.stamp as f64 / 1000.0, delta)
+    }
+
+    fn toggle_timing(&mut self) {
+        self.show_timing = !self.show_timing;
+        let state = if self.show_timing { "on" } else { "off" };
+        self.push_status(format!("Timing columns (stamp ms, delta ms): {state}"));
+    }
+
+    /// Latest-first log rows; each row is one entry, or several simultaneous
+    /// ones (latest first) when grouping is on.
+    fn recent_clusters(&self, max: usize) -> Vec<Vec<&LoggedEvent>> {
+        let mut visible = self.log.iter().rev().filter(|e| self.log_visible(e));
+        if !self.group_simultaneous {
+            return visible.take(max).map(|ev| vec![ev]).collect();
+        }
+        let window = self.config.group_window_us;
+        let mut out = Vec::new();
+        let mut cluster: Vec<&LoggedEvent> = Vec::new();
+        while out.len() < max {
+            let next = visible.next();
+            let joins = match (cluster.first(), next) {
+                (Some(first), Some(ev)) => {
+                    ev.kind == EventKind::Midi
+                        && first.kind == EventKind::Midi
+                        && ev.device == first.device
+                        && first.stamp.abs_diff(ev.stamp) <= window
+                }
+                _ => false,
+            };
+            if joins {
+                cluster.extend(next);
+                continue;
+            }
+            if !cluster.is_empty() {
+                out.push(std::mem::take(&mut cluster));
+            }
+            match next {
+                Some(ev) => cluster.push(ev),
+                None => break,
+            }
+        }
+        out
+    }
+
+    fn cluster_line(&self, cluster: &[&LoggedEvent]) -> String {
+        let [ev] = cluster else {
+            // Collected latest-first; list them in arrival order
+            let parts: Vec<String> = cluster
+                .iter()
+                .rev()
+                .map(|e| {
+                    if self.show_raw {
+                        format!("{:02X?}", e.data)
+                    } else {
+                        self.describe(e)
+                    }
+                })
+                .collect();
+            let name = cluster[0]
+                .device
+                .as_ref()
+                .map(|d| d.name.as_str())
+                .unwrap_or("");
+            return format!(
+                "IN  [{}]  ({} msgs)  [{}]",
+                parts.join(", "),
+                cluster.len(),
+                name
+            );
+        };
+        self.log_line(ev)
+    }
+
+    /// (raw hex, decoded text) for the split view of one log row.
+    fn cluster_columns(&self, cluster: &[&LoggedEvent]) -> (String, String) {
+        let Some(first) = cluster.first() else {
+            return (String::new(), String::new());
+        };
+        let timing = self.timing_columns(cluster);
+        if let EventKind::Status(msg) = &first.kind {
+            return (format!("{timing}{}", first.direction_tag()), msg.clone());
+        }
+        // Collected latest-first; list them in arrival order
+        let raw: Vec<String> = cluster
+            .iter()
+            .rev()
+            .map(|e| format!("{:02X?}", e.data))
+            .collect();
+        let decoded: Vec<String> = cluster.iter().rev().map(|e| self.describe(e)).collect();
+        let name = first.device.as_ref().map(|d| d.name.as_str()).unwrap_or("");
+        (
+            format!("{timing}{}  {}", first.direction_tag(), raw.join(" ")),
+            format!("{}  [{}]", decoded.join(", "), name),
+        )
+    }
+
+    fn log_title(&self) -> String {
+        let mut title = " Log ".to_string();
+        if let Some(ch) = self.channel_filter {
+            title.push_str(&format!("[ch {}] ", ch + 1));
+        }
+        if let Some(p) = &self.byte_filter {
+            title.push_str(&format!("[pattern {p}] "));
+        }
+        if !self.hidden_types.is_empty() {
+            title.push_str(&format!("[hidden: {}] ", self.hidden_types));
+        }
+        if !self.muted_ccs.is_empty() {
+            let ccs: Vec<String> = self.muted_ccs.iter().map(u8::to_string).collect();
+            title.push_str(&format!("[muted CC {}] ", ccs.join(",")));
+        }
+        let dropped = self.dropped.load(Ordering::Relaxed);
+        if dropped > 0 {
+            title.push_str(&format!("[dropped {dropped}] "));
+        }
+        if self.log_paused {
+            title.push_str("[PAUSED; Space to resume] ");
+        } else if self.log_scroll > 0 {
+            title.push_str(&format!(
+                "[scrolled up {}; End to follow] ",
+                self.log_scroll
+            ));
+        } else {
+            title.push_str("[following] ");
+        }
+        title
+    }
+
+    /// Freezes the log view in place while logging goes on; resuming jumps
+    /// back to the newest entry.
+    fn toggle_log_pause(&mut self) {
+        self.log_paused = !self.log_paused;
+        if !self.log_paused {
+            self.log_scroll = 0;
+        }
+    }
+
+    fn view_state(&self) -> ViewState {
+        ViewState {
+            show_timing: self.show_timing,
+            hidden_types: self.hidden_types,
+            channel_filter: self.channel_filter,
+            byte_filter: self.byte_filter.as_ref().map(|p| p.to_string()),
+            log_scroll: self.log_scroll,
+        }
+    }
+
+    /// Stores the log view of the previously selected device and restores
+    /// the newly selected one's, if it has been viewed before.
+    fn sync_view_state(&mut self) {
+        let current = self.selected_device().map(|d| d.key.clone());
+        if current == self.view_device {
+            return;
+        }
+        if let Some(old) = self.view_device.take() {
+            let view = self.view_state();
+            self.view_states.insert(old, view);
+        }
+        if let Some(view) = current
+            .as_ref()
+            .and_then(|k| self.view_states.get(k))
+            .cloned()
+        {
+            self.show_timing = view.show_timing;
+            self.hidden_types = view.hidden_types;
+            self.channel_filter = view.channel_filter.filter(|ch| *ch < 16);
+            self.byte_filter = view
+                .byte_filter
+                .as_deref()
+                .and_then(|p| BytePattern::parse(p).ok());
+            self.log_scroll = view.log_scroll;
+            // The log may have moved on since; keep the offset in range
+            self.scroll_log(0);
+        }
+        self.view_device = current;
+    }
+
+    /// Scrolls the log view; positive `delta` moves toward older entries.
+    fn scroll_log(&mut self, delta: isize) {
+        let total = self.recent_clusters(usize::MAX).len();
+        let scroll = self.log_scroll as isize + delta;
+        self.log_scroll = scroll.clamp(0, total.saturating_sub(1) as isize) as usize;
+    }
 
-        let ports = inp.ports();
-        let port = ports
-            .get(dev.index)
-            .context("input port index out of range")?;
-        let port_name = inp
-            .port_name(port)
-            .unwrap_or_else(|_| format!("Input #{}", dev.index));
+    /// 1-9 → channels 1-9 and 0 → channel 10, while `1` quickly followed by
+    /// 0-6 picks channels 10-16.
+    fn channel_digit(&mut self, n: u8) {
+        let after_one = self
+            .channel_digit_at
+            .take()
+            .is_some_and(|t| t.elapsed() < CHANNEL_DIGIT_WINDOW);
+        let channel = match n {
+            0..=6 if after_one => 9 + n,
+            0 => 9,
+            n => n - 1,
+        };
+        if n == 1 && !after_one {
+            self.channel_digit_at = Some(Instant::now());
+        }
+        self.set_channel_filter(Some(channel));
+    }
 
-        let name_for_log = dev.key.name.clone();
-        let tx = self.tx.clone();
-        let conn = inp
-            .connect(
-                port,
-                "midir-tui-in",
-                move |_stamp, message, _| {
-                    let s = format!(
-                        "IN  {:02X?}  (len {})  [{}]",
-                        message,
-                        message.len(),
-                        name_for_log
-                    );
-                    let _ = tx.send(s);
-                },
-                (),
-            )
-            .map_err(|e| anyhow!("Failed to open input: {port_name}: {e}"))?;
+    fn set_channel_filter(&mut self, channel: Option<u8>) {
+        self.channel_filter = channel;
+        self.log_scroll = 0;
+        match channel {
+            Some(ch) => self.push_status(format!("Showing channel {} only", ch + 1)),
+            None => self.push_status("Channel filter cleared".to_string()),
+        }
+    }
 
-        self.in_conns.insert(dev.key.clone(), conn);
-        self.push_status(format!("Opened input: {}", port_name));
-        Ok(())
+    fn toggle_hidden_type(&mut self, t: MsgTypes) {
+        self.hidden_types.toggle(t);
+        self.log_scroll = 0;
+        let state = if self.hidden_types.contains(t) {
+            "hidden"
+        } else {
+            "shown"
+        };
+        self.push_status(format!("Log: {} messages {state}", t.name()));
     }
 
-    fn open_output(&mut self, dev: &DeviceItem) -> Result<()> {
-        let out = MidiOutput::new("midir-tui-output").context("create MidiOutput failed")?;
-        let ports = out.ports();
-        let port = ports
-            .get(dev.index)
-            .context("output port index out of range")?;
-        let port_name = out
-            .port_name(port)
-            .unwrap_or_else(|_| format!("Output #{}", dev.index));
-
-        let conn = out
-            .connect(port, "midir-tui-out")
-            .map_err(|e| anyhow!("Failed to open output: {port_name}: {e}"))?;
-
-        self.out_conns.insert(dev.key.clone(), conn);
-        self.push_status(format!("Opened output: {}", port_name));
-        Ok(())
+    fn toggle_grouping(&mut self) {
+        self.group_simultaneous = !self.group_simultaneous;
+        let state = if self.group_simultaneous { "on" } else { "off" };
+        self.push_status(format!("Group simultaneous messages: {state}"));
     }
 
-    fn push_status(&mut self, msg: String) {
-        if self.log.len() == self.log.capacity() {
-            self.log.pop_front();
+    /// Whether a log entry passes the active display filters.
+    fn log_visible(&self, ev: &LoggedEvent) -> bool {
+        if ev.kind != EventKind::Midi {
+            return true;
+        }
+        if self.channel_filter.is_some() && ev.channel() != self.channel_filter {
+            return false;
+        }
+        if MsgTypes::of(&ev.data).is_some_and(|t| self.hidden_types.contains(t)) {
+            return false;
+        }
+        if let [status, cc, ..] = ev.data[..] {
+            if status & 0xF0 == 0xB0 && self.muted_ccs.contains(&cc) {
+                return false;
+            }
+        }
+        if matches!(ev.param, Some(ParamEvent::Select | ParamEvent::Held))
+            && !self.config.show_param_ccs
+        {
+            return false;
         }
-        self.log.push_back(format!("· {}", msg));
+        self.byte_filter
+            .as_ref()
+            .is_none_or(|p| p.matches(&ev.data))
     }
 
-    fn drain_rx(&mut self) {
-        while let Ok(s) = self.rx.try_recv() {
-            if self.log.len() == self.log.capacity() {
-                self.log.pop_front();
+    fn toggle_note_trail(&mut self) {
+        self.show_note_trail = !self.show_note_trail;
+        let state = if self.show_note_trail { "on" } else { "off" };
+        self.push_status(format!("Note trail: {state}"));
+    }
+
+    fn toggle_mpe_view(&mut self) {
+        self.mpe_view = !self.mpe_view;
+        let state = if self.mpe_view { "on" } else { "off" };
+        self.push_status(format!("MPE view: {state}"));
+    }
+
+    fn change_out_channel(&mut self, delta: i8) {
+        self.out_channel = (self.out_channel as i8 + delta).rem_euclid(16) as u8;
+        self.push_status(format!("Output channel: {}", self.out_channel + 1));
+    }
+
+    fn flashing(&self) -> bool {
+        self.flash_until.is_some_and(|t| Instant::now() < t)
+    }
+
+    /// How long to wait for terminal input before the next frame: the fast
+    /// tick while anything is happening, the slow one once idle.
+    fn tick(&self) -> Duration {
+        let min = self.config.tick_min_ms.max(10);
+        let max = self.config.tick_max_ms.max(min);
+        let busy = self.last_activity.elapsed() < IDLE_AFTER
+            || self.flashing()
+            || self.kb_target.is_some()
+            || self.playback.is_some()
+            || self.clock_out.is_some();
+        Duration::from_millis(if busy { min } else { max })
+    }
+
+    fn reveal_config_dir(&mut self) {
+        let Some(path) = self.persist_path.clone() else {
+            self.push_status("No config directory available on this platform".to_string());
+            return;
+        };
+        self.push_status(format!("State file: {}", path.display()));
+        if let Some(config_path) = self.config_path.clone() {
+            self.push_status(format!("Config file: {}", config_path.display()));
+        }
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+            match open_in_file_manager(dir) {
+                Ok(()) => self.push_status(format!("Opened folder: {}", dir.display())),
+                Err(e) => self.push_status(format!("Could not open folder: {e:#}")),
             }
-            self.log.push_back(s);
+        }
+    }
+
+    fn dump_state(&mut self) {
+        let now = SystemTime::now();
+        let dump = StateDump {
+            generated_at: format_utc(now),
+            devices: &self.devices,
+            selected: self.selected,
+            focus: self.focus,
+            open_inputs: self.in_conns.keys().collect(),
+            open_outputs: self.out_conns.keys().collect(),
+            virtual_ports: self.virtual_ports.iter().collect(),
+            routes: &self.routes,
+            linked_pairs: self.linked_pairs().collect(),
+            out_channel: self.out_channel,
+            alert_trigger: self.alert_trigger,
+            alert_style: self.alert_style,
+            recent: &self.recent,
+            favorites: &self.favorites,
+            hidden_devices: &self.hidden_devices,
+            message_counts: self
+                .device_stats
+                .iter()
+                .map(|(k, st)| (k, st.total))
+                .collect(),
+            hidden_types: self.hidden_types.to_string(),
+            muted_ccs: &self.muted_ccs,
+            midi_bindings: self
+                .midi_bindings
+                .iter()
+                .map(|(&(status, data1), a)| format!("{status:02X} {data1:02X} → {}", a.name()))
+                .collect(),
+            view_states: self.view_states.iter().collect(),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            log_len: self.log.len(),
+            pending_sysex: self.sysex_partial.keys().collect(),
+            config: &self.config,
+        };
+        let path = PathBuf::from(format!("midir-tui-state-{}.json", file_stamp(now)));
+        let res = serde_json::to_vec_pretty(&dump)
+            .context("serialize state failed")
+            .and_then(|bytes| {
+                fs::write(&path, bytes).with_context(|| format!("write {}", path.display()))
+            });
+        match res {
+            Ok(()) => self.push_status(format!("State dumped to {}", path.display())),
+            Err(e) => self.push_status(format!("State dump failed: {e:#}")),
+        }
+    }
+
+    fn export_devices(&mut self) {
+        let path = PathBuf::from(format!(
+            "midir-tui-devices-{}.json",
+            file_stamp(SystemTime::now())
+        ));
+        match export_devices_json(&path, &self.devices, &self.in_conns, &self.out_conns) {
+            Ok(()) => self.push_status(format!(
+                "Exported {} devices to {}",
+                self.devices.len(),
+                path.display()
+            )),
+            Err(e) => self.push_status(format!("Device export failed: {e:#}")),
+        }
+    }
+
+    fn save_log(&mut self) {
+        let path = PathBuf::from(format!(
+            "midir-tui-log-{}.txt",
+            file_stamp(SystemTime::now())
+        ));
+        let mut text = String::new();
+        for ev in &self.log {
+            text.push_str(&self.timed_log_line(ev));
+            text.push('\n');
+        }
+        match fs::write(&path, text) {
+            Ok(()) => self.push_status(format!(
+                "Saved {} log lines to {}",
+                self.log.len(),
+                path.display()
+            )),
+            Err(e) => self.push_status(format!("Save log failed: {}: {e}", path.display())),
         }
     }
 
@@ -251,6 +4535,48 @@ impl App {
             let p = Persisted {
                 last_device: key,
                 last_focus: Some(self.focus),
+                alert_trigger: self.alert_trigger,
+                alert_style: self.alert_style,
+                out_channel: self.out_channel,
+                recent_devices: self.recent.clone(),
+                favorites: self.favorites.clone(),
+                hidden_devices: self.hidden_devices.clone(),
+                sort_mode: self.sort_mode,
+                hidden_types: self.hidden_types,
+                channel_filter: self.channel_filter,
+                byte_filter: self.byte_filter.as_ref().map(|p| p.to_string()),
+                show_raw: self.show_raw,
+                split_columns: self.split_columns,
+                group_simultaneous: self.group_simultaneous,
+                show_timing: self.show_timing,
+                muted_ccs: self.muted_ccs.clone(),
+                midi_bindings: self
+                    .midi_bindings
+                    .iter()
+                    .map(|(&(status, data1), a)| MidiBinding {
+                        status,
+                        data1,
+                        action: a.name().to_string(),
+                    })
+                    .collect(),
+                view_states: {
+                    let mut views = self.view_states.clone();
+                    if let Some(key) = &self.view_device {
+                        views.insert(key.clone(), self.view_state());
+                    }
+                    views
+                },
+                open_devices: self
+                    .in_conns
+                    .keys()
+                    .chain(self.out_conns.keys())
+                    .filter(|k| !self.virtual_ports.contains(k))
+                    .cloned()
+                    .collect(),
+                linked_pairs: self
+                    .linked_pairs()
+                    .map(|(i, o)| (i.clone(), o.clone()))
+                    .collect(),
             };
             if let Some(parent) = path.parent() {
                 let _ = fs::create_dir_all(parent);
@@ -260,48 +4586,94 @@ impl App {
     }
 }
 
-fn collect_devices() -> Result<Vec<DeviceItem>> {
-    let inp = MidiInput::new("midir-tui").context("Failed to create MidiInput")?;
-    let out = MidiOutput::new("midir-tui").context("Failed to create MidiOutput")?;
+/// Device list report written by `export_devices_json`.
+#[derive(Serialize)]
+struct DeviceReport<'a> {
+    generated_at: String,
+    devices: Vec<DeviceReportEntry<'a>>,
+}
 
-    let mut items: Vec<DeviceItem> = Vec::new();
+#[derive(Serialize)]
+struct DeviceReportEntry<'a> {
+    #[serde(flatten)]
+    key: &'a DeviceKey,
+    index: usize,
+    open: bool,
+}
 
-    // Inputs
-    for (idx, port) in inp.ports().iter().enumerate() {
-        let name = inp
-            .port_name(port)
-            .unwrap_or_else(|_| format!("Input #{idx}"));
-        items.push(DeviceItem {
-            key: DeviceKey {
-                name,
-                kind: MidiKind::Input,
-            },
-            index: idx,
-        });
-    }
+fn export_devices_json(
+    path: &Path,
+    devices: &[DeviceItem],
+    in_conns: &HashMap<DeviceKey, MidiInputConnection<()>>,
+    out_conns: &HashMap<DeviceKey, SharedOutput>,
+) -> Result<()> {
+    let report = DeviceReport {
+        generated_at: format_utc(SystemTime::now()),
+        devices: devices
+            .iter()
+            .map(|d| DeviceReportEntry {
+                key: &d.key,
+                index: d.index,
+                open: in_conns.contains_key(&d.key) || out_conns.contains_key(&d.key),
+            })
+            .collect(),
+    };
+    let bytes = serde_json::to_vec_pretty(&report).context("serialize device list failed")?;
+    fs::write(path, bytes).with_context(|| format!("write {}", path.display()))
+}
 
-    // Outputs
-    for (idx, port) in out.ports().iter().enumerate() {
-        let name = out
-            .port_name(port)
-            .unwrap_or_else(|_| format!("Output #{idx}"));
-        items.push(DeviceItem {
-            key: DeviceKey {
-                name,
-                kind: MidiKind::Output,
-            },
-            index: idx,
-        });
+/// Whether an open error reads like the port being held by another app.
+/// Backends word this differently and midir passes most of it through, so
+/// this only looks for the usual phrases.
+fn looks_busy(error: &str) -> bool {
+    let error = error.to_lowercase();
+    ["busy", "in use", "allocated", "access denied", "exclusive"]
+        .iter()
+        .any(|phrase| error.contains(phrase))
+}
+
+/// How far a direction may exceed its log cap before its oldest entries are
+/// evicted in one pass.
+fn evict_slack(cap: usize) -> usize {
+    cap / 16
+}
+
+/// A port name without a trailing "in"/"out" word, so "Synth In" and
+/// "Synth Out" pair up; names without one are returned whole.
+fn base_name(name: &str) -> &str {
+    for suffix in [" input", " output", " in", " out"] {
+        let Some(cut) = name.len().checked_sub(suffix.len()) else {
+            continue;
+        };
+        if name.is_char_boundary(cut) && name[cut..].eq_ignore_ascii_case(suffix) {
+            return &name[..cut];
+        }
     }
+    name
+}
 
-    // Sort by kind then name
-    items.sort_by(|a, b| match (&a.key.kind, &b.key.kind) {
-        (MidiKind::Input, MidiKind::Output) => std::cmp::Ordering::Less,
-        (MidiKind::Output, MidiKind::Input) => std::cmp::Ordering::Greater,
-        _ => a.key.name.to_lowercase().cmp(&b.key.name.to_lowercase()),
+/// Stable re-sort putting inputs that have sent anything first, most recent
+/// on top; silent inputs and all outputs keep their order below them.
+fn sort_recently_active(items: &mut [DeviceItem], last_seen: &HashMap<DeviceKey, Instant>) {
+    items.sort_by_key(|d| match d.key.kind {
+        MidiKind::Input => match last_seen.get(&d.key) {
+            Some(t) => (0, Some(std::cmp::Reverse(*t))),
+            None => (1, None),
+        },
+        MidiKind::Output => (2, None),
     });
+}
 
-    Ok(items)
+/// Stable re-sort of a `collect_devices` list so favorites lead their kind
+/// while both groups keep the name order.
+fn sort_favorites_first(items: &mut [DeviceItem], favorites: &HashSet<DeviceKey>) {
+    items.sort_by_key(|d| {
+        let kind = match d.key.kind {
+            MidiKind::Input => 0,
+            MidiKind::Output => 1,
+        };
+        (kind, !favorites.contains(&d.key))
+    });
 }
 
 fn persist_file_path() -> Option<PathBuf> {
@@ -312,51 +4684,1028 @@ fn persist_file_path() -> Option<PathBuf> {
     })
 }
 
+fn config_file_path() -> Option<PathBuf> {
+    ProjectDirs::from("dev", "example", "midir-tui").map(|pd| {
+        let mut p = pd.config_dir().to_path_buf();
+        p.push("config.json");
+        p
+    })
+}
+
+fn load_config(path: &Option<PathBuf>) -> Option<Config> {
+    let p = path.as_ref()?;
+    let bytes = fs::read(p).ok()?;
+    serde_json::from_slice::<Config>(&bytes).ok()
+}
+
+/// Equal-tempered frequency of a (possibly fractional) MIDI note number.
+fn note_to_hz(note: f64, a4_hz: f64) -> f64 {
+    a4_hz * 2f64.powf((note - 69.0) / 12.0)
+}
+
+/// Classic hex dump rows: offset, 16 bytes in hex, printable ASCII gutter.
+fn hex_dump_lines(data: &[u8]) -> Vec<String> {
+    data.chunks(16)
+        .enumerate()
+        .map(|(row, bytes)| {
+            let hex: Vec<String> = bytes.iter().map(|b| format!("{b:02X}")).collect();
+            let ascii: String = bytes
+                .iter()
+                .map(|&b| {
+                    if b.is_ascii_graphic() || b == b' ' {
+                        b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            format!("{:04X}  {:<47}  |{}|", row * 16, hex.join(" "), ascii)
+        })
+        .collect()
+}
+
+/// Hard-wraps `text` into lines of at most `width` characters.
+fn wrap_chars(text: &str, width: usize) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    if chars.is_empty() {
+        return vec![String::new()];
+    }
+    chars
+        .chunks(width.max(1))
+        .map(|c| c.iter().collect())
+        .collect()
+}
+
+/// Lays the 128 notes out as whole octaves per row, each row labelled with
+/// its first note. Held notes are solid blocks brightening with velocity;
+/// idle keys are dots, dimmer for the black keys.
+fn held_note_rows(
+    held: &[u8; 128],
+    width: usize,
+    theme: &Theme,
+    c_octave: i8,
+) -> Vec<Line<'static>> {
+    const LABEL: usize = 5;
+    let per_row = (width.saturating_sub(LABEL) / 12).max(1) * 12;
+    held.chunks(per_row)
+        .enumerate()
+        .map(|(row, notes)| {
+            let first = (row * per_row) as u8;
+            let mut spans = vec![Span::styled(
+                format!(
+                    "{:<width$}",
+                    midi::note_name(first, c_octave),
+                    width = LABEL
+                ),
+                Style::default().fg(theme.muted),
+            )];
+            spans.extend(notes.iter().enumerate().map(|(i, &vel)| {
+                let note = first as usize + i;
+                if vel > 0 {
+                    let level = 95 + (vel as u16 * 160 / 127) as u8;
+                    Span::styled("█", Style::default().fg(Color::Rgb(0, level, 0)))
+                } else if matches!(note % 12, 1 | 3 | 6 | 8 | 10) {
+                    Span::styled("·", Style::default().fg(theme.muted))
+                } else {
+                    Span::styled("·", Style::default().fg(theme.key_idle))
+                }
+            }));
+            Line::from(spans)
+        })
+        .collect()
+}
+
+/// Sounding notes grouped by MPE zone, one row per note with its channel's
+/// bend (semitones, master bend included), pressure and CC 74 slide.
+fn mpe_rows(mpe: &MpeState, theme: &Theme, c_octave: i8) -> Vec<Line<'static>> {
+    let label = Style::default().fg(theme.label);
+    let (lower, upper) = mpe.layout();
+    let notes = mpe.active_notes();
+    let mut lines = Vec::new();
+    for (zone, members) in [(mpe::Zone::Lower, lower), (mpe::Zone::Upper, upper)] {
+        if members == 0 {
+            continue;
+        }
+        let master = zone.master();
+        let first = if master == 0 { 2 } else { 16 - members };
+        let last = if master == 0 { members + 1 } else { 15 };
+        lines.push(Line::from(Span::styled(
+            format!(
+                "MPE {} zone (master ch {}, members ch {first}-{last}):",
+                zone.name(),
+                master + 1
+            ),
+            label,
+        )));
+        let in_zone: Vec<_> = notes.iter().filter(|n| n.zone == Some(zone)).collect();
+        if in_zone.is_empty() {
+            lines.push(Line::from(Span::styled(
+                "  no notes",
+                Style::default().fg(theme.muted),
+            )));
+        }
+        for n in in_zone {
+            lines.push(Line::from(vec![
+                Span::styled(format!("  ch {:<2} ", n.channel + 1), label),
+                Span::styled(
+                    format!("{:<4}", midi::note_name(n.note, c_octave)),
+                    Style::default().fg(theme.accent),
+                ),
+                Span::raw(format!(
+                    " vel {:>3}  bend {:+6.2} st  pressure {:>3}  slide {:>3}",
+                    n.velocity, n.bend, n.pressure, n.timbre
+                )),
+            ]));
+        }
+    }
+    let outside: Vec<_> = notes.iter().filter(|n| n.zone.is_none()).collect();
+    if !outside.is_empty() {
+        let names: Vec<String> = outside
+            .iter()
+            .map(|n| {
+                format!(
+                    "{} (ch {})",
+                    midi::note_name(n.note, c_octave),
+                    n.channel + 1
+                )
+            })
+            .collect();
+        lines.push(Line::from(vec![
+            Span::styled("Outside zones: ", label),
+            Span::raw(names.join(" ")),
+        ]));
+    }
+    lines
+}
+
+/// One text bar per velocity bin, scaled to the fullest bin.
+fn velocity_rows(hist: &[u64; VELOCITY_BINS], width: usize, theme: &Theme) -> Vec<Line<'static>> {
+    // "113-127  " before the bar, the count after it
+    const LABEL: usize = 9;
+    let counts: Vec<String> = hist.iter().map(|n| n.to_string()).collect();
+    let count_width = counts.iter().map(String::len).max().unwrap_or(1);
+    let bar_width = width.saturating_sub(LABEL + count_width + 1).max(1);
+    let max = hist.iter().copied().max().unwrap_or(0).max(1);
+    hist.iter()
+        .zip(counts)
+        .enumerate()
+        .map(|(bin, (&n, count))| {
+            // Inverse of the binning in drain_rx
+            let lo = (bin * 127).div_ceil(VELOCITY_BINS) + 1;
+            let hi = ((bin + 1) * 127).div_ceil(VELOCITY_BINS);
+            let len = (n * bar_width as u64).div_ceil(max) as usize;
+            Line::from(vec![
+                Span::styled(
+                    format!("{:>width$}", format!("{lo}-{hi}  "), width = LABEL),
+                    Style::default().fg(theme.muted),
+                ),
+                Span::styled("█".repeat(len), Style::default().fg(theme.ok)),
+                Span::raw(format!(
+                    "{:w$} {count:>count_width$}",
+                    "",
+                    w = bar_width - len
+                )),
+            ])
+        })
+        .collect()
+}
+
+/// Renders the notes `device` held during the last `window` as text rows,
+/// highest pitch on top, oldest time on the left. Note On with velocity 0 is
+/// treated as Note Off. Only the window's end of the log is read; notes
+/// sounding since before it start at its left edge, the ones still held
+/// taken from `held`. Returns the pitch range shown with the rows.
+fn note_trail_rows(
+    log: &VecDeque<LoggedEvent>,
+    device: &DeviceKey,
+    held: Option<&[u8; 128]>,
+    now: SystemTime,
+    window: Duration,
+    width: usize,
+    height: usize,
+) -> Option<((u8, u8), Vec<String>)> {
+    let start = now.checked_sub(window)?;
+    // (pitch, on, off) segments; a note still held runs until now
+    let mut segments: Vec<(u8, SystemTime, SystemTime)> = Vec::new();
+    // Newest first: per (channel, note), the end of the segment being traced
+    // back (its Note Off, or now) and the earliest Note On since that end
+    let mut open: HashMap<(u8, u8), (SystemTime, Option<SystemTime>)> = HashMap::new();
+    for ev in log.iter().rev() {
+        if ev.ts < start {
+            break;
+        }
+        if ev.kind != EventKind::Midi || ev.device.as_ref() != Some(device) {
+            continue;
+        }
+        for message in midi::split_messages(&ev.data).iter().rev() {
+            match midi::note_event(message) {
+                Some(NoteEvent::On { channel, note, .. }) => {
+                    open.entry((channel, note)).or_insert((now, None)).1 = Some(ev.ts);
+                }
+                Some(NoteEvent::Off { channel, note, .. }) => {
+                    if let Some((off, Some(on))) = open.insert((channel, note), (ev.ts, None)) {
+                        segments.push((note, on, off));
+                    }
+                }
+                None => {}
+            }
+        }
+    }
+    segments.extend(
+        open.into_iter()
+            .map(|((_, note), (off, on))| (note, on.unwrap_or(start), off)),
+    );
+    for note in (0..128u8).filter(|n| held.is_some_and(|h| h[*n as usize] > 0)) {
+        if !segments.iter().any(|s| s.0 == note && s.2 == now) {
+            segments.push((note, start, now));
+        }
+    }
+    let lo = segments.iter().map(|s| s.0).min()?;
+    let hi = segments.iter().map(|s| s.0).max()?;
+
+    let width = width.max(1);
+    let rows = height.min((hi - lo) as usize + 1).max(1);
+    // Paragraph wrapping trims leading spaces, so pad with a visible filler
+    let mut grid = vec![vec!['·'; width]; rows];
+    let col_of = |t: SystemTime| {
+        let offset = t.duration_since(start).unwrap_or_default();
+        ((offset.as_secs_f64() / window.as_secs_f64()) * width as f64) as usize
+    };
+    for (note, on, off) in segments {
+        let row = if hi == lo {
+            0
+        } else {
+            (hi - note) as usize * (rows - 1) / (hi - lo) as usize
+        };
+        let (c0, c1) = (col_of(on.max(start)), col_of(off).min(width - 1));
+        for cell in grid[row].iter_mut().take(c1 + 1).skip(c0) {
+            *cell = '█';
+        }
+    }
+    Some(((lo, hi), grid.into_iter().map(String::from_iter).collect()))
+}
+
+/// Streams a MIDI file to `conn` in real time, reporting progress through the
+/// log channel. When `cancel` is set mid-file, All Notes Off goes out on every
+/// channel the file has used so far.
+fn play_smf(
+    path: &Path,
+    conn: &SharedOutput,
+    cancel: &AtomicBool,
+    status: &StatusSender,
+) -> Result<()> {
+    let events = smf::read_smf(path)?;
+    let name = path.display().to_string();
+    let status = |msg: String| status.send(msg);
+    let send = |msg: &[u8]| -> Result<()> {
+        conn.lock()
+            .map_err(|_| anyhow!("output connection poisoned"))?
+            .send(msg)
+            .map_err(|e| anyhow!("send failed: {e}"))
+    };
+    let total = events.last().map_or(0, |(t, _)| *t);
+    status(format!(
+        "Playing {name} ({} events, {:.1}s)",
+        events.len(),
+        total as f64 / 1e6
+    ));
+
+    let start = Instant::now();
+    let mut used_channels = [false; 16];
+    let mut next_quarter = 1;
+    for (at, msg) in &events {
+        let due = start + Duration::from_micros(*at);
+        loop {
+            if cancel.load(Ordering::Relaxed) {
+                for ch in (0..16u8).filter(|ch| used_channels[*ch as usize]) {
+                    let _ = send(&[0xB0 | ch, 123, 0]);
+                }
+                status(format!("Playback stopped: {name}"));
+                return Ok(());
+            }
+            let now = Instant::now();
+            if now >= due {
+                break;
+            }
+            // Short naps keep cancellation responsive
+            thread::sleep((due - now).min(Duration::from_millis(20)));
+        }
+        if let Some(&s) = msg.first() {
+            if (0x80..0xF0).contains(&s) {
+                used_channels[(s & 0x0F) as usize] = true;
+            }
+        }
+        send(msg)?;
+        while next_quarter < 4 && total > 0 && *at >= total * next_quarter / 4 {
+            status(format!("Playing {name}: {}%", next_quarter * 25));
+            next_quarter += 1;
+        }
+    }
+    status(format!("Playback finished: {name}"));
+    Ok(())
+}
+
+/// Ascending C major scale from C4, each note sounding `TEST_NOTE`. The
+/// lock is taken per message so panic and thru keep working meanwhile, and
+/// every Note On is followed by its Note Off even when cancelled. Returns
+/// false if cancelled.
+fn send_test_sequence(conn: &SharedOutput, channel: u8, cancel: &AtomicBool) -> Result<bool> {
+    const TEST_NOTE: Duration = Duration::from_millis(200);
+    let send = |msg: &[u8]| -> Result<()> {
+        conn.lock()
+            .map_err(|_| anyhow!("output connection poisoned"))?
+            .send(msg)
+            .map_err(|e| anyhow!("send failed: {e}"))
+    };
+    let ch = channel & 0x0F;
+    for note in [60u8, 62, 64, 65, 67, 69, 71, 72] {
+        if cancel.load(Ordering::Relaxed) {
+            return Ok(false);
+        }
+        send(&[0x90 | ch, note, 100])?;
+        let until = Instant::now() + TEST_NOTE;
+        while !cancel.load(Ordering::Relaxed) && Instant::now() < until {
+            thread::sleep(Duration::from_millis(10));
+        }
+        send(&[0x80 | ch, note, 0])?;
+    }
+    Ok(!cancel.load(Ordering::Relaxed))
+}
+
+/// Sends Start, then 24 clock pulses per quarter note at the tempo in `bpm`
+/// (f64 bits), then Stop once `cancel` is set. Pulses are scheduled against
+/// a running target instant so sleep overshoot doesn't accumulate as drift.
+fn send_clock(conn: &SharedOutput, bpm: &AtomicU64, cancel: &AtomicBool) -> Result<()> {
+    let send = |msg: &[u8]| -> Result<()> {
+        conn.lock()
+            .map_err(|_| anyhow!("output connection poisoned"))?
+            .send(msg)
+            .map_err(|e| anyhow!("send failed: {e}"))
+    };
+    send(&[0xFA])?;
+    let mut next = Instant::now();
+    while !cancel.load(Ordering::Relaxed) {
+        send(&[0xF8])?;
+        let pulse =
+            Duration::from_secs_f64(60.0 / (f64::from_bits(bpm.load(Ordering::Relaxed)) * 24.0));
+        next += pulse;
+        let now = Instant::now();
+        if next > now {
+            thread::sleep(next - now);
+        } else if now - next > pulse {
+            // Stalled for more than a pulse: resync rather than burst to catch up
+            next = now;
+        }
+    }
+    send(&[0xFC])
+}
+
+/// Civil UTC date/time (y, m, d, hh, mm, ss) for a wall-clock instant.
+fn utc_parts(t: SystemTime) -> (i64, u32, u32, u32, u32, u32) {
+    let secs = t
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let (days, rem) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+    // Howard Hinnant's civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = yoe + era * 400 + i64::from(m <= 2);
+    let (hh, mm, ss) = (rem / 3600, rem % 3600 / 60, rem % 60);
+    (y, m, d, hh as u32, mm as u32, ss as u32)
+}
+
+/// `20240101-120000` (UTC), for file names.
+fn file_stamp(t: SystemTime) -> String {
+    let (y, m, d, hh, mm, ss) = utc_parts(t);
+    format!("{y:04}{m:02}{d:02}-{hh:02}{mm:02}{ss:02}")
+}
+
+/// `2024-01-01T12:00:00Z`
+fn format_utc(t: SystemTime) -> String {
+    let (y, m, d, hh, mm, ss) = utc_parts(t);
+    format!("{y:04}-{m:02}-{d:02}T{hh:02}:{mm:02}:{ss:02}Z")
+}
+
+/// `2024-01-01 12:00:00.123` (UTC), for log lines.
+fn format_log_time(t: SystemTime) -> String {
+    let (y, m, d, hh, mm, ss) = utc_parts(t);
+    let ms = t
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.subsec_millis())
+        .unwrap_or(0);
+    format!("{y:04}-{m:02}-{d:02} {hh:02}:{mm:02}:{ss:02}.{ms:03}")
+}
+
+/// A device's log color: FNV-1a of its name and instance into the palette,
+/// so it stays the same across runs.
+fn device_color(theme: &Theme, key: &DeviceKey) -> Color {
+    let hash = key
+        .name
+        .bytes()
+        .chain(key.instance.to_le_bytes())
+        .fold(0x811c_9dc5u32, |h, b| {
+            (h ^ b as u32).wrapping_mul(0x0100_0193)
+        });
+    theme.devices[hash as usize % theme.devices.len()]
+}
+
+/// List row of a `--rtpmidi` target.
+#[cfg(feature = "rtpmidi")]
+fn rtp_key(target: &str) -> DeviceKey {
+    DeviceKey {
+        name: format!("{target} (rtp-midi)"),
+        kind: MidiKind::Output,
+        instance: 0,
+    }
+}
+
+/// `HH:MM:SS`; hours keep counting past 24.
+fn format_hms(d: Duration) -> String {
+    let secs = d.as_secs();
+    format!(
+        "{:02}:{:02}:{:02}",
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+/// Rect of the given percentage size, centered in `r`.
+fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
+    let vertical = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage((100 - percent_y) / 2),
+            Constraint::Percentage(percent_y),
+            Constraint::Percentage((100 - percent_y) / 2),
+        ])
+        .split(r);
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage((100 - percent_x) / 2),
+            Constraint::Percentage(percent_x),
+            Constraint::Percentage((100 - percent_x) / 2),
+        ])
+        .split(vertical[1])[1]
+}
+
+fn open_in_file_manager(dir: &Path) -> Result<()> {
+    let opener = if cfg!(target_os = "macos") {
+        "open"
+    } else if cfg!(windows) {
+        "explorer"
+    } else {
+        "xdg-open"
+    };
+    // Detach: the file manager outlives us and its output would garble the TUI.
+    Command::new(opener)
+        .arg(dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("{opener} failed"))?;
+    Ok(())
+}
+
+/// Runs a macro's command through the shell without waiting for it; a
+/// thread reaps it and reports a failing exit in the log.
+fn spawn_macro(command: &str, status: StatusSender) -> Result<()> {
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    // Its output would garble the TUI
+    let mut child = Command::new(shell)
+        .args([flag, command])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("{shell} failed to start"))?;
+    let command = command.to_string();
+    thread::spawn(move || match child.wait() {
+        Ok(exit) if !exit.success() => status.send(format!("Macro exited with {exit}: {command}")),
+        Ok(_) => {}
+        Err(e) => status.send(format!("Macro wait failed: {e}")),
+    });
+    Ok(())
+}
+
+/// `F0 7E 7F 06 01 F7`, the form `:` (send hex) accepts back.
+fn format_hex(data: &[u8]) -> String {
+    data.iter()
+        .map(|b| format!("{b:02X}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Puts `text` on the system clipboard through the platform's command line
+/// tool, returning the one that worked.
+#[cfg(feature = "clipboard")]
+fn copy_to_clipboard(text: &str) -> Result<&'static str> {
+    let tools: &[(&'static str, &[&str])] = if cfg!(target_os = "macos") {
+        &[("pbcopy", &[])]
+    } else if cfg!(windows) {
+        &[("clip", &[])]
+    } else {
+        &[
+            ("wl-copy", &[]),
+            ("xclip", &["-selection", "clipboard"]),
+            ("xsel", &["--clipboard", "--input"]),
+        ]
+    };
+    let mut last = anyhow!("no clipboard tool found");
+    for (tool, args) in tools {
+        let child = Command::new(tool)
+            .args(*args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(e) => {
+                last = anyhow!("{tool}: {e}");
+                continue;
+            }
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            let _ = stdin.write_all(text.as_bytes());
+        }
+        match child.wait() {
+            Ok(status) if status.success() => return Ok(tool),
+            Ok(status) => last = anyhow!("{tool} exited with {status}"),
+            Err(e) => last = anyhow!("{tool}: {e}"),
+        }
+    }
+    Err(last)
+}
+
+#[cfg(not(feature = "clipboard"))]
+fn copy_to_clipboard(_text: &str) -> Result<&'static str> {
+    Err(anyhow!("built without the `clipboard` feature"))
+}
+
 fn load_persisted(path: &Option<PathBuf>) -> Option<Persisted> {
     let p = path.as_ref()?;
     let bytes = fs::read(p).ok()?;
     serde_json::from_slice::<Persisted>(&bytes).ok()
 }
 
+/// Command-line options; with none given the interactive TUI starts.
+#[derive(Debug, Default)]
+struct CliArgs {
+    record: Option<String>,
+    duration: Option<Duration>,
+    out: Option<PathBuf>,
+    /// Ports to open at startup (`--open` inputs, `--open-output` outputs)
+    open: Vec<String>,
+    open_output: Vec<String>,
+    /// Start with the details/log pane focused
+    monitor: bool,
+    /// Print the ports and exit
+    list: bool,
+    /// No TUI: print decoded messages from the `--open` ports to stdout
+    headless: bool,
+    /// Loop messages through virtual ports and exit 0 (pass) or 1 (fail)
+    selftest: bool,
+    /// `host:port` to forward incoming MIDI to as OSC (`osc` feature)
+    osc_out: Option<String>,
+    /// Remote RTP-MIDI sessions to list as outputs (`rtpmidi` feature)
+    rtpmidi: Vec<String>,
+    /// `host:port` to serve incoming MIDI on as JSON lines
+    json_tcp: Option<String>,
+    /// Hidden: synthetic input at this many messages per second, with frame
+    /// times printed on exit
+    stress: Option<u32>,
+    /// Hidden: panic after the first frame, to check the terminal is restored
+    /// (`examples/crash-test.sh` compares the tty settings around it)
+    crash_test: bool,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<CliArgs> {
+    let mut cli = CliArgs::default();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().with_context(|| format!("{arg} needs a value"));
+        match arg.as_str() {
+            "--record" => cli.record = Some(value()?),
+            "--duration" => cli.duration = Some(parse_duration(&value()?)?),
+            "--out" => cli.out = Some(PathBuf::from(value()?)),
+            "--open" => cli.open.push(value()?),
+            "--open-output" => cli.open_output.push(value()?),
+            "--monitor" => cli.monitor = true,
+            "--list" => cli.list = true,
+            "--headless" => cli.headless = true,
+            "--selftest" => cli.selftest = true,
+            "--osc-out" if cfg!(feature = "osc") => cli.osc_out = Some(value()?),
+            "--osc-out" => return Err(anyhow!("--osc-out needs a build with the `osc` feature")),
+            "--json-tcp" => cli.json_tcp = Some(value()?),
+            "--crash-test" => cli.crash_test = true,
+            "--stress" => {
+                let rate = value()?;
+                cli.stress = Some(
+                    rate.parse()
+                        .with_context(|| format!("bad --stress rate: {rate}"))?,
+                );
+            }
+            "--rtpmidi" if cfg!(feature = "rtpmidi") => cli.rtpmidi.push(value()?),
+            "--rtpmidi" => {
+                return Err(anyhow!(
+                    "--rtpmidi needs a build with the `rtpmidi` feature"
+                ))
+            }
+            other => return Err(anyhow!("unknown argument: {other}")),
+        }
+    }
+    Ok(cli)
+}
+
+/// `500ms`, `30s`, `2m`, or a bare number of seconds.
+fn parse_duration(s: &str) -> Result<Duration> {
+    let s = s.trim();
+    let (num, unit) = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .map_or((s, ""), |i| s.split_at(i));
+    let n: f64 = num.parse().with_context(|| format!("bad duration: {s}"))?;
+    let secs = match unit {
+        "ms" => n / 1000.0,
+        "" | "s" => n,
+        "m" => n * 60.0,
+        "h" => n * 3600.0,
+        _ => return Err(anyhow!("bad duration unit: {s}")),
+    };
+    Duration::try_from_secs_f64(secs).map_err(|_| anyhow!("duration out of range: {s}"))
+}
+
+fn print_devices(devices: &[DeviceItem]) {
+    for d in devices {
+        let tag = match d.key.kind {
+            MidiKind::Input => "IN ",
+            MidiKind::Output => "OUT",
+        };
+        println!("{tag}  #{:<3} {}", d.index, d.key.label());
+    }
+}
+
+/// Resolves `--open`/`--open-output` names up front so a typo fails before
+/// the terminal is taken over.
+fn resolve_cli_devices(cli: &CliArgs) -> Result<Vec<DeviceKey>> {
+    if cli.open.is_empty() && cli.open_output.is_empty() {
+        return Ok(Vec::new());
+    }
+    let devices = collect_devices()?;
+    let wanted = cli
+        .open
+        .iter()
+        .map(|n| (MidiKind::Input, n))
+        .chain(cli.open_output.iter().map(|n| (MidiKind::Output, n)));
+    let mut keys = Vec::new();
+    for (kind, name) in wanted {
+        match match_device(&devices, &kind, name) {
+            Some(d) => keys.push(d.key.clone()),
+            None => {
+                let what = if kind == MidiKind::Input {
+                    "input"
+                } else {
+                    "output"
+                };
+                eprintln!("midir-tui: no {what} port matching {name:?}. Available ports:");
+                print_devices(&devices);
+                std::process::exit(1);
+            }
+        }
+    }
+    Ok(keys)
+}
+
+/// Headless `--record`: capture one input for a fixed time into an SMF file.
+fn run_record(port_name: &str, duration: Duration, out: &Path) -> Result<()> {
+    let devices = collect_devices()?;
+    let dev = match_device(&devices, &MidiKind::Input, port_name)
+        .with_context(|| format!("no input port matching {port_name:?}"))?;
+    let name = dev.key.label();
+    let (mut monitor, rx) = MidiMonitor::new("midir-tui-record");
+    monitor.open(dev)?;
+
+    let started = Instant::now();
+    let mut events = Vec::new();
+    while started.elapsed() < duration {
+        let left = duration.saturating_sub(started.elapsed());
+        if let Ok(ev) = rx.recv_timeout(left.min(Duration::from_secs(1))) {
+            events.push((ev.stamp, ev.data));
+            events.extend(rx.try_iter().map(|ev| (ev.stamp, ev.data)));
+        }
+        eprint!(
+            "\rRecording {name}: {:.0}/{:.0}s, {} messages",
+            started.elapsed().as_secs_f64().min(duration.as_secs_f64()),
+            duration.as_secs_f64(),
+            events.len()
+        );
+    }
+    eprintln!();
+
+    smf::write_smf(out, &events)?;
+    eprintln!("Wrote {} messages to {}", events.len(), out.display());
+    let dropped = monitor.dropped();
+    if dropped > 0 {
+        eprintln!("Dropped {dropped} messages that came in faster than they were written");
+    }
+    Ok(())
+}
+
 fn main() -> Result<()> {
+    let cli = parse_args(std::env::args().skip(1))?;
+    if let Some(port) = &cli.record {
+        let duration = cli.duration.unwrap_or(Duration::from_secs(30));
+        let out = cli
+            .out
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("capture.mid"));
+        return run_record(port, duration, &out);
+    }
+
+    if cli.selftest {
+        return run_selftest();
+    }
+    if cli.headless {
+        let startup = resolve_cli_devices(&cli)?;
+        return run_headless(&startup, &cli);
+    }
+    if cli.list {
+        print_devices(&collect_devices()?);
+        return Ok(());
+    }
+    let startup = resolve_cli_devices(&cli)?;
+
+    // Raw mode fails with an opaque OS error when piped or run under CI.
+    if !io::stdout().is_terminal() || !io::stdin().is_terminal() {
+        eprintln!("midir-tui: no interactive terminal detected (stdin/stdout is not a TTY).");
+        eprintln!("Run it from a terminal emulator to use the interactive UI, or use");
+        eprintln!("--headless --open <name> to print decoded MIDI to stdout.");
+        std::process::exit(2);
+    }
+
+    // Restore before the default hook prints, or the message lands on the
+    // alternate screen and vanishes
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        restore_terminal();
+        default_hook(info);
+    }));
+
     enable_raw_mode().context("enable_raw_mode failed")?;
+    // Restores on every way out of main from here, `?` included
+    let _restore = TerminalGuard;
     let mut stdout = io::stdout();
     execute!(stdout, EnterAlternateScreen).context("EnterAlternateScreen failed")?;
+    // Focus reporting is best-effort; not every terminal supports it
+    execute!(stdout, EnableFocusChange).ok();
+    execute!(stdout, EnableMouseCapture).ok();
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend).context("Create terminal failed")?;
     terminal.clear()?;
 
-    let res = run_app(&mut terminal);
+    let res = run_app(&mut terminal, &startup, &cli);
+    restore_terminal();
 
-    // Restore terminal
+    let frames = res?;
+    if cli.stress.is_some() {
+        println!("Frame times: {frames}");
+    }
+    Ok(())
+}
+
+/// Leaves raw mode and the alternate screen; safe to call more than once.
+fn restore_terminal() {
+    let mut stdout = io::stdout();
     disable_raw_mode().ok();
-    execute!(terminal.backend_mut(), LeaveAlternateScreen).ok();
-    terminal.show_cursor().ok();
+    execute!(stdout, DisableFocusChange).ok();
+    execute!(stdout, DisableMouseCapture).ok();
+    execute!(stdout, LeaveAlternateScreen).ok();
+    execute!(stdout, crossterm::cursor::Show).ok();
+}
+
+struct TerminalGuard;
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore_terminal();
+    }
+}
+
+/// `--headless`: the log pipeline without a terminal UI, until Ctrl-C.
+fn run_headless(startup: &[DeviceKey], cli: &CliArgs) -> Result<()> {
+    if startup.is_empty() {
+        return Err(anyhow!("--headless needs at least one --open <name>"));
+    }
+    let stop = Arc::new(AtomicBool::new(false));
+    #[cfg(unix)]
+    for signal in [signal_hook::consts::SIGINT, signal_hook::consts::SIGTERM] {
+        signal_hook::flag::register(signal, Arc::clone(&stop))
+            .context("install signal handler failed")?;
+    }
+
+    // The TUI's saved ports, routes and hidden devices don't apply here
+    let config = load_config(&config_file_path()).unwrap_or_default();
+    let mut app = App::with_state(config, Persisted::default(), collect_devices()?, false);
+    app.echo_stdout = true;
+    if let Some(target) = &cli.osc_out {
+        app.connect_osc(target)?;
+    }
+    if let Some(addr) = &cli.json_tcp {
+        app.listen_json(addr)?;
+    }
+    app.add_rtp_peers(&cli.rtpmidi);
+    app.open_startup_devices(startup);
+    while !stop.load(Ordering::Relaxed) {
+        app.drain_rx();
+        thread::sleep(Duration::from_millis(5));
+    }
+    app.drain_rx();
+    app.close_all();
+    Ok(())
+}
+
+/// Sent both ways by `--selftest`: channel messages and a short SysEx.
+const SELFTEST_SEQUENCE: [&[u8]; 4] = [
+    &[0x90, 60, 100],
+    &[0xB0, 7, 64],
+    &[0x80, 60, 0],
+    &[0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7],
+];
+
+/// `--selftest`: creates a virtual input and output, connects to each from
+/// the other side with `open_output`/`open_input`, and checks that
+/// `SELFTEST_SEQUENCE` arrives intact through the log pipeline both ways.
+fn run_selftest() -> Result<()> {
+    if !cfg!(unix) {
+        println!("selftest skipped: virtual ports are not supported on this platform");
+        return Ok(());
+    }
+    // Defaults only, so the user's thru, routes and filters can't skew it
+    let mut app = App::with_state(
+        Config::default(),
+        Persisted::default(),
+        collect_devices()?,
+        false,
+    );
+    let name = format!("midir-tui-selftest-{}", std::process::id());
+    let (in_name, out_name) = (format!("{name}-in"), format!("{name}-out"));
+    app.create_virtual_port(MidiKind::Input, &in_name)?;
+    app.create_virtual_port(MidiKind::Output, &out_name)?;
+    app.refresh_devices();
+
+    // Each virtual port as other clients see it, e.g. ALSA's
+    // "midir-tui-virtual:<name> 128:0"
+    let peer = |kind: MidiKind, port: &str| {
+        app.devices
+            .iter()
+            .find(|d| {
+                d.key.kind == kind
+                    && d.key.name.contains(port)
+                    && !app.virtual_ports.contains(&d.key)
+            })
+            .cloned()
+            .with_context(|| format!("virtual port {port} not visible to its peers"))
+    };
+    let to_virtual_in = peer(MidiKind::Output, &in_name)?;
+    let from_virtual_out = peer(MidiKind::Input, &out_name)?;
+    app.open_output(&to_virtual_in)?;
+    app.open_input(&from_virtual_out)?;
+
+    let virtual_key = |kind: MidiKind, port: &str| DeviceKey {
+        name: format!("{port} (virtual)"),
+        kind,
+        instance: 0,
+    };
+    // (label, sending output, receiving input)
+    let checks = [
+        (
+            "open_output → virtual input",
+            to_virtual_in.key,
+            virtual_key(MidiKind::Input, &in_name),
+        ),
+        (
+            "virtual output → open_input",
+            virtual_key(MidiKind::Output, &out_name),
+            from_virtual_out.key,
+        ),
+    ];
+    for (_, sender, _) in &checks {
+        for msg in SELFTEST_SEQUENCE {
+            app.send_to(sender, msg)?;
+        }
+    }
+
+    let received = |app: &App, key: &DeviceKey| -> Vec<Vec<u8>> {
+        app.log
+            .iter()
+            .filter(|e| e.kind == EventKind::Midi && e.direction == LogDirection::In)
+            .filter(|e| e.device.as_ref() == Some(key))
+            .map(|e| e.data.clone())
+            .collect()
+    };
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while Instant::now() < deadline {
+        app.drain_rx();
+        let complete = checks
+            .iter()
+            .all(|(_, _, to)| received(&app, to).len() >= SELFTEST_SEQUENCE.len());
+        if complete {
+            break;
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
 
-    res
+    let expected: Vec<Vec<u8>> = SELFTEST_SEQUENCE.iter().map(|m| m.to_vec()).collect();
+    let mut failed = 0;
+    for (label, _, to) in &checks {
+        let got = received(&app, to);
+        if got == expected {
+            println!("ok    {label}");
+        } else {
+            println!("FAIL  {label}: expected {expected:02X?}, got {got:02X?}");
+            failed += 1;
+        }
+    }
+    app.close_all();
+    if failed > 0 {
+        return Err(anyhow!(
+            "selftest failed ({failed} of {} checks)",
+            checks.len()
+        ));
+    }
+    println!("selftest passed");
+    Ok(())
 }
 
-fn run_app(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> Result<()> {
+fn run_app(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    startup: &[DeviceKey],
+    cli: &CliArgs,
+) -> Result<FrameTimes> {
     let mut app = App::new()?;
+    if let Some(target) = &cli.osc_out {
+        app.connect_osc(target)?;
+    }
+    if let Some(addr) = &cli.json_tcp {
+        app.listen_json(addr)?;
+    }
+    app.add_rtp_peers(&cli.rtpmidi);
+    app.open_startup_devices(startup);
+    if cli.monitor {
+        app.focus = Focus::Right;
+    }
+    if let Some(rate) = cli.stress {
+        app.start_stress(rate);
+    }
+    let mut frames = FrameTimes::default();
 
-    let tick = Duration::from_millis(100);
-    let refresh_every = Duration::from_secs(5);
+    let refresh_every = Duration::from_millis(app.config.refresh_interval_ms.max(100));
 
     let mut list_state = ListState::default();
-    list_state.select(Some(app.selected));
 
-    let exit_result = loop {
+    // Rows in the log view at the last draw, for page-wise scrolling
+    let mut log_height = 0usize;
+    // Where the device list and the log were drawn, for mouse hit-testing
+    let mut list_rect = Rect::default();
+    let mut log_rect = Rect::default();
+
+    let exit_result = 'main: loop {
         // Drain incoming MIDI messages to log
         app.drain_rx();
+        app.release_keyboard_notes(false);
+        app.step_latency_probe();
+        app.reap_playback();
 
         // Auto refresh (hotplug-ish)
         if app.last_refresh.elapsed() >= refresh_every {
             app.refresh_devices();
-            list_state.select(Some(app.selected));
         }
 
+        app.keep_active_first();
+        list_state.select(app.selected_row());
+        app.sync_view_state();
+        app.update_port_meta();
+
+        let frame_start = Instant::now();
         terminal.draw(|f| {
-            let size = f.size();
+            let theme = app.theme;
+            let rows = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(0), Constraint::Length(1)])
+                .split(f.size());
+            let (size, status_rect) = (rows[0], rows[1]);
             let chunks = Layout::default()
                 .direction(Direction::Horizontal)
                 .constraints([Constraint::Percentage(45), Constraint::Percentage(55)].as_ref())
@@ -364,18 +5713,47 @@ fn run_app(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> Result<()>
 
             // LEFT: list with OPEN marks
             let items: Vec<ListItem> = app
-                .devices
-                .iter()
-                .map(|d| {
-                    let kind_tag = match d.key.kind {
-                        MidiKind::Input => "[IN] ",
-                        MidiKind::Output => "[OUT]",
+                .list_rows()
+                .into_iter()
+                .map(|row| {
+                    let i = match row {
+                        ListRow::Device(i) => i,
+                        ListRow::Header(kind) => {
+                            let title = match kind {
+                                MidiKind::Input => "── Inputs ──",
+                                MidiKind::Output => "── Outputs ──",
+                            };
+                            return ListItem::new(Span::styled(
+                                title,
+                                Style::default()
+                                    .fg(theme.label)
+                                    .add_modifier(Modifier::BOLD),
+                            ));
+                        }
                     };
-                    let mut spans = vec![
-                        Span::styled(kind_tag, Style::default().fg(Color::Yellow)),
-                        Span::raw(" "),
-                        Span::raw(&d.key.name),
-                    ];
+                    let d = &app.devices[i];
+                    let star = if app.favorites.contains(&d.key) {
+                        "★ "
+                    } else {
+                        "  "
+                    };
+                    let mut spans = vec![Span::styled(star, Style::default().fg(theme.label))];
+                    if !app.marked.is_empty() {
+                        let check = if app.marked.contains(&d.key) {
+                            "[x] "
+                        } else {
+                            "[ ] "
+                        };
+                        spans.push(Span::styled(check, Style::default().fg(theme.accent)));
+                    }
+                    if app.show_hidden && app.is_hidden(&d.key) {
+                        spans.push(Span::styled(
+                            format!("{} (hidden)", d.key.label()),
+                            Style::default().fg(theme.muted),
+                        ));
+                    } else {
+                        spans.push(Span::raw(d.key.label()));
+                    }
                     let is_open = match d.key.kind {
                         MidiKind::Input => app.in_conns.contains_key(&d.key),
                         MidiKind::Output => app.out_conns.contains_key(&d.key),
@@ -384,8 +5762,49 @@ fn run_app(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> Result<()>
                         spans.push(Span::raw(" "));
                         spans.push(Span::styled(
                             "●OPEN",
+                            Style::default().fg(theme.ok).add_modifier(Modifier::BOLD),
+                        ));
+                    }
+                    if let Some(error) = app.open_failures.get(&d.key).filter(|_| !is_open) {
+                        let badge = if looks_busy(error) {
+                            " ⚠IN USE"
+                        } else {
+                            " ⚠FAILED"
+                        };
+                        spans.push(Span::styled(
+                            badge,
+                            Style::default()
+                                .fg(theme.alert)
+                                .add_modifier(Modifier::BOLD),
+                        ));
+                    }
+                    if app.linked.contains_key(&d.key) {
+                        spans.push(Span::styled(" ⇄", Style::default().fg(theme.accent)));
+                    }
+                    if app.clock_out.as_ref().is_some_and(|c| c.target == d.key) {
+                        spans.push(Span::raw(" "));
+                        spans.push(Span::styled(
+                            format!("♩{:.0}", app.clock_bpm().unwrap_or_default()),
+                            Style::default()
+                                .fg(theme.accent)
+                                .add_modifier(Modifier::BOLD),
+                        ));
+                    }
+                    if app.playback.as_ref().is_some_and(|pb| pb.target == d.key) {
+                        spans.push(Span::raw(" "));
+                        spans.push(Span::styled(
+                            "▶PLAY",
+                            Style::default()
+                                .fg(theme.accent)
+                                .add_modifier(Modifier::BOLD),
+                        ));
+                    }
+                    if app.index_recently_changed(&d.key) {
+                        spans.push(Span::raw(" "));
+                        spans.push(Span::styled(
+                            "Δidx",
                             Style::default()
-                                .fg(Color::Green)
+                                .fg(theme.marker)
                                 .add_modifier(Modifier::BOLD),
                         ));
                     }
@@ -394,34 +5813,86 @@ fn run_app(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> Result<()>
                 .collect();
 
             let (left_border_color, right_border_color) = match app.focus {
-                Focus::Left => (Color::Cyan, Color::DarkGray),
-                Focus::Right => (Color::DarkGray, Color::Cyan),
+                _ if app.flashing() => (theme.alert, theme.alert),
+                Focus::Left => (theme.accent, theme.muted),
+                Focus::Right => (theme.muted, theme.accent),
             };
 
             let left_block = Block::default()
                 .title(format!(
-                    " MIDI Devices  (open: in {}, out {}) ",
+                    " MIDI Devices  (open: in {}, out {}){}{}{} ",
                     app.in_conns.len(),
-                    app.out_conns.len()
+                    app.out_conns.len(),
+                    match (app.list_frozen, app.sort_mode) {
+                        (true, _) => "  LIST FROZEN",
+                        (false, SortMode::Activity) => "  by activity",
+                        (false, SortMode::Name) => "",
+                    },
+                    match app.devices.iter().filter(|d| app.is_hidden(&d.key)).count() {
+                        0 => String::new(),
+                        n if app.show_hidden => format!("  +{n} hidden"),
+                        n => format!("  {n} hidden"),
+                    },
+                    if app.filter_editing || !app.device_filter.is_empty() {
+                        format!(
+                            "  /{}{}",
+                            app.device_filter,
+                            if app.filter_editing { "▏" } else { "" }
+                        )
+                    } else {
+                        String::new()
+                    }
                 ))
                 .borders(Borders::ALL)
                 .border_style(Style::default().fg(left_border_color));
 
             let list = List::new(items)
-                .block(left_block)
+                .block(left_block.clone())
                 .highlight_style(
                     Style::default()
-                        .bg(Color::Blue)
-                        .fg(Color::White)
+                        .bg(theme.selection_bg)
+                        .fg(theme.selection_fg)
                         .add_modifier(Modifier::BOLD),
                 )
                 .highlight_symbol("▶ ");
 
-            f.render_stateful_widget(list, chunks[0], &mut list_state);
+            list_rect = chunks[0];
+            if app.devices.is_empty() {
+                // Nothing to list; fill the pane with a hint instead
+                let inner_height = chunks[0].height.saturating_sub(2) as usize;
+                let mut empty = vec![Line::from(""); inner_height.saturating_sub(2) / 2];
+                empty.push(Line::from(Span::styled(
+                    "No MIDI devices",
+                    Style::default()
+                        .fg(theme.label)
+                        .add_modifier(Modifier::BOLD),
+                )));
+                empty.push(Line::from(Span::styled(
+                    format!("press {} to rescan", app.keys.label(Action::Refresh)),
+                    Style::default().fg(theme.muted),
+                )));
+                f.render_widget(
+                    Paragraph::new(empty)
+                        .alignment(Alignment::Center)
+                        .block(left_block),
+                    chunks[0],
+                );
+            } else {
+                f.render_stateful_widget(list, chunks[0], &mut list_state);
+            }
 
             // RIGHT: details + recent MIDI
+            let right_title = match &app.kb_target {
+                Some(target) => format!(
+                    " Keyboard → {}  base {}  ch {}  (z/x octave, [/] channel, Esc exit) ",
+                    target.name,
+                    midi::note_name(app.kb_base, app.c_octave),
+                    app.out_channel + 1
+                ),
+                None => " Details ".to_string(),
+            };
             let right_block = Block::default()
-                .title(" Details ")
+                .title(right_title)
                 .borders(Borders::ALL)
                 .border_style(Style::default().fg(right_border_color));
 
@@ -430,7 +5901,26 @@ fn run_app(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> Result<()>
 
             let mut lines: Vec<Line> = vec![];
 
-            if let Some(dev) = app.devices.get(app.selected) {
+            if let Some((key, error)) = &app.last_error {
+                lines.extend([
+                    Line::from(vec![
+                        Span::styled(
+                            "Last error: ",
+                            Style::default()
+                                .fg(theme.alert)
+                                .add_modifier(Modifier::BOLD),
+                        ),
+                        Span::raw(key.label()),
+                    ]),
+                    Line::from(Span::styled(
+                        error.as_str(),
+                        Style::default().fg(theme.alert),
+                    )),
+                    Line::from(""),
+                ]);
+            }
+
+            if let Some(dev) = app.selected_device() {
                 let kind_str = match dev.key.kind {
                     MidiKind::Input => "Input",
                     MidiKind::Output => "Output",
@@ -439,7 +5929,12 @@ fn run_app(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> Result<()>
                     MidiKind::Input => app.in_conns.contains_key(&dev.key),
                     MidiKind::Output => app.out_conns.contains_key(&dev.key),
                 };
-                let open_str = if is_open { "OPEN" } else { "CLOSED" };
+                let open_str = match app.open_failures.get(&dev.key) {
+                    _ if is_open => "OPEN",
+                    Some(error) if looks_busy(error) => "CLOSED (in use elsewhere?)",
+                    Some(_) => "CLOSED (last open failed)",
+                    None => "CLOSED",
+                };
 
                 lines.extend([
                     Line::from(Span::styled(
@@ -448,76 +5943,373 @@ fn run_app(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> Result<()>
                     )),
                     Line::from(""),
                     Line::from(vec![
-                        Span::styled("Name: ", Style::default().fg(Color::Yellow)),
-                        Span::raw(&dev.key.name),
+                        Span::styled("Name: ", Style::default().fg(theme.label)),
+                        Span::raw(dev.key.label()),
                     ]),
                     Line::from(vec![
-                        Span::styled("Kind: ", Style::default().fg(Color::Yellow)),
+                        Span::styled("Kind: ", Style::default().fg(theme.label)),
                         Span::raw(kind_str),
                     ]),
                     Line::from(vec![
-                        Span::styled("Index: ", Style::default().fg(Color::Yellow)),
-                        Span::raw(dev.index.to_string()),
+                        Span::styled("Index: ", Style::default().fg(theme.label)),
+                        Span::raw(if app.virtual_ports.contains(&dev.key) {
+                            "virtual".to_string()
+                        } else {
+                            dev.index.to_string()
+                        }),
                     ]),
                     Line::from(vec![
-                        Span::styled("Status: ", Style::default().fg(Color::Yellow)),
+                        Span::styled("Status: ", Style::default().fg(theme.label)),
                         Span::styled(
                             open_str,
                             if is_open {
-                                Style::default()
-                                    .fg(Color::Green)
-                                    .add_modifier(Modifier::BOLD)
+                                Style::default().fg(theme.ok).add_modifier(Modifier::BOLD)
                             } else {
-                                Style::default().fg(Color::Red)
+                                Style::default().fg(theme.alert)
                             },
                         ),
                     ]),
-                    Line::from(""),
                 ]);
+                if let Some(partner) = app.linked.get(&dev.key) {
+                    lines.push(Line::from(vec![
+                        Span::styled("Linked: ", Style::default().fg(theme.label)),
+                        Span::raw(format!("⇄ {}", partner.label())),
+                    ]));
+                }
+                lines.push(Line::from(""));
 
-                if dev.key.kind == MidiKind::Input {
+                if let Some((_, meta)) = app.port_meta.as_ref().filter(|(k, _)| *k == dev.key) {
+                    if let Some(client) = &meta.client {
+                        lines.push(Line::from(vec![
+                            Span::styled("Client: ", Style::default().fg(theme.label)),
+                            Span::raw(client.as_str()),
+                        ]));
+                    }
+                    if !meta.port.is_empty() {
+                        lines.push(Line::from(vec![
+                            Span::styled("Port: ", Style::default().fg(theme.label)),
+                            Span::raw(meta.port.as_str()),
+                        ]));
+                    }
+                    if let Some(address) = &meta.address {
+                        lines.push(Line::from(vec![
+                            Span::styled("Address: ", Style::default().fg(theme.label)),
+                            Span::raw(address.as_str()),
+                        ]));
+                    }
+                    lines.push(Line::from(vec![
+                        Span::styled("Backend: ", Style::default().fg(theme.label)),
+                        Span::raw(meta.backend),
+                    ]));
+                    lines.push(Line::from(""));
+                }
+
+                let alert_str = if app.alert_learning {
+                    "learning…".to_string()
+                } else if let Some(t) = app.alert_trigger {
+                    format!("{} ({:?})", t.describe(), app.alert_style)
+                } else {
+                    "off".to_string()
+                };
+                lines.push(Line::from(vec![
+                    Span::styled("Alert: ", Style::default().fg(theme.label)),
+                    Span::raw(alert_str),
+                ]));
+                lines.push(Line::from(""));
+
+                if let Some(mark) = &app.route_mark {
+                    lines.push(Line::from(vec![
+                        Span::styled("Marked: ", Style::default().fg(theme.label)),
+                        Span::raw(mark.name.as_str()),
+                    ]));
+                }
+                if !app.routes.is_empty() {
                     lines.push(Line::from(Span::styled(
-                        "Recent MIDI (latest first):",
-                        Style::default().add_modifier(Modifier::BOLD),
+                        "Routes:",
+                        Style::default().fg(theme.label),
                     )));
+                    for (n, route) in app.routes.iter().enumerate() {
+                        let (mark, style) = if app.route_active(route) {
+                            ("●", Style::default().fg(theme.ok))
+                        } else {
+                            ("○", Style::default().fg(theme.muted))
+                        };
+                        lines.push(Line::from(Span::styled(
+                            format!(
+                                "  {mark} {}. {} → {}{}",
+                                n + 1,
+                                route.src.name,
+                                route.dst.name,
+                                route.describe_processing()
+                            ),
+                            style,
+                        )));
+                    }
+                    lines.push(Line::from(""));
+                }
+
+                if dev.key.kind == MidiKind::Input {
+                    lines.push(Line::from(vec![
+                        Span::styled("Ignoring: ", Style::default().fg(theme.label)),
+                        Span::raw(IGNORE_MODES[app.input_ignore].1),
+                    ]));
+                    let (total, rate) = app
+                        .device_stats
+                        .get(&dev.key)
+                        .map_or((0, 0.0), |st| (st.total, st.rate()));
+                    lines.push(Line::from(vec![
+                        Span::styled("Messages: ", Style::default().fg(theme.label)),
+                        Span::raw(format!("{total}  ({rate:.1}/s)")),
+                    ]));
+                    if let Some(bpm) = app.clock_tempo.get(&dev.key).and_then(|t| t.bpm()) {
+                        lines.push(Line::from(vec![
+                            Span::styled("Tempo: ", Style::default().fg(theme.label)),
+                            Span::raw(format!("{bpm:.1} BPM")),
+                        ]));
+                    }
                     lines.push(Line::from(""));
-                    for s in app.log.iter().rev().take(15) {
-                        lines.push(Line::from(s.clone()));
+                    if let Some((note, cents)) = app.last_note_with_bend(&dev.key) {
+                        let hz = note_to_hz(note as f64 + cents / 100.0, app.config.a4_hz);
+                        let bend = if cents.abs() >= 0.5 {
+                            format!(" ({cents:+.0} cents)")
+                        } else {
+                            String::new()
+                        };
+                        lines.push(Line::from(vec![
+                            Span::styled("Last note: ", Style::default().fg(theme.label)),
+                            Span::raw(format!(
+                                "{note} ({}) → {hz:.2} Hz{bend}",
+                                midi::note_name(note, app.c_octave)
+                            )),
+                        ]));
+                        lines.push(Line::from(""));
+                    }
+                    if let Some(mpe) = app.mpe.get(&dev.key).filter(|_| app.mpe_view) {
+                        lines.extend(mpe_rows(mpe, &theme, app.c_octave));
+                        lines.push(Line::from(""));
+                    }
+                    if let Some(held) = app.held_notes.get(&dev.key) {
+                        let down: Vec<u8> = (0..128u8).filter(|&n| held[n as usize] > 0).collect();
+                        let names: Vec<String> = down
+                            .iter()
+                            .map(|&n| midi::note_name(n, app.c_octave))
+                            .collect();
+                        let mut spans = vec![Span::styled(
+                            "Held notes: ",
+                            Style::default().fg(theme.label),
+                        )];
+                        spans.push(Span::raw(names.join(" ")));
+                        if let Some(chord) = midi::chord_name(&down) {
+                            spans.push(Span::raw("  "));
+                            spans.push(Span::styled(
+                                chord,
+                                Style::default()
+                                    .fg(theme.accent)
+                                    .add_modifier(Modifier::BOLD),
+                            ));
+                        }
+                        lines.push(Line::from(spans));
+                        let width = detail_area.width.saturating_sub(2) as usize;
+                        lines.extend(held_note_rows(held, width, &theme, app.c_octave));
+                        lines.push(Line::from(""));
+                    }
+                    if let Some(hist) = app.velocity_hist.get(&dev.key) {
+                        lines.push(Line::from(Span::styled(
+                            "Velocity:",
+                            Style::default().fg(theme.label),
+                        )));
+                        let width = detail_area.width.saturating_sub(2) as usize;
+                        lines.extend(velocity_rows(hist, width, &theme));
+                        lines.push(Line::from(""));
+                    }
+                    if app.show_note_trail {
+                        let width = detail_area.width.saturating_sub(4) as usize;
+                        match note_trail_rows(
+                            &app.log,
+                            &dev.key,
+                            app.held_notes.get(&dev.key),
+                            SystemTime::now(),
+                            TRAIL_WINDOW,
+                            width,
+                            TRAIL_ROWS,
+                        ) {
+                            Some(((lo, hi), rows)) => {
+                                lines.push(Line::from(Span::styled(
+                                    format!(
+                                        "Note trail (last {}s, notes {}–{}, {:.1}–{:.1} Hz):",
+                                        TRAIL_WINDOW.as_secs(),
+                                        midi::note_name(lo, app.c_octave),
+                                        midi::note_name(hi, app.c_octave),
+                                        note_to_hz(lo as f64, app.config.a4_hz),
+                                        note_to_hz(hi as f64, app.config.a4_hz)
+                                    ),
+                                    Style::default().add_modifier(Modifier::BOLD),
+                                )));
+                                for row in rows {
+                                    lines.push(Line::from(Span::styled(
+                                        row,
+                                        Style::default().fg(theme.ok),
+                                    )));
+                                }
+                            }
+                            None => lines.push(Line::from("Note trail: no recent notes")),
+                        }
+                        lines.push(Line::from(""));
                     }
                 } else {
+                    lines.push(Line::from(vec![
+                        Span::styled("Channel: ", Style::default().fg(theme.label)),
+                        Span::styled(
+                            (app.out_channel + 1).to_string(),
+                            Style::default()
+                                .fg(theme.accent)
+                                .add_modifier(Modifier::BOLD),
+                        ),
+                        Span::raw("  ([/] to change)"),
+                    ]));
+                    lines.push(Line::from(""));
                     lines.extend([
                         Line::from("This is an OUTPUT device."),
                         Line::from("Press Enter to open/close this port."),
                         Line::from("Shift+C closes all open ports."),
                     ]);
                 }
-            } else {
+            } else if app.devices.is_empty() {
                 lines.extend([
                     Line::from("No devices detected."),
-                    Line::from("Press r to refresh."),
+                    Line::from(format!(
+                        "Connect one and press {} to rescan (or wait for the auto refresh),",
+                        app.keys.label(Action::Refresh)
+                    )),
+                    Line::from(format!(
+                        "or press {} / {} to create a virtual port.",
+                        app.keys.label(Action::VirtualInput),
+                        app.keys.label(Action::VirtualOutput)
+                    )),
                 ]);
+            } else {
+                lines.push(Line::from(format!(
+                    "No device matches \"{}\".",
+                    app.device_filter
+                )));
             }
 
-            let details = Paragraph::new(lines).wrap(Wrap { trim: true });
             let detail_inner = detail_area.inner(&Margin {
                 horizontal: 1,
-                vertical: 1,
+                vertical: 0,
             });
-            f.render_widget(details, detail_inner);
+            let details_height = (lines.len() as u16).min(detail_inner.height * 3 / 5);
+            let right_chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(details_height), Constraint::Min(3)])
+                .split(detail_inner);
+            let details = Paragraph::new(lines).wrap(Wrap { trim: true });
+            f.render_widget(details, right_chunks[0]);
+
+            // LOG: oldest at top, newest at the bottom; scrolled views stay put
+            let log_block = Block::default()
+                .title(Line::from(
+                    std::iter::once(Span::raw(app.log_title()))
+                        .chain(app.device_legend())
+                        .collect::<Vec<_>>(),
+                ))
+                .borders(Borders::TOP)
+                .border_style(Style::default().fg(theme.muted));
+            let log_area = log_block.inner(right_chunks[1]);
+            log_height = log_area.height as usize;
+            log_rect = right_chunks[1];
+            let split = app.split_columns
+                && app
+                    .selected_device()
+                    .is_some_and(|d| d.key.kind == MidiKind::Input);
+            if split {
+                let cols = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([Constraint::Percentage(45), Constraint::Percentage(55)])
+                    .split(log_area);
+                let raw_width = cols[0].width.saturating_sub(1) as usize;
+                let text_width = cols[1].width as usize;
+                // Rows newest first until the pane is full; each row is as tall
+                // as its longer column so the two sides stay aligned
+                let mut blocks = Vec::new();
+                let mut used = 0;
+                let clusters = app.recent_clusters(app.log_scroll + log_height);
+                for (i, cluster) in clusters.iter().enumerate().skip(app.log_scroll) {
+                    let (raw, text) = app.cluster_columns(cluster);
+                    let raw = wrap_chars(&raw, raw_width);
+                    let text = wrap_chars(&text, text_width);
+                    let height = raw.len().max(text.len());
+                    if used + height > log_height && used > 0 {
+                        break;
+                    }
+                    used += height;
+                    let style = if app.log_scroll > 0 && i == app.log_scroll {
+                        app.cluster_style(cluster).bg(theme.muted)
+                    } else {
+                        app.cluster_style(cluster)
+                    };
+                    blocks.push((raw, text, height, style));
+                }
+                let mut raw_lines: Vec<Line> = Vec::new();
+                let mut text_lines: Vec<Line> = Vec::new();
+                for (raw, text, height, style) in blocks.into_iter().rev() {
+                    for n in 0..height {
+                        let cell = |col: &Vec<String>| {
+                            Line::from(Span::styled(col.get(n).cloned().unwrap_or_default(), style))
+                        };
+                        raw_lines.push(cell(&raw));
+                        text_lines.push(cell(&text));
+                    }
+                }
+                if raw_lines.is_empty() {
+                    raw_lines.push(Line::from(Span::styled(
+                        "(no messages yet)",
+                        Style::default().fg(theme.muted),
+                    )));
+                }
+                f.render_widget(log_block, right_chunks[1]);
+                f.render_widget(
+                    Paragraph::new(raw_lines).style(Style::default().fg(theme.accent)),
+                    cols[0],
+                );
+                f.render_widget(Paragraph::new(text_lines), cols[1]);
+            } else {
+                let mut rows = app.recent_lines(app.log_scroll + log_height);
+                let shown = rows.len().saturating_sub(app.log_scroll).min(log_height);
+                let start = rows.len().saturating_sub(shown);
+                let mut rows: Vec<ListItem> = rows
+                    .drain(start..)
+                    .rev()
+                    .map(|(line, style)| ListItem::new(Span::styled(line, style)))
+                    .collect();
+                if rows.is_empty() {
+                    rows.push(ListItem::new(Span::styled(
+                        "(no messages yet)",
+                        Style::default().fg(theme.muted),
+                    )));
+                }
+                let mut log_state = ListState::default();
+                if app.log_scroll > 0 {
+                    log_state.select(Some(rows.len() - 1));
+                }
+                let log_list = List::new(rows)
+                    .block(log_block)
+                    .highlight_style(Style::default().bg(theme.muted));
+                f.render_stateful_widget(log_list, right_chunks[1], &mut log_state);
+            }
 
             // FOOTER
-            let help = Paragraph::new(Line::from(vec![
-                Span::styled("Keys: ", Style::default().fg(Color::Yellow)),
-                Span::raw("↑/↓ select  "),
-                Span::raw("←/→ focus  "),
-                Span::raw("Enter open/close  "),
-                Span::raw("Shift+C close-all  "),
-                Span::raw("r refresh  "),
-                Span::raw("q/Esc quit"),
-            ]))
-            .block(Block::default().borders(Borders::TOP));
-
-            let size = f.size();
+            let mut help_spans = vec![Span::styled("Keys: ", Style::default().fg(theme.label))];
+            help_spans.extend(
+                Action::ALL
+                    .iter()
+                    .filter(|a| !app.keys.label(**a).is_empty())
+                    .map(|a| Span::raw(format!("{} {}  ", app.keys.label(*a), a.help()))),
+            );
+            help_spans.push(Span::raw("0-9 channel-filter"));
+            let help = Paragraph::new(Line::from(help_spans))
+                .block(Block::default().borders(Borders::TOP));
+
             let footer_rect = Rect {
                 x: size.x,
                 y: size.y + size.height.saturating_sub(1),
@@ -525,53 +6317,919 @@ fn run_app(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> Result<()>
                 height: 1,
             };
             f.render_widget(help, footer_rect);
+
+            // STATUS BAR
+            f.render_widget(
+                Paragraph::new(Line::from(app.status_spans(&theme))),
+                status_rect,
+            );
+
+            // RECENT popup
+            if let Some(sel) = app.recent_popup {
+                let items: Vec<ListItem> = app
+                    .recent
+                    .iter()
+                    .map(|k| {
+                        let kind_tag = match k.kind {
+                            MidiKind::Input => "[IN] ",
+                            MidiKind::Output => "[OUT]",
+                        };
+                        let present = app.devices.iter().any(|d| &d.key == k);
+                        let name_style = if present {
+                            Style::default()
+                        } else {
+                            Style::default().fg(theme.muted)
+                        };
+                        ListItem::new(Line::from(vec![
+                            Span::styled(kind_tag, Style::default().fg(theme.label)),
+                            Span::raw(" "),
+                            Span::styled(k.name.as_str(), name_style),
+                        ]))
+                    })
+                    .collect();
+                let popup = List::new(items)
+                    .block(
+                        Block::default()
+                            .title(" Recent devices (Enter jump, Esc close) ")
+                            .borders(Borders::ALL)
+                            .border_style(Style::default().fg(theme.accent)),
+                    )
+                    .highlight_style(
+                        Style::default()
+                            .bg(theme.selection_bg)
+                            .fg(theme.selection_fg)
+                            .add_modifier(Modifier::BOLD),
+                    )
+                    .highlight_symbol("▶ ");
+                let area = centered_rect(60, 50, size);
+                let mut popup_state = ListState::default();
+                popup_state.select(Some(sel));
+                f.render_widget(Clear, area);
+                f.render_stateful_widget(popup, area, &mut popup_state);
+            }
+
+            // CONFIRM modal
+            if let Some(confirm) = app.confirm {
+                let area = centered_rect(40, 20, size);
+                let area = Rect {
+                    height: area.height.min(3),
+                    ..area
+                };
+                let question = Paragraph::new(Line::from(Span::styled(
+                    confirm.question(),
+                    Style::default().add_modifier(Modifier::BOLD),
+                )))
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .border_style(Style::default().fg(theme.label)),
+                );
+                f.render_widget(Clear, area);
+                f.render_widget(question, area);
+            }
+
+            // SYSEX dump modal
+            if let Some((data, scroll)) = &app.sysex_view {
+                let area = centered_rect(80, 70, size);
+                let rows: Vec<Line> = hex_dump_lines(data)
+                    .into_iter()
+                    .skip(*scroll)
+                    .map(Line::from)
+                    .collect();
+                let dump = Paragraph::new(rows).block(
+                    Block::default()
+                        .title(format!(
+                            " SysEx dump, {} bytes  (↑/↓/PgUp/PgDn scroll, Esc close) ",
+                            data.len()
+                        ))
+                        .borders(Borders::ALL)
+                        .border_style(Style::default().fg(theme.accent)),
+                );
+                f.render_widget(Clear, area);
+                f.render_widget(dump, area);
+            }
+
+            // HELP overlay
+            if let Some(scroll) = app.help_view {
+                let rows = app.help_lines();
+                let key_width = rows
+                    .iter()
+                    .map(|(k, _)| k.chars().count())
+                    .max()
+                    .unwrap_or(0);
+                let lines: Vec<Line> = rows
+                    .into_iter()
+                    .skip(scroll)
+                    .map(|(keys, what)| {
+                        Line::from(vec![
+                            Span::styled(
+                                format!("{keys:>key_width$}  "),
+                                Style::default().fg(theme.label),
+                            ),
+                            Span::raw(what),
+                        ])
+                    })
+                    .collect();
+                let area = centered_rect(60, 80, size);
+                let help = Paragraph::new(lines).block(
+                    Block::default()
+                        .title(" Keys  (↑/↓/PgUp/PgDn scroll, any other key closes) ")
+                        .borders(Borders::ALL)
+                        .border_style(Style::default().fg(theme.accent)),
+                );
+                f.render_widget(
+                    Block::default().style(Style::default().add_modifier(Modifier::DIM)),
+                    size,
+                );
+                f.render_widget(Clear, area);
+                f.render_widget(help, area);
+            }
+
+            // PROMPT modal
+            if let Some(prompt) = &app.prompt {
+                let area = centered_rect(60, 20, size);
+                let area = Rect {
+                    height: area.height.min(3),
+                    ..area
+                };
+                let input = Paragraph::new(Line::from(vec![
+                    Span::raw(prompt.input.as_str()),
+                    Span::styled("▏", Style::default().fg(theme.accent)),
+                ]))
+                .block(
+                    Block::default()
+                        .title(prompt.kind.title())
+                        .borders(Borders::ALL)
+                        .border_style(Style::default().fg(theme.accent)),
+                );
+                f.render_widget(Clear, area);
+                f.render_widget(input, area);
+            }
         })?;
+        frames.record(frame_start.elapsed());
+        if cli.crash_test {
+            panic!("--crash-test: forced panic after the first frame");
+        }
 
         // Input handling
-        if event::poll(tick)? {
-            if let Event::Key(key) = event::read()? {
-                match key.code {
-                    KeyCode::Char('q') | KeyCode::Esc => break Ok(()),
-                    KeyCode::Char('r') => {
-                        app.refresh_devices();
-                        list_state.select(Some(app.selected));
-                    }
-                    KeyCode::Left => app.focus = Focus::Left,
-                    KeyCode::Right => app.focus = Focus::Right,
-                    KeyCode::Enter => {
-                        if app.focus == Focus::Left {
-                            if let Err(e) = app.toggle_open_selected() {
-                                app.push_status(format!("Error: {e:#}"));
+        if event::poll(app.tick())? {
+            let ev = event::read()?;
+            app.last_activity = Instant::now();
+            if matches!(ev, Event::FocusLost) && app.config.auto_panic_on_focus_loss {
+                app.panic_all();
+            }
+            if let Event::Mouse(mouse) = ev {
+                app.handle_mouse(mouse, list_rect, list_state.offset(), log_rect);
+            }
+            if let Event::Key(key) = ev {
+                if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                    break Ok(());
+                }
+                if let Some(confirm) = app.confirm {
+                    match key.code {
+                        KeyCode::Char('y') | KeyCode::Char('Y') => {
+                            app.confirm = None;
+                            match confirm {
+                                Confirm::Quit => break Ok(()),
+                                Confirm::CloseAll(_) => app.close_all(),
                             }
                         }
+                        KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
+                            app.confirm = None
+                        }
+                        _ => {}
+                    }
+                    continue;
+                }
+                if let Some(scroll) = app.help_view {
+                    let max = app.help_lines().len().saturating_sub(1);
+                    app.help_view = match key.code {
+                        KeyCode::Up => Some(scroll.saturating_sub(1)),
+                        KeyCode::Down => Some((scroll + 1).min(max)),
+                        KeyCode::PageUp => Some(scroll.saturating_sub(10)),
+                        KeyCode::PageDown => Some((scroll + 10).min(max)),
+                        _ => None,
+                    };
+                    continue;
+                }
+                if let Some((data, scroll)) = app.sysex_view.as_mut() {
+                    let max = data.len().div_ceil(16).saturating_sub(1);
+                    match key.code {
+                        KeyCode::Esc | KeyCode::Char('x') | KeyCode::Char('q') => {
+                            app.sysex_view = None
+                        }
+                        KeyCode::Up => *scroll = scroll.saturating_sub(1),
+                        KeyCode::Down => *scroll = (*scroll + 1).min(max),
+                        KeyCode::PageUp => *scroll = scroll.saturating_sub(16),
+                        KeyCode::PageDown => *scroll = (*scroll + 16).min(max),
+                        _ => {}
                     }
-                    KeyCode::Char('C') if key.modifiers.contains(KeyModifiers::SHIFT) => {
-                        app.close_all();
+                    continue;
+                }
+                if let Some(prompt) = app.prompt.as_mut() {
+                    match key.code {
+                        KeyCode::Enter => app.submit_prompt(),
+                        KeyCode::Esc => app.prompt = None,
+                        KeyCode::Backspace => {
+                            prompt.input.pop();
+                        }
+                        KeyCode::Char(c) => prompt.input.push(c),
+                        KeyCode::Up | KeyCode::Down if prompt.kind == PromptKind::SendHex => {
+                            let pos = match (key.code, prompt.history_pos) {
+                                (KeyCode::Up, None) => Some(0),
+                                (KeyCode::Up, Some(p)) => Some(p + 1),
+                                (_, Some(p)) => p.checked_sub(1),
+                                (_, None) => None,
+                            };
+                            match pos.filter(|p| *p < app.send_history.len()) {
+                                Some(p) => {
+                                    prompt.history_pos = Some(p);
+                                    prompt.input = app.send_history[p].clone();
+                                }
+                                // Down past the latest entry returns to an empty line
+                                None if key.code == KeyCode::Down => {
+                                    prompt.history_pos = None;
+                                    prompt.input.clear();
+                                }
+                                None => {}
+                            }
+                        }
+                        _ => {}
                     }
-                    KeyCode::Up => {
-                        if app.focus == Focus::Left {
-                            app.select_up();
-                            list_state.select(Some(app.selected));
+                    continue;
+                }
+                if app.kb_target.is_some() {
+                    match key.code {
+                        KeyCode::Esc | KeyCode::Char('K') => app.toggle_keyboard(),
+                        KeyCode::Char('z') => app.shift_keyboard_octave(-1),
+                        KeyCode::Char('x') => app.shift_keyboard_octave(1),
+                        KeyCode::Char('[') => {
+                            app.release_keyboard_notes(true);
+                            app.change_out_channel(-1);
+                        }
+                        KeyCode::Char(']') => {
+                            app.release_keyboard_notes(true);
+                            app.change_out_channel(1);
                         }
+                        KeyCode::Char(c) => app.keyboard_key(c),
+                        _ => {}
                     }
-                    KeyCode::Down => {
-                        if app.focus == Focus::Left {
-                            app.select_down();
-                            list_state.select(Some(app.selected));
+                    continue;
+                }
+                if app.filter_editing {
+                    app.device_filter_key(key.code);
+                    continue;
+                }
+                if let Some(sel) = app.recent_popup {
+                    match key.code {
+                        KeyCode::Up => {
+                            app.recent_popup =
+                                Some(sel.checked_sub(1).unwrap_or(app.recent.len() - 1))
                         }
+                        KeyCode::Down => app.recent_popup = Some((sel + 1) % app.recent.len()),
+                        KeyCode::Enter => {
+                            app.recent_popup = None;
+                            app.jump_to_recent(sel);
+                        }
+                        KeyCode::Esc | KeyCode::Char('R') => app.recent_popup = None,
+                        _ => {}
+                    }
+                    continue;
+                }
+                if app.midi_learn == Some(MidiLearn::Key) {
+                    app.midi_learn_key(key);
+                    continue;
+                }
+                if key.code == KeyCode::Esc && !app.device_filter.is_empty() {
+                    app.device_filter_key(KeyCode::Esc);
+                    continue;
+                }
+                let Some(action) = app.keys.action(&key) else {
+                    if let KeyCode::Char(c @ '0'..='9') = key.code {
+                        app.channel_digit(c.to_digit(10).unwrap_or(0) as u8);
+                    }
+                    continue;
+                };
+                app.pending_actions.push_back(action);
+            }
+        }
+
+        // Keys and MIDI-bound controls both end up here
+        while let Some(action) = app.pending_actions.pop_front() {
+            match action {
+                Action::Panic => app.panic_all(),
+                Action::Filter => app.start_device_filter(),
+                Action::Favorite => app.toggle_favorite(),
+                Action::HideDevice => app.toggle_hidden(),
+                Action::ShowHidden => app.toggle_show_hidden(),
+                Action::SortMode => app.cycle_sort_mode(),
+                Action::Mark => app.toggle_mark(),
+                Action::OpenMarked => app.open_marked(),
+                Action::OpenPair => app.open_pair(),
+                Action::ResetCounts => app.reset_device_stats(),
+                Action::Quit => {
+                    if !app.config.confirm_quit {
+                        break 'main Ok(());
+                    }
+                    app.confirm = Some(Confirm::Quit);
+                }
+                Action::Refresh => {
+                    if app.list_frozen {
+                        app.push_status("Device list is frozen (F to unfreeze)".to_string());
+                    } else {
+                        app.open_failures.clear();
                     }
-                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                        break Ok(())
+                    app.refresh_devices();
+                }
+                Action::Freeze => app.toggle_list_frozen(),
+                Action::FocusLeft => app.focus = Focus::Left,
+                Action::FocusRight => app.focus = Focus::Right,
+                Action::ToggleOpen if app.focus == Focus::Left => {
+                    if let Err(e) = app.toggle_open_selected() {
+                        app.push_status(format!("Error: {e:#}"));
+                    }
+                }
+                Action::CloseAll => {
+                    let open = app.in_conns.len() + app.out_conns.len();
+                    if open == 0 {
+                        app.push_status("No ports are open".to_string());
+                    } else {
+                        app.confirm = Some(Confirm::CloseAll(open));
                     }
-                    _ => {}
                 }
+                Action::ConfigDir => app.reveal_config_dir(),
+                Action::AlertLearn => app.toggle_alert_learn(),
+                Action::AlertStyle => app.cycle_alert_style(),
+                Action::MidiLearn => app.toggle_midi_learn(),
+                Action::Recent => app.open_recent_popup(),
+                Action::DumpState => app.dump_state(),
+                Action::NoteTrail => app.toggle_note_trail(),
+                Action::MpeView => app.toggle_mpe_view(),
+                Action::Group => app.toggle_grouping(),
+                Action::Timing => app.toggle_timing(),
+                Action::Hex => app.toggle_raw(),
+                Action::SplitColumns => app.toggle_split_columns(),
+                Action::SysexDump => app.open_sysex_view(),
+                Action::CopyHex => app.copy_highlighted_hex(),
+                Action::SendHex => app.start_send_hex(),
+                Action::Help => app.help_view = Some(0),
+                Action::MuteCc => app.mute_highlighted_cc(),
+                Action::UnmuteCcs => app.unmute_ccs(),
+                Action::InputIgnore => app.cycle_input_ignore(),
+                Action::HideNotes => app.toggle_hidden_type(MsgTypes::NOTES),
+                Action::HideCc => app.toggle_hidden_type(MsgTypes::CC),
+                Action::HideProgram => app.toggle_hidden_type(MsgTypes::PROGRAM),
+                Action::HidePitchBend => app.toggle_hidden_type(MsgTypes::PITCH_BEND),
+                Action::HideSysex => app.toggle_hidden_type(MsgTypes::SYSEX),
+                Action::HideRealtime => app.toggle_hidden_type(MsgTypes::REALTIME),
+                Action::Keyboard => app.toggle_keyboard(),
+                Action::LogPageUp => app.scroll_log(log_height.max(1) as isize),
+                Action::LogPageDown => app.scroll_log(-(log_height.max(1) as isize)),
+                Action::LogTop => app.scroll_log(isize::MAX / 2),
+                Action::LogBottom => {
+                    app.log_paused = false;
+                    app.log_scroll = 0;
+                }
+                Action::PauseLog => app.toggle_log_pause(),
+                Action::ClearChannelFilter => app.set_channel_filter(None),
+                Action::SaveLog => app.save_log(),
+                Action::ExportDevices => app.export_devices(),
+                Action::Theme => app.cycle_theme(),
+                Action::COctave => app.cycle_c_octave(),
+                Action::UndoClose => app.undo_close(),
+                Action::OpenAllInputs => app.open_all(MidiKind::Input),
+                Action::OpenAllOutputs => app.open_all(MidiKind::Output),
+                Action::LongCapture => app.toggle_long_capture(),
+                Action::RouteMark => app.mark_for_route(),
+                Action::RouteToggle => app.toggle_route(),
+                Action::RouteEdit => app.start_route_edit(),
+                Action::LatencyProbe => app.toggle_latency_probe(),
+                Action::VelocityCurve => app.cycle_velocity_curve(),
+                Action::ByteFilter => app.open_prompt(PromptKind::BytePattern),
+                Action::Playback => app.toggle_playback(),
+                Action::Quantize => app.open_prompt(PromptKind::Quantize),
+                Action::ClockOut => app.toggle_clock(),
+                Action::ClockFaster => app.change_clock_bpm(1.0),
+                Action::ClockSlower => app.change_clock_bpm(-1.0),
+                Action::TestSequence => app.start_test_sequence(),
+                Action::VirtualInput => app.open_prompt(PromptKind::VirtualInput),
+                Action::VirtualOutput => app.open_prompt(PromptKind::VirtualOutput),
+                Action::ChannelDown => app.change_out_channel(-1),
+                Action::ChannelUp => app.change_out_channel(1),
+                Action::SelectUp if app.focus == Focus::Left => app.select_up(),
+                Action::SelectDown if app.focus == Focus::Left => app.select_down(),
+                Action::ToggleOpen | Action::SelectUp | Action::SelectDown => {}
             }
         }
     };
 
     // Persist before exit
     let _ = exit_result.as_ref();
-    let app_for_persist = app;
+    let mut app_for_persist = app;
+    app_for_persist.stop_playback();
+    app_for_persist.stop_clock();
     app_for_persist.save_persisted();
-    exit_result
+    exit_result.map(|()| frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An app with default settings and no ports, independent of the
+    /// machine's config and MIDI backend.
+    fn test_app() -> App {
+        App::with_state(Config::default(), Persisted::default(), Vec::new(), false)
+    }
+
+    fn test_key(kind: MidiKind) -> DeviceKey {
+        DeviceKey {
+            name: "Test Port".to_string(),
+            kind,
+            instance: 0,
+        }
+    }
+
+    fn midi_in(data: &[u8]) -> LoggedEvent {
+        LoggedEvent {
+            ts: SystemTime::now(),
+            at: Instant::now(),
+            stamp: 0,
+            device: Some(test_key(MidiKind::Input)),
+            direction: LogDirection::In,
+            kind: EventKind::Midi,
+            data: data.to_vec(),
+            param: None,
+            delta_us: None,
+        }
+    }
+
+    fn midi_out(data: &[u8]) -> LoggedEvent {
+        LoggedEvent {
+            device: Some(test_key(MidiKind::Output)),
+            direction: LogDirection::Out,
+            ..midi_in(data)
+        }
+    }
+
+    fn count_dir(app: &App, dir: LogDirection) -> usize {
+        app.log.iter().filter(|e| e.direction == dir).count()
+    }
+
+    #[test]
+    fn log_stays_bounded() {
+        let mut app = test_app();
+        for i in 0..5000 {
+            app.push_log(midi_in(&[0xB0, 1, (i % 128) as u8]));
+        }
+        assert_eq!(app.log.len(), app.config.log_capacity);
+        assert_eq!(app.log.back().unwrap().data, [0xB0, 1, (4999 % 128) as u8]);
+    }
+
+    #[test]
+    fn direction_caps_evict_their_own_entries() {
+        let mut app = test_app();
+        // Room for both directions, so only their own caps evict
+        app.config.log_capacity = 4096;
+        app.log_capacity = 4096;
+        let (cap_in, cap_out) = (app.config.log_capacity_in, app.config.log_capacity_out);
+        for i in 0..5000 {
+            app.push_log(midi_in(&[0xB0, 1, (i % 128) as u8]));
+            app.push_log(midi_out(&[0xB0, 2, (i % 128) as u8]));
+            if i % 7 == 0 {
+                app.push_status(format!("status {i}"));
+            }
+        }
+        let (ins, outs) = (
+            count_dir(&app, LogDirection::In),
+            count_dir(&app, LogDirection::Out),
+        );
+        assert!(app.log.len() <= app.config.log_capacity);
+        assert!(ins <= cap_in + evict_slack(cap_in));
+        assert!(outs <= cap_out + evict_slack(cap_out));
+        assert!(
+            outs >= cap_out - 1,
+            "outputs evicted below their cap: {outs}"
+        );
+        assert_eq!(app.log_counts[&LogDirection::In], ins);
+        assert_eq!(app.log_counts[&LogDirection::Out], outs);
+    }
+
+    #[test]
+    fn note_trail_reads_only_the_window() {
+        let now = SystemTime::now();
+        let at = |secs_ago: u64, data: &[u8]| LoggedEvent {
+            ts: now - Duration::from_secs(secs_ago),
+            ..midi_in(data)
+        };
+        let log: VecDeque<LoggedEvent> = [
+            // Held since before the window and through it
+            at(9, &[0x90, 48, 100]),
+            // Sounding from before the window into it
+            at(8, &[0x90, 50, 100]),
+            at(3, &[0x80, 50, 0]),
+            at(2, &[0x90, 60, 100, 62, 100]),
+            at(1, &[0x80, 60, 0]),
+        ]
+        .into();
+        let mut held = [0u8; 128];
+        held[48] = 100;
+        held[62] = 100;
+        let key = test_key(MidiKind::Input);
+        let window = Duration::from_secs(4);
+        let ((lo, hi), rows) =
+            note_trail_rows(&log, &key, Some(&held), now, window, 8, 16).unwrap();
+        assert_eq!((lo, hi), (48, 62));
+        let row = |note: u8| &rows[(hi - note) as usize];
+        assert_eq!(row(48), "████████");
+        assert_eq!(row(50), "███·····");
+        assert_eq!(row(60), "····███·");
+        assert_eq!(row(62), "····████");
+    }
+
+    #[test]
+    fn last_note_keeps_its_channels_bend() {
+        let mut app = test_app();
+        app.config.pitch_bend_range = 2.0;
+        for data in [
+            &[0xE1, 0x00, 0x60][..],
+            &[0x90, 60, 100, 0x91, 64, 100],
+            &[0xE0, 0x00, 0x20],
+        ] {
+            app.tx.try_send(midi_in(data)).unwrap();
+        }
+        app.drain_rx();
+        // Channel 2 sits half way from center to the top of its 200 cent range
+        assert_eq!(
+            app.last_note_with_bend(&test_key(MidiKind::Input)),
+            Some((64, 100.0))
+        );
+    }
+
+    #[test]
+    fn huge_durations_are_an_error() {
+        assert_eq!(parse_duration("1.5s").unwrap(), Duration::from_millis(1500));
+        assert!(parse_duration("1e400").is_err());
+        assert!(parse_duration("99999999999999999999999h").is_err());
+    }
+
+    #[test]
+    fn two_digit_channels() {
+        let mut app = test_app();
+        app.channel_digit(1);
+        assert_eq!(app.channel_filter, Some(0));
+        app.channel_digit(6);
+        assert_eq!(app.channel_filter, Some(15));
+        app.channel_digit(0);
+        assert_eq!(app.channel_filter, Some(9));
+        app.channel_digit(1);
+        app.channel_digit(1);
+        assert_eq!(app.channel_filter, Some(10));
+        app.channel_digit(9);
+        assert_eq!(app.channel_filter, Some(8));
+    }
+
+    #[test]
+    fn stopping_an_outputs_users_cancels_playback_and_keyboard() {
+        let mut app = test_app();
+        let key = test_key(MidiKind::Output);
+        let cancel = Arc::new(AtomicBool::new(false));
+        app.playback = Some(Playback {
+            target: key.clone(),
+            cancel: Arc::clone(&cancel),
+            handle: thread::spawn(|| {}),
+        });
+        app.kb_target = Some(key.clone());
+        app.stop_users_of(&test_key(MidiKind::Input), "output closed");
+        assert!(!cancel.load(Ordering::Relaxed) && app.kb_target.is_some());
+        app.stop_users_of(&key, "output closed");
+        assert!(cancel.load(Ordering::Relaxed));
+        assert_eq!(app.kb_target, None);
+    }
+
+    /// The callback only ships bytes; decoding happens as the UI drains. Run
+    /// with `--nocapture` to see the per-message cost.
+    #[test]
+    fn drain_keeps_up_with_many_raw_messages() {
+        const COUNT: u64 = 50_000;
+        let mut app = test_app();
+        let tx = app.tx.clone();
+        let started = Instant::now();
+        let mut sent = 0;
+        while sent < COUNT {
+            while sent < COUNT
+                && tx
+                    .try_send(midi_in(&[0x90, (sent % 128) as u8, 100]))
+                    .is_ok()
+            {
+                sent += 1;
+            }
+            app.drain_rx();
+        }
+        let lines: usize = app.log.iter().map(|ev| app.log_line(ev).len()).sum();
+        let elapsed = started.elapsed();
+        eprintln!(
+            "{COUNT} messages drained and {} decoded in {elapsed:?} ({:.2} µs each)",
+            app.log.len(),
+            elapsed.as_secs_f64() * 1e6 / COUNT as f64
+        );
+        assert!(lines > 0);
+        assert!(app.log.iter().all(|ev| ev.data.len() == 3));
+        assert!(elapsed < Duration::from_secs(20));
+    }
+
+    #[test]
+    fn timing_delta_is_set_when_drained() {
+        let mut app = test_app();
+        for stamp in [1_000, 3_500, 3_600] {
+            let ev = LoggedEvent {
+                stamp,
+                ..midi_in(&[0xF8])
+            };
+            app.tx.try_send(ev).unwrap();
+        }
+        app.drain_rx();
+        let deltas: Vec<Option<u64>> = app.log.iter().map(|ev| ev.delta_us).collect();
+        assert_eq!(deltas, [None, Some(2_500), Some(100)]);
+    }
+
+    #[test]
+    fn velocity_histogram_counts_every_note_on_of_a_packet() {
+        let mut app = test_app();
+        let key = test_key(MidiKind::Input);
+        app.count_velocities(&key, &[0x90, 60, 1, 62, 127, 64, 0]);
+        let hist = app.velocity_hist[&key];
+        assert_eq!((hist[0], hist[VELOCITY_BINS - 1]), (1, 1));
+        assert_eq!(hist.iter().sum::<u64>(), 2);
+    }
+
+    fn device(name: &str, kind: MidiKind, index: usize) -> DeviceItem {
+        DeviceItem {
+            key: DeviceKey {
+                name: name.to_string(),
+                kind,
+                instance: 0,
+            },
+            index,
+        }
+    }
+
+    #[test]
+    fn selection_survives_an_empty_device_list() {
+        let mut app = test_app();
+        let ports = || {
+            vec![
+                device("Alpha", MidiKind::Input, 0),
+                device("Beta", MidiKind::Input, 1),
+                device("Gamma", MidiKind::Output, 0),
+            ]
+        };
+        app.apply_device_list(ports());
+        app.selected = app
+            .devices
+            .iter()
+            .position(|d| d.key.name == "Beta")
+            .unwrap();
+        // Everything unplugged: nothing to select, navigation stays in range
+        app.apply_device_list(Vec::new());
+        assert_eq!(app.selected, 0);
+        assert!(app.selected_device().is_none());
+        app.select_down();
+        app.select_up();
+        assert_eq!(app.selected, 0);
+        // Plugged back in: the previous pick is selected again
+        app.apply_device_list(ports());
+        assert_eq!(app.devices[app.selected].key.name, "Beta");
+    }
+
+    #[test]
+    fn startup_with_no_devices_selects_the_saved_one_when_it_appears() {
+        let persisted = Persisted {
+            last_device: Some(device("Gamma", MidiKind::Output, 0).key),
+            ..Persisted::default()
+        };
+        let mut app = App::with_state(Config::default(), persisted, Vec::new(), false);
+        assert!(app.devices.is_empty());
+        app.apply_device_list(vec![
+            device("Alpha", MidiKind::Input, 0),
+            device("Gamma", MidiKind::Output, 0),
+        ]);
+        assert_eq!(app.devices[app.selected].key.name, "Gamma");
+    }
+
+    #[test]
+    fn paused_scroll_stays_inside_a_full_log() {
+        let mut app = test_app();
+        app.config.log_capacity = 8;
+        app.log_capacity = 8;
+        app.log_paused = true;
+        for note in 0..100 {
+            app.push_log(midi_in(&[0x90, note, 100]));
+        }
+        assert_eq!(app.log.len(), 8);
+        assert!(app.log_scroll < app.log.len());
+    }
+
+    #[test]
+    fn midi_bindings_wait_for_modals_to_close() {
+        let mut app = test_app();
+        app.midi_bindings.insert((0x90, 36), Action::Panic);
+        app.confirm = Some(Confirm::Quit);
+        app.check_midi_binding(&[0x90, 36, 100]);
+        assert!(app.pending_actions.is_empty());
+        app.confirm = None;
+        app.check_midi_binding(&[0x90, 36, 100]);
+        assert_eq!(app.pending_actions.pop_front(), Some(Action::Panic));
+    }
+
+    #[test]
+    fn midi_bindings_fire_inside_packed_packets() {
+        let mut app = test_app();
+        app.midi_bindings.insert((0x90, 36), Action::Panic);
+        app.midi_bindings.insert((0xB0, 20), Action::Freeze);
+        app.check_midi_binding(&[0x90, 30, 100, 36, 100]);
+        app.check_midi_binding(&[0xB0, 7, 100, 20, 127]);
+        assert_eq!(
+            app.pending_actions.drain(..).collect::<Vec<_>>(),
+            vec![Action::Panic, Action::Freeze]
+        );
+        app.controls.update(&[0xB0, 7, 100, 20, 127]);
+        assert_eq!(app.controls.value(0xB0, 20), Some(127));
+    }
+
+    #[test]
+    fn macros_fire_and_describe_one_message_of_a_packet() {
+        let mut app = test_app();
+        app.config.macros.push(MidiMacro {
+            note: Some(36),
+            cc: None,
+            channel: None,
+            command: "true".to_string(),
+            min_interval_ms: 500,
+        });
+        app.run_macros(&[0x90, 30, 100, 36, 100]);
+        assert!(app.macro_fired.contains_key(&0));
+        let what = midi::decode_midi(&[0x90, 36, 100], app.c_octave);
+        assert!(app.log.iter().any(|e| matches!(
+            &e.kind,
+            EventKind::Status(msg) if *msg == format!("Macro ({what}): true")
+        )));
+    }
+
+    #[test]
+    fn held_notes_follow_running_status_packets() {
+        let mut app = test_app();
+        let key = test_key(MidiKind::Input);
+        app.track_held_notes(key.clone(), &[0x90, 60, 64, 62, 100]);
+        assert_eq!(
+            (app.held_notes[&key][60], app.held_notes[&key][62]),
+            (64, 100)
+        );
+        app.track_held_notes(key.clone(), &[0x90, 60, 0, 62, 0]);
+        assert!(app.held_notes[&key].iter().all(|&v| v == 0));
+        app.mpe
+            .entry(key.clone())
+            .or_default()
+            .update(&[0x92, 60, 64, 62, 100]);
+        assert_eq!(app.mpe[&key].active_notes().len(), 2);
+    }
+
+    #[test]
+    fn data_entry_shows_one_row_per_change() {
+        let mut app = test_app();
+        for (cc, v) in [(99, 2), (98, 44), (6, 1), (38, 5), (6, 7)] {
+            app.tx.try_send(midi_in(&[0xB0, cc, v])).unwrap();
+        }
+        app.drain_rx();
+        let shown = |app: &App| -> Vec<String> {
+            app.log
+                .iter()
+                .filter(|e| app.log_visible(e))
+                .map(|e| app.describe(e))
+                .collect()
+        };
+        assert_eq!(shown(&app), ["NRPN ch1 param=300 value=133 (1/5)"]);
+        // No LSB follows the second MSB, so it shows alone once the wait ends
+        app.flush_held_params(Instant::now() + PARAM_LSB_WAIT);
+        assert_eq!(
+            shown(&app),
+            [
+                "NRPN ch1 param=300 value=133 (1/5)",
+                "NRPN ch1 param=300 value=896 (7/0)"
+            ]
+        );
+    }
+
+    #[test]
+    fn packed_parameter_sequences_are_read_message_by_message() {
+        let mut app = test_app();
+        app.tx
+            .try_send(midi_in(&[0xB0, 101, 0, 100, 2, 6, 64, 38, 0]))
+            .unwrap();
+        // An MSB that the same packet ends without an LSB
+        app.tx.try_send(midi_in(&[0xB0, 6, 66, 7, 100])).unwrap();
+        app.drain_rx();
+        let params: Vec<_> = app.log.iter().map(|e| e.param).collect();
+        assert!(matches!(
+            params[..],
+            [
+                Some(ParamEvent::Value(ParamChange {
+                    param: 2,
+                    value: 8192,
+                    ..
+                })),
+                Some(ParamEvent::Value(ParamChange {
+                    param: 2,
+                    value: 8448,
+                    ..
+                })),
+            ]
+        ));
+        assert!(app.param_held_at.is_empty());
+    }
+
+    #[test]
+    fn latency_is_timed_by_arrival_stamps() {
+        let start = Instant::now();
+        let mut probe = LatencyProbe {
+            output: test_key(MidiKind::Output),
+            input: test_key(MidiKind::Input),
+            samples: Vec::new(),
+            lost: 0,
+            pending: None,
+            next_at: start,
+            stamp_base: None,
+        };
+        // Stamp 0 is at `start`; the callback ran 3 ms late, then 1 ms late
+        for (stamp, late_ms) in [(10_000, 3), (20_000, 1)] {
+            probe.observe(&LoggedEvent {
+                at: start + Duration::from_micros(stamp) + Duration::from_millis(late_ms),
+                stamp,
+                ..midi_in(&[0xF8])
+            });
+        }
+        assert_eq!(probe.stamp_base, Some(start + Duration::from_millis(1)));
+        // Sent 1 ms after the base, back 3 and 5 ms later
+        let sent = start + Duration::from_millis(2);
+        probe.samples = vec![(sent, 4_000), (sent, 6_000)];
+        assert_eq!(
+            probe.report(),
+            "min 3.00 ms, avg 4.00 ms, max 5.00 ms, jitter 1.00 ms over 2 samples (0 lost)"
+        );
+    }
+
+    #[test]
+    fn hidden_ports_are_not_reopened() {
+        let key = test_key(MidiKind::Input);
+        let persisted = Persisted {
+            open_devices: vec![key.clone()],
+            hidden_devices: HashSet::from([key.clone()]),
+            ..Persisted::default()
+        };
+        let devices = vec![DeviceItem {
+            key: key.clone(),
+            index: 0,
+        }];
+        let app = App::with_state(Config::default(), persisted, devices, true);
+        let statuses: Vec<&str> = app
+            .log
+            .iter()
+            .filter_map(|e| match &e.kind {
+                EventKind::Status(msg) => Some(msg.as_str()),
+                EventKind::Midi => None,
+            })
+            .collect();
+        assert!(statuses.contains(&"Not reopened (hidden): Test Port"));
+        assert!(!statuses.iter().any(|m| m.contains("Reopen failed")));
+    }
+
+    #[test]
+    fn split_sysex_is_reassembled() {
+        let mut app = test_app();
+        assert!(app.reassemble_sysex(midi_in(&[0xF0, 0x7E, 0x7F])).is_none());
+        let whole = app.reassemble_sysex(midi_in(&[0x06, 0x01, 0xF7])).unwrap();
+        assert_eq!(whole.data, [0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7]);
+        assert!(app.sysex_partial.is_empty());
+    }
+
+    #[test]
+    fn truncated_sysex_is_flushed_as_incomplete() {
+        let mut app = test_app();
+        app.config.sysex_timeout_ms = 0;
+        assert!(app.reassemble_sysex(midi_in(&[0xF0, 0x43, 0x10])).is_none());
+        app.flush_stale_sysex();
+        assert!(app.sysex_partial.is_empty());
+        let flushed = app.log.back().unwrap();
+        assert_eq!(flushed.data, [0xF0, 0x43, 0x10]);
+        assert!(flushed.display().contains("(incomplete)"));
+    }
+
+    #[test]
+    fn status_byte_cuts_sysex_short() {
+        let mut app = test_app();
+        assert!(app.reassemble_sysex(midi_in(&[0xF0, 0x43])).is_none());
+        let note = app.reassemble_sysex(midi_in(&[0x90, 60, 100])).unwrap();
+        assert_eq!(note.data, [0x90, 60, 100]);
+        assert!(app.log.back().unwrap().is_incomplete_sysex());
+    }
 }
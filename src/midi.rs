@@ -0,0 +1,999 @@
+//! Pure MIDI helpers: naming of notes and controllers, message decoding, and
+//! the per-message state behind parameters, control presses and alerts.
+
+use std::{
+    borrow::Cow,
+    collections::{HashMap, VecDeque},
+    fmt,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/// Friendly names for one device's notes (drum pads) and controllers (knobs).
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeviceNames {
+    pub notes: HashMap<u8, String>,
+    pub ccs: HashMap<u8, String>,
+}
+
+/// Octave number of `note` when note 60 is named C`c_octave` (4 is
+/// scientific pitch, where note 0 is C-1; Yamaha uses 3, some others 5).
+pub fn octave_of(note: u8, c_octave: i8) -> i32 {
+    note as i32 / 12 + (c_octave as i32 - 5)
+}
+
+/// Pitch name such as `C4`, with the middle-C convention from [`octave_of`].
+pub fn note_name(note: u8, c_octave: i8) -> String {
+    format!(
+        "{}{}",
+        NOTE_NAMES[note as usize % 12],
+        octave_of(note, c_octave)
+    )
+}
+
+/// Lowest output of [`VelocityCurve::Compressed`], which maps 1-127 onto
+/// this-127.
+const COMPRESSED_FLOOR: u8 = 40;
+
+/// Shaping of outgoing Note On velocities.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VelocityCurve {
+    #[default]
+    Linear,
+    /// Squared: soft playing gets softer, hard stays hard
+    Exponential,
+    /// Every note at this velocity (1-127)
+    Fixed(u8),
+    /// Narrower dynamic range with soft notes lifted
+    Compressed,
+}
+
+impl VelocityCurve {
+    pub fn next(self) -> Self {
+        match self {
+            VelocityCurve::Linear => VelocityCurve::Exponential,
+            VelocityCurve::Exponential => VelocityCurve::Compressed,
+            VelocityCurve::Compressed => VelocityCurve::Fixed(100),
+            VelocityCurve::Fixed(_) => VelocityCurve::Linear,
+        }
+    }
+
+    pub fn name(self) -> String {
+        match self {
+            VelocityCurve::Linear => "linear".to_string(),
+            VelocityCurve::Exponential => "exponential".to_string(),
+            VelocityCurve::Fixed(v) => format!("fixed {v}"),
+            VelocityCurve::Compressed => "compressed".to_string(),
+        }
+    }
+}
+
+/// Note On velocity after `curve`; 0 (a Note Off) always stays 0 and other
+/// velocities never drop to it.
+pub fn apply_velocity_curve(vel: u8, curve: VelocityCurve) -> u8 {
+    if vel == 0 {
+        return 0;
+    }
+    let vel = vel.min(127);
+    let out = match curve {
+        VelocityCurve::Linear => vel,
+        VelocityCurve::Exponential => ((vel as u32 * vel as u32 + 63) / 127) as u8,
+        VelocityCurve::Fixed(v) => v,
+        VelocityCurve::Compressed => {
+            let span = (127 - COMPRESSED_FLOOR) as u32;
+            COMPRESSED_FLOOR + ((vel as u32 - 1) * span / 126) as u8
+        }
+    };
+    out.clamp(1, 127)
+}
+
+/// Chord qualities by interval set above the root, as pitch-class bitmasks.
+const CHORDS: [(u16, &str); 14] = [
+    (1 << 0 | 1 << 4 | 1 << 7, "maj"),
+    (1 << 0 | 1 << 3 | 1 << 7, "min"),
+    (1 << 0 | 1 << 3 | 1 << 6, "dim"),
+    (1 << 0 | 1 << 4 | 1 << 8, "aug"),
+    (1 << 0 | 1 << 2 | 1 << 7, "sus2"),
+    (1 << 0 | 1 << 5 | 1 << 7, "sus4"),
+    (1 << 0 | 1 << 4 | 1 << 7 | 1 << 9, "6"),
+    (1 << 0 | 1 << 3 | 1 << 7 | 1 << 9, "m6"),
+    (1 << 0 | 1 << 4 | 1 << 7 | 1 << 10, "7"),
+    (1 << 0 | 1 << 4 | 1 << 7 | 1 << 11, "maj7"),
+    (1 << 0 | 1 << 3 | 1 << 7 | 1 << 10, "m7"),
+    (1 << 0 | 1 << 3 | 1 << 7 | 1 << 11, "mMaj7"),
+    (1 << 0 | 1 << 3 | 1 << 6 | 1 << 10, "m7b5"),
+    (1 << 0 | 1 << 3 | 1 << 6 | 1 << 9, "dim7"),
+];
+
+/// Best-effort name such as `C maj7` or `E min/G` for held notes (sorted
+/// or not). Needs three or more distinct pitch classes; the lowest note is
+/// tried as the root first, otherwise it is named as the bass.
+pub fn chord_name(notes: &[u8]) -> Option<String> {
+    let bass = *notes.iter().min()? % 12;
+    let classes = notes.iter().fold(0u16, |acc, n| acc | 1 << (n % 12));
+    if classes.count_ones() < 3 {
+        return None;
+    }
+    std::iter::once(bass)
+        .chain((0..12).filter(|&pc| pc != bass && classes & 1 << pc != 0))
+        .find_map(|root| {
+            // Rotate so the candidate root becomes bit 0
+            let rel = (classes >> root | classes << (12 - root)) & 0xFFF;
+            let (_, quality) = CHORDS.iter().find(|(set, _)| *set == rel)?;
+            let name = format!("{} {quality}", NOTE_NAMES[root as usize]);
+            Some(if root == bass {
+                name
+            } else {
+                format!("{name}/{}", NOTE_NAMES[bass as usize])
+            })
+        })
+}
+
+/// General MIDI names for the commonly used controller numbers.
+pub fn standard_cc_name(cc: u8) -> Option<&'static str> {
+    let name = match cc {
+        0 => "Bank Select",
+        1 => "Modulation",
+        2 => "Breath",
+        4 => "Foot Pedal",
+        5 => "Portamento Time",
+        6 => "Data Entry",
+        7 => "Volume",
+        8 => "Balance",
+        10 => "Pan",
+        11 => "Expression",
+        32 => "Bank Select LSB",
+        38 => "Data Entry LSB",
+        64 => "Sustain",
+        65 => "Portamento",
+        66 => "Sostenuto",
+        67 => "Soft Pedal",
+        71 => "Resonance",
+        72 => "Release Time",
+        73 => "Attack Time",
+        74 => "Filter Cutoff",
+        91 => "Reverb",
+        93 => "Chorus",
+        98 => "NRPN LSB",
+        99 => "NRPN MSB",
+        100 => "RPN LSB",
+        101 => "RPN MSB",
+        120 => "All Sound Off",
+        121 => "Reset All Controllers",
+        123 => "All Notes Off",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Number of data bytes following a status byte, or None for SysEx/undefined.
+fn data_len(status: u8) -> Option<usize> {
+    match status {
+        0x80..=0xBF | 0xE0..=0xEF => Some(2),
+        0xC0..=0xDF => Some(1),
+        0xF1 | 0xF3 => Some(1),
+        0xF2 => Some(2),
+        0xF6 | 0xF8..=0xFF => Some(0),
+        _ => None,
+    }
+}
+
+/// A Note On or Note Off. By the spec a Note On with velocity 0 is a Note
+/// Off, and many devices send it that way to stay in running status, so it
+/// comes back as `Off` with `via_vel0` set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NoteEvent {
+    On {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+    },
+    Off {
+        channel: u8,
+        note: u8,
+        velocity: u8,
+        via_vel0: bool,
+    },
+}
+
+impl NoteEvent {
+    pub fn channel(self) -> u8 {
+        match self {
+            NoteEvent::On { channel, .. } | NoteEvent::Off { channel, .. } => channel,
+        }
+    }
+
+    pub fn note(self) -> u8 {
+        match self {
+            NoteEvent::On { note, .. } | NoteEvent::Off { note, .. } => note,
+        }
+    }
+}
+
+/// The note event of a three-byte Note On/Off message; every place that
+/// tracks notes goes through here so all agree on velocity 0. Packets that
+/// pack several messages go through [`split_messages`] first.
+pub fn note_event(message: &[u8]) -> Option<NoteEvent> {
+    let &[status, note, velocity] = message else {
+        return None;
+    };
+    let channel = status & 0x0F;
+    match status & 0xF0 {
+        0x90 if velocity > 0 => Some(NoteEvent::On {
+            channel,
+            note,
+            velocity,
+        }),
+        0x80 | 0x90 => Some(NoteEvent::Off {
+            channel,
+            note,
+            velocity,
+            via_vel0: status & 0xF0 == 0x90,
+        }),
+        _ => None,
+    }
+}
+
+/// Running status across the messages of one input stream: a message that
+/// arrives without its status byte gets the last channel status put back.
+/// A packet packing several messages stays one packet; pass it through
+/// [`split_messages`] to get them one by one. System common messages
+/// (SysEx included) cancel it and realtime bytes leave it alone, per the spec;
+/// SysEx continuation chunks therefore pass through untouched.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RunningStatus {
+    last: Option<u8>,
+}
+
+impl RunningStatus {
+    pub fn complete<'a>(&mut self, message: &'a [u8]) -> Cow<'a, [u8]> {
+        let out = match (message.first(), self.last) {
+            (Some(&b), Some(status)) if b < 0x80 => {
+                let mut full = Vec::with_capacity(message.len() + 1);
+                full.push(status);
+                full.extend_from_slice(message);
+                Cow::Owned(full)
+            }
+            _ => Cow::Borrowed(message),
+        };
+        // The last status byte of a packed message is what runs on
+        for &b in message.iter().filter(|b| **b >= 0x80) {
+            match b {
+                0x80..=0xEF => self.last = Some(b),
+                0xF0..=0xF7 => self.last = None,
+                _ => {}
+            }
+        }
+        out
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParamKind {
+    Rpn,
+    Nrpn,
+}
+
+/// A registered or non-registered parameter set through Data Entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParamChange {
+    pub kind: ParamKind,
+    /// 0-based
+    pub channel: u8,
+    /// 14-bit parameter number, MSB (CC 99/101) and LSB (CC 98/100)
+    pub param: u16,
+    /// 14-bit value, MSB (CC 6) and LSB (CC 38)
+    pub value: u16,
+}
+
+impl fmt::Display for ParamChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            ParamKind::Rpn => "RPN",
+            ParamKind::Nrpn => "NRPN",
+        };
+        write!(f, "{kind} ch{} param={}", self.channel + 1, self.param)?;
+        let name = match (self.kind, self.param) {
+            (ParamKind::Rpn, 0) => Some("Pitch Bend Range"),
+            (ParamKind::Rpn, 1) => Some("Fine Tuning"),
+            (ParamKind::Rpn, 2) => Some("Coarse Tuning"),
+            (ParamKind::Rpn, 5) => Some("Modulation Depth Range"),
+            (ParamKind::Rpn, 6) => Some("MPE Configuration"),
+            _ => None,
+        };
+        if let Some(name) = name {
+            write!(f, " ({name})")?;
+        }
+        write!(
+            f,
+            " value={} ({}/{})",
+            self.value,
+            self.value >> 7,
+            self.value & 0x7F
+        )
+    }
+}
+
+/// How a CC takes part in an RPN/NRPN sequence.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParamEvent {
+    /// CC 98-101 picking the parameter
+    Select,
+    /// CC 6 waiting to see whether a CC 38 completes the value
+    Held,
+    /// CC 6 or 38 setting the selected parameter
+    Value(ParamChange),
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct ParamState {
+    kind: Option<ParamKind>,
+    msb: u8,
+    lsb: u8,
+    value_msb: u8,
+    // A CC 6 was read and no CC 38 yet
+    held: bool,
+}
+
+/// Per-channel RPN/NRPN selection of one input stream, so Data Entry CCs
+/// can be read as the parameter change they make. A CC 6 is held until its
+/// CC 38, so a full 14-bit value is one change rather than two; the next other
+/// message on the channel, or [`flush`](Self::flush), ends the wait.
+#[derive(Clone, Debug, Default)]
+pub struct ParamTracker {
+    channels: [ParamState; 16],
+}
+
+impl ParamTracker {
+    /// How `message` takes part in a parameter sequence, and the change of a
+    /// held CC 6 that `message` ends without an LSB. Give it one message at a
+    /// time; see [`split_messages`].
+    pub fn update(&mut self, message: &[u8]) -> (Option<ParamEvent>, Option<ParamChange>) {
+        let Some(&status) = message.first().filter(|s| (0x80..0xF0).contains(*s)) else {
+            // Realtime and system messages don't interrupt a channel's sequence
+            return (None, None);
+        };
+        let is_lsb = matches!(*message, [s, 38, _] if s & 0xF0 == 0xB0);
+        let ended = if is_lsb {
+            None
+        } else {
+            self.end_hold(status & 0x0F)
+        };
+        (self.read(message), ended)
+    }
+
+    /// [`Self::update`] for each message of a packet: the packet's part in a
+    /// parameter sequence, and the changes of CC 6s held by earlier packets
+    /// that it ends without an LSB. A CC 6 that a later message of the same
+    /// packet ends comes back as the packet's own value.
+    pub fn update_packet(&mut self, packet: &[u8]) -> (Option<ParamEvent>, Vec<ParamChange>) {
+        let mut param = None;
+        let mut held_channel = None;
+        let mut ended_before = Vec::new();
+        for message in split_messages(packet) {
+            let (event, ended) = self.update(&message);
+            if let Some(change) = ended {
+                if held_channel == Some(change.channel) {
+                    param = Some(ParamEvent::Value(change));
+                    held_channel = None;
+                } else {
+                    ended_before.push(change);
+                }
+            }
+            if event == Some(ParamEvent::Held) {
+                held_channel = message.first().map(|s| s & 0x0F);
+            }
+            param = event.or(param);
+        }
+        (param, ended_before)
+    }
+
+    /// Ends every held CC 6, for when no CC 38 came in time.
+    pub fn flush(&mut self) -> Vec<ParamChange> {
+        (0..16).filter_map(|ch| self.end_hold(ch)).collect()
+    }
+
+    fn end_hold(&mut self, channel: u8) -> Option<ParamChange> {
+        let state = &mut self.channels[channel as usize];
+        if !std::mem::take(&mut state.held) {
+            return None;
+        }
+        Some(ParamChange {
+            kind: state.kind?,
+            channel,
+            param: (state.msb as u16) << 7 | state.lsb as u16,
+            value: (state.value_msb as u16) << 7,
+        })
+    }
+
+    fn read(&mut self, message: &[u8]) -> Option<ParamEvent> {
+        let &[status, cc, v] = message else {
+            return None;
+        };
+        if status & 0xF0 != 0xB0 {
+            return None;
+        }
+        let channel = status & 0x0F;
+        let state = &mut self.channels[channel as usize];
+        let mut select = |kind: ParamKind, msb: Option<u8>, lsb: Option<u8>| {
+            if state.kind != Some(kind) {
+                *state = ParamState {
+                    kind: Some(kind),
+                    ..ParamState::default()
+                };
+            }
+            state.msb = msb.unwrap_or(state.msb);
+            state.lsb = lsb.unwrap_or(state.lsb);
+            // RPN 127/127 is "no parameter", sent to guard against stray entry
+            if kind == ParamKind::Rpn && state.msb == 127 && state.lsb == 127 {
+                state.kind = None;
+            }
+            Some(ParamEvent::Select)
+        };
+        let value_lsb = match cc {
+            99 => return select(ParamKind::Nrpn, Some(v), None),
+            98 => return select(ParamKind::Nrpn, None, Some(v)),
+            101 => return select(ParamKind::Rpn, Some(v), None),
+            100 => return select(ParamKind::Rpn, None, Some(v)),
+            // A new MSB clears the LSB; wait for one before reporting it
+            6 => {
+                state.value_msb = v;
+                state.held = state.kind.is_some();
+                return state.kind.map(|_| ParamEvent::Held);
+            }
+            38 => {
+                state.held = false;
+                v
+            }
+            _ => return None,
+        };
+        let kind = state.kind?;
+        Some(ParamEvent::Value(ParamChange {
+            kind,
+            channel,
+            param: (state.msb as u16) << 7 | state.lsb as u16,
+            value: (state.value_msb as u16) << 7 | value_lsb as u16,
+        }))
+    }
+}
+
+/// Last value of each controller, to tell a CC press, its value rising
+/// through 64, from the further steps of a turned knob.
+#[derive(Debug, Default)]
+pub struct ControlTracker {
+    cc_values: HashMap<(u8, u8), u8>,
+}
+
+impl ControlTracker {
+    /// (status, note/controller) when the single `message` presses a
+    /// control: any Note On, or a CC whose value rises through 64, so a turned
+    /// knob counts once rather than on every step.
+    pub fn pressed(&self, message: &[u8]) -> Option<(u8, u8)> {
+        match *message {
+            [s, d1, v] if s & 0xF0 == 0x90 && v > 0 => Some((s, d1)),
+            [s, d1, v] if s & 0xF0 == 0xB0 && v >= 64 => {
+                let prev = self.cc_values.get(&(s, d1));
+                prev.is_none_or(|p| *p < 64).then_some((s, d1))
+            }
+            _ => None,
+        }
+    }
+
+    /// Records the CC values of a packet, once [`Self::pressed`] has seen it.
+    pub fn update(&mut self, packet: &[u8]) {
+        for message in split_messages(packet) {
+            if let [s, d1, v] = *message {
+                if s & 0xF0 == 0xB0 {
+                    self.cc_values.insert((s, d1), v);
+                }
+            }
+        }
+    }
+
+    /// Last value of controller `cc` under channel `status`.
+    pub fn value(&self, status: u8, cc: u8) -> Option<u8> {
+        self.cc_values.get(&(status, cc)).copied()
+    }
+}
+
+/// A learned message that raises an alert when seen again. Channel messages
+/// match on status plus first data byte (note/controller number); the value
+/// byte is ignored so any velocity or CC value triggers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AlertTrigger {
+    pub status: u8,
+    pub data1: Option<u8>,
+}
+
+impl AlertTrigger {
+    /// The trigger `message` would set when learned.
+    pub fn from_message(message: &[u8]) -> Option<Self> {
+        let &status = message.first()?;
+        let data1 = match status & 0xF0 {
+            0x80 | 0x90 | 0xA0 | 0xB0 => message.get(1).copied(),
+            _ => None,
+        };
+        Some(Self { status, data1 })
+    }
+
+    /// Whether any message of the packet is the learned one.
+    pub fn matches(&self, packet: &[u8]) -> bool {
+        split_messages(packet)
+            .iter()
+            .any(|m| Self::from_message(m) == Some(*self))
+    }
+
+    /// Hex bytes matched, e.g. `90 3C`.
+    pub fn describe(&self) -> String {
+        match self.data1 {
+            Some(d) => format!("{:02X} {:02X}", self.status, d),
+            None => format!("{:02X}", self.status),
+        }
+    }
+}
+
+/// Clock pulse intervals [`ClockTempo`] averages over: one quarter note.
+const CLOCK_PULSES: usize = 24;
+/// Tempo is dropped when no clock pulse arrives for this long.
+const CLOCK_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Tempo estimate for one input from its MIDI clock (0xF8, 24 per quarter
+/// note), averaged over the last `CLOCK_PULSES` pulse intervals.
+#[derive(Debug, Default)]
+pub struct ClockTempo {
+    // midir stamps (µs) of the most recent pulses
+    pulses: VecDeque<u64>,
+    last_seen: Option<Instant>,
+}
+
+impl ClockTempo {
+    /// Adds a pulse with its midir `stamp`, received at `now`.
+    pub fn record(&mut self, stamp: u64, now: Instant) {
+        // A stamp going backwards means the port was reopened
+        if self.pulses.back().is_some_and(|last| stamp < *last) {
+            self.pulses.clear();
+        }
+        self.pulses.push_back(stamp);
+        while self.pulses.len() > CLOCK_PULSES + 1 {
+            self.pulses.pop_front();
+        }
+        self.last_seen = Some(now);
+    }
+
+    /// Start, stop and continue restart the estimate.
+    pub fn reset(&mut self) {
+        self.pulses.clear();
+        self.last_seen = None;
+    }
+
+    /// None until two pulses have arrived, or once the clock has gone quiet.
+    pub fn bpm(&self) -> Option<f64> {
+        if self.last_seen?.elapsed() > CLOCK_TIMEOUT {
+            return None;
+        }
+        let (first, last) = (self.pulses.front()?, self.pulses.back()?);
+        let intervals = self.pulses.len().checked_sub(1).filter(|n| *n > 0)?;
+        let pulse_us = (last - first) as f64 / intervals as f64;
+        (pulse_us > 0.0).then(|| 60_000_000.0 / (pulse_us * 24.0))
+    }
+}
+
+/// Decodes one callback's worth of bytes into readable text such as
+/// `Note On ch3 note=60 (C4) vel=100`. Several messages packed with running
+/// status are joined with `; `; anything unrecognized falls back to hex.
+pub fn decode_midi(message: &[u8], c_octave: i8) -> String {
+    decode_midi_named(message, None, c_octave)
+}
+
+/// Like [`decode_midi`], preferring the given device names for notes/CCs.
+pub fn decode_midi_named(message: &[u8], names: Option<&DeviceNames>, c_octave: i8) -> String {
+    let raw = || format!("{:02X?}", message);
+    let Some(&first) = message.first() else {
+        return raw();
+    };
+    if first == 0xF0 {
+        return decode_sysex(message);
+    }
+    if first < 0x80 {
+        // Data bytes without a status to run from
+        return raw();
+    }
+
+    let Some(parts) = split_packed(message) else {
+        return raw();
+    };
+    let parts: Vec<String> = parts
+        .into_iter()
+        .map(|(status, data)| decode_one(status, data, names, c_octave))
+        .collect();
+    parts.join("; ")
+}
+
+/// The messages packed into one packet, each whole: `90 3C 40 3E 00` (running
+/// status) gives `90 3C 40` and `90 3E 00`. Anything that doesn't split
+/// cleanly, SysEx included, comes back as the one message it was.
+pub fn split_messages(message: &[u8]) -> Vec<Cow<'_, [u8]>> {
+    match split_packed(message) {
+        Some(parts) if parts.len() > 1 => parts
+            .into_iter()
+            .map(|(status, data)| Cow::Owned([&[status], data].concat()))
+            .collect(),
+        _ => vec![Cow::Borrowed(message)],
+    }
+}
+
+/// (status, data bytes) of each message in a packet, or None for SysEx,
+/// stray data bytes and truncated messages.
+fn split_packed(message: &[u8]) -> Option<Vec<(u8, &[u8])>> {
+    let &first = message.first()?;
+    if first < 0x80 || first == 0xF0 {
+        return None;
+    }
+    let mut parts = Vec::new();
+    let mut status = first;
+    let mut i = 1;
+    loop {
+        let n = data_len(status)?;
+        parts.push((status, message.get(i..i + n)?));
+        i += n;
+        match message.get(i) {
+            None => return Some(parts),
+            // Running status: a data byte continues the previous channel status
+            Some(&b) if b < 0x80 && status < 0xF0 => {}
+            Some(&b) if b >= 0x80 && b != 0xF0 && b != 0xF7 => {
+                status = b;
+                i += 1;
+            }
+            Some(_) => return None,
+        }
+    }
+}
+
+/// Short name of the first message's type, as the decoder words it:
+/// `Note On`, `CC`, `SysEx`, ... A Note On with velocity 0 is a `Note Off`.
+pub fn message_type(message: &[u8]) -> &'static str {
+    let Some(&status) = message.first() else {
+        return "";
+    };
+    match status & 0xF0 {
+        // By the first message's velocity, so packed packets name it too
+        0x90 if message.get(2).is_some_and(|&v| v > 0) => "Note On",
+        0x80 | 0x90 => "Note Off",
+        0xA0 => "Poly Aftertouch",
+        0xB0 => "CC",
+        0xC0 => "Program Change",
+        0xD0 => "Channel Pressure",
+        0xE0 => "Pitch Bend",
+        _ => match status {
+            0xF0 => "SysEx",
+            0xF1 => "MTC Quarter Frame",
+            0xF2 => "Song Position",
+            0xF3 => "Song Select",
+            0xF6 => "Tune Request",
+            0xF8 => "Clock",
+            0xFA => "Start",
+            0xFB => "Continue",
+            0xFC => "Stop",
+            0xFE => "Active Sensing",
+            0xFF => "System Reset",
+            _ => "Unknown",
+        },
+    }
+}
+
+fn decode_one(status: u8, data: &[u8], names: Option<&DeviceNames>, c_octave: i8) -> String {
+    let ch = (status & 0x0F) + 1;
+    let note = |n: u8| {
+        let name = names
+            .and_then(|m| m.notes.get(&n).cloned())
+            .unwrap_or_else(|| note_name(n, c_octave));
+        format!("note={n} ({name})")
+    };
+    match (status & 0xF0, data) {
+        (0x80 | 0x90, &[n, v]) => match note_event(&[status, n, v]) {
+            Some(NoteEvent::Off { via_vel0: true, .. }) => {
+                format!("Note Off (via vel0) ch{ch} {}", note(n))
+            }
+            Some(NoteEvent::Off { .. }) => format!("Note Off ch{ch} {} vel={v}", note(n)),
+            _ => format!("Note On ch{ch} {} vel={v}", note(n)),
+        },
+        (0xA0, &[n, v]) => format!("Poly Aftertouch ch{ch} {} val={v}", note(n)),
+        (0xB0, &[cc, v]) => {
+            let name = names
+                .and_then(|m| m.ccs.get(&cc).map(String::as_str))
+                .or_else(|| standard_cc_name(cc));
+            match name {
+                Some(name) => format!("CC ch{ch} #{cc} ({name}) val={v}"),
+                None => format!("CC ch{ch} #{cc} val={v}"),
+            }
+        }
+        (0xC0, &[p]) => format!("Program Change ch{ch} prog={p}"),
+        (0xD0, &[v]) => format!("Channel Pressure ch{ch} val={v}"),
+        (0xE0, &[lsb, msb]) => {
+            let value = ((msb as i32) << 7 | lsb as i32) - 8192;
+            format!("Pitch Bend ch{ch} {value:+}")
+        }
+        _ => decode_system(status, data),
+    }
+}
+
+fn decode_system(status: u8, data: &[u8]) -> String {
+    match (status, data) {
+        (0xF1, &[v]) => format!("MTC Quarter Frame type={} val={}", v >> 4, v & 0x0F),
+        (0xF2, &[lsb, msb]) => format!("Song Position {}", (msb as u16) << 7 | lsb as u16),
+        (0xF3, &[s]) => format!("Song Select {s}"),
+        (0xF6, _) => "Tune Request".to_string(),
+        (0xF8, _) => "Clock".to_string(),
+        (0xFA, _) => "Start".to_string(),
+        (0xFB, _) => "Continue".to_string(),
+        (0xFC, _) => "Stop".to_string(),
+        (0xFE, _) => "Active Sensing".to_string(),
+        (0xFF, _) => "System Reset".to_string(),
+        _ => format!("{:02X?}", [&[status], data].concat()),
+    }
+}
+
+fn decode_sysex(message: &[u8]) -> String {
+    let complete = message.last() == Some(&0xF7) && message.len() > 1;
+    let id = match message.get(1) {
+        Some(0x7E) => "Universal Non-Realtime".to_string(),
+        Some(0x7F) => "Universal Realtime".to_string(),
+        // Three-byte manufacturer IDs start with 00
+        Some(0x00) => match message.get(2..4) {
+            Some(&[a, b]) => format!("id=00 {a:02X} {b:02X}"),
+            _ => "id=00".to_string(),
+        },
+        Some(&b) if b < 0x80 => format!("id={b:02X}"),
+        _ => "id=?".to_string(),
+    };
+    let suffix = if complete { "" } else { " (incomplete)" };
+    format!("SysEx {id} len={}{suffix}", message.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packed_parameter_packets_are_read_per_message() {
+        let mut params = ParamTracker::default();
+        let (param, ended) = params.update_packet(&[0xB0, 101, 0, 100, 0, 6, 2]);
+        assert_eq!(param, Some(ParamEvent::Held));
+        assert!(ended.is_empty());
+        // Ends the earlier packet's hold, then holds and ends its own
+        let (param, ended) = params.update_packet(&[0xB0, 7, 100, 6, 3, 7, 90]);
+        assert!(matches!(
+            param,
+            Some(ParamEvent::Value(ParamChange { value: 384, .. }))
+        ));
+        assert!(matches!(ended[..], [ParamChange { value: 256, .. }]));
+    }
+
+    #[test]
+    fn clock_tempo_averages_pulse_stamps() {
+        let mut tempo = ClockTempo::default();
+        let now = Instant::now();
+        // 120 BPM is 2 quarter notes, 48 pulses, per second
+        for i in 0..30u64 {
+            tempo.record(i * 1_000_000 / 48, now);
+        }
+        assert!((tempo.bpm().unwrap() - 120.0).abs() < 0.1);
+        tempo.record(0, now);
+        assert_eq!(tempo.bpm(), None);
+    }
+
+    #[test]
+    fn controls_press_on_note_on_and_rising_cc() {
+        let mut controls = ControlTracker::default();
+        assert_eq!(controls.pressed(&[0x90, 36, 100]), Some((0x90, 36)));
+        assert_eq!(controls.pressed(&[0x90, 36, 0]), None);
+        assert_eq!(controls.pressed(&[0xB0, 20, 100]), Some((0xB0, 20)));
+        controls.update(&[0xB0, 7, 10, 20, 100]);
+        assert_eq!(controls.value(0xB0, 20), Some(100));
+        assert_eq!(controls.pressed(&[0xB0, 20, 110]), None);
+        controls.update(&[0xB0, 20, 30]);
+        assert_eq!(controls.pressed(&[0xB0, 20, 64]), Some((0xB0, 20)));
+    }
+
+    #[test]
+    fn alerts_match_any_message_of_a_packet() {
+        let trigger = AlertTrigger::from_message(&[0x90, 62, 100]).unwrap();
+        assert!(trigger.matches(&[0x90, 60, 100, 62, 1]));
+        assert!(trigger.matches(&[0xB0, 7, 100, 0x90, 62, 1]));
+        assert!(!trigger.matches(&[0x90, 60, 100, 61, 1]));
+    }
+
+    #[test]
+    fn octave_follows_the_middle_c_convention() {
+        let cases = [
+            (0, 3, -2),
+            (0, 4, -1),
+            (0, 5, 0),
+            (60, 3, 3),
+            (60, 4, 4),
+            (60, 5, 5),
+            (127, 3, 8),
+            (127, 4, 9),
+            (127, 5, 10),
+        ];
+        for (note, c_octave, octave) in cases {
+            assert_eq!(octave_of(note, c_octave), octave, "note {note} c{c_octave}");
+        }
+        assert_eq!(note_name(60, 3), "C3");
+        assert_eq!(note_name(127, 4), "G9");
+    }
+
+    #[test]
+    fn note_on_with_velocity_zero_is_a_note_off() {
+        assert_eq!(
+            note_event(&[0x91, 60, 100]),
+            Some(NoteEvent::On {
+                channel: 1,
+                note: 60,
+                velocity: 100
+            })
+        );
+        assert_eq!(
+            note_event(&[0x90, 60, 0]),
+            Some(NoteEvent::Off {
+                channel: 0,
+                note: 60,
+                velocity: 0,
+                via_vel0: true
+            })
+        );
+        assert_eq!(
+            note_event(&[0x80, 60, 64]),
+            Some(NoteEvent::Off {
+                channel: 0,
+                note: 60,
+                velocity: 64,
+                via_vel0: false
+            })
+        );
+        assert_eq!(note_event(&[0xB0, 60, 0]), None);
+        // One message at a time; packed packets are split first
+        assert_eq!(note_event(&[0x90, 60, 64, 62, 0]), None);
+    }
+
+    #[test]
+    fn decode_names_note_off_via_velocity_zero() {
+        assert_eq!(
+            decode_midi(&[0x90, 0x3C, 0x00], 4),
+            "Note Off (via vel0) ch1 note=60 (C4)"
+        );
+        assert_eq!(
+            decode_midi(&[0x90, 0x3C, 0x40], 4),
+            "Note On ch1 note=60 (C4) vel=64"
+        );
+    }
+
+    #[test]
+    fn packed_messages_split_like_the_decoder_reads_them() {
+        let packet = [0x90, 0x3C, 0x40, 0x3E, 0x00];
+        assert_eq!(
+            decode_midi(&packet, 4),
+            "Note On ch1 note=60 (C4) vel=64; Note Off (via vel0) ch1 note=62 (D4)"
+        );
+        let split = split_messages(&packet);
+        assert_eq!(split, [&[0x90, 0x3C, 0x40][..], &[0x90, 0x3E, 0x00]]);
+        let notes: Vec<_> = split.iter().filter_map(|m| note_event(m)).collect();
+        assert!(matches!(
+            notes[..],
+            [
+                NoteEvent::On { note: 60, .. },
+                NoteEvent::Off { note: 62, .. }
+            ]
+        ));
+        // A new status mid-packet, and a realtime byte
+        assert_eq!(
+            split_messages(&[0xB0, 7, 100, 0xC0, 5, 0xF8]),
+            [&[0xB0, 7, 100][..], &[0xC0, 5], &[0xF8]]
+        );
+        // Whatever doesn't split stays whole
+        assert_eq!(
+            split_messages(&[0xF0, 0x7E, 0xF7]),
+            [&[0xF0, 0x7E, 0xF7][..]]
+        );
+        assert_eq!(split_messages(&[0x90, 0x3C]), [&[0x90, 0x3C][..]]);
+    }
+
+    /// Select NRPN `param` on channel 1, then send `entry` CCs.
+    fn nrpn(
+        tracker: &mut ParamTracker,
+        param: u16,
+        entry: &[(u8, u8)],
+    ) -> Vec<(Option<ParamEvent>, Option<ParamChange>)> {
+        let mut out = Vec::new();
+        for (cc, v) in [(99, (param >> 7) as u8), (98, (param & 0x7F) as u8)]
+            .into_iter()
+            .chain(entry.iter().copied())
+        {
+            out.push(tracker.update(&[0xB0, cc, v]));
+        }
+        out
+    }
+
+    fn change(param: u16, value: u16) -> ParamChange {
+        ParamChange {
+            kind: ParamKind::Nrpn,
+            channel: 0,
+            param,
+            value,
+        }
+    }
+
+    #[test]
+    fn data_entry_msb_and_lsb_make_one_change() {
+        let mut tracker = ParamTracker::default();
+        let out = nrpn(&mut tracker, 300, &[(6, 2), (38, 5)]);
+        assert_eq!(out[2], (Some(ParamEvent::Held), None));
+        assert_eq!(
+            out[3],
+            (Some(ParamEvent::Value(change(300, 2 << 7 | 5))), None)
+        );
+        assert!(tracker.flush().is_empty());
+        // An LSB on its own adjusts the last MSB
+        assert_eq!(
+            tracker.update(&[0xB0, 38, 9]),
+            (Some(ParamEvent::Value(change(300, 2 << 7 | 9))), None)
+        );
+    }
+
+    #[test]
+    fn a_held_msb_ends_with_the_next_message_or_a_flush() {
+        let mut tracker = ParamTracker::default();
+        nrpn(&mut tracker, 300, &[(6, 2)]);
+        // Clock doesn't end it; a note on the channel does
+        assert_eq!(tracker.update(&[0xF8]), (None, None));
+        assert_eq!(
+            tracker.update(&[0x90, 60, 100]),
+            (None, Some(change(300, 2 << 7)))
+        );
+        nrpn(&mut tracker, 301, &[(6, 3)]);
+        assert_eq!(tracker.flush(), [change(301, 3 << 7)]);
+        assert!(tracker.flush().is_empty());
+        // Another channel's messages leave it waiting
+        nrpn(&mut tracker, 302, &[(6, 4)]);
+        assert_eq!(tracker.update(&[0x91, 60, 100]), (None, None));
+        // And a new selection ends it before switching
+        let (_, ended) = tracker.update(&[0xB0, 101, 0]);
+        assert_eq!(ended, Some(change(302, 4 << 7)));
+    }
+
+    #[test]
+    fn message_type_reads_the_first_message() {
+        assert_eq!(message_type(&[0x90, 60, 100]), "Note On");
+        assert_eq!(message_type(&[0x90, 60, 0]), "Note Off");
+        assert_eq!(message_type(&[0x90, 60, 100, 62, 0]), "Note On");
+        assert_eq!(message_type(&[0x80, 60, 64, 62, 64]), "Note Off");
+        assert_eq!(message_type(&[0xB0, 7, 100]), "CC");
+        assert_eq!(message_type(&[0xF0, 0x7E, 0xF7]), "SysEx");
+    }
+
+    #[test]
+    fn running_status_is_put_back() {
+        let mut running = RunningStatus::default();
+        // Nothing to run from yet
+        assert_eq!(&*running.complete(&[0x3C, 0x40]), [0x3C, 0x40]);
+        assert_eq!(&*running.complete(&[0x91, 0x3C, 0x40]), [0x91, 0x3C, 0x40]);
+        assert_eq!(&*running.complete(&[0x3E, 0x00]), [0x91, 0x3E, 0x00]);
+        // Realtime leaves it alone
+        assert_eq!(&*running.complete(&[0xF8]), [0xF8]);
+        assert_eq!(&*running.complete(&[0x40, 0x00]), [0x91, 0x40, 0x00]);
+        // The last status of a packed message runs on
+        running.complete(&[0x90, 0x3C, 0x40, 0xB2, 0x07, 0x64]);
+        assert_eq!(&*running.complete(&[0x0A, 0x40]), [0xB2, 0x0A, 0x40]);
+        // System common and SysEx cancel it, continuation chunks pass through
+        running.complete(&[0xF0, 0x7E, 0x7F]);
+        assert_eq!(&*running.complete(&[0x06, 0x01, 0xF7]), [0x06, 0x01, 0xF7]);
+        assert!(matches!(running.complete(&[0x3C, 0x40]), Cow::Borrowed(_)));
+    }
+}
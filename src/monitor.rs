@@ -0,0 +1,205 @@
+//! Opening inputs and receiving their messages, decoded, over a channel:
+//! the MIDI side of the TUI without any of the UI.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Arc,
+    },
+};
+
+use anyhow::{anyhow, Context, Result};
+use midir::{MidiInput, MidiInputConnection};
+
+use crate::{
+    devices::{resolve_port, DeviceItem, DeviceKey, MidiKind},
+    midi::{decode_midi, split_messages, RunningStatus},
+};
+
+/// One message from an open input.
+#[derive(Clone, Debug)]
+pub struct MonitorEvent {
+    pub device: DeviceKey,
+    /// midir timestamp in µs, from a per-connection base
+    pub stamp: u64,
+    /// One complete message, running status restored and packed packets
+    /// split. A SysEx the backend splits over several callbacks arrives in
+    /// pieces.
+    pub data: Vec<u8>,
+    c_octave: i8,
+}
+
+impl MonitorEvent {
+    /// Text as in the TUI log, e.g. `Note On ch1 note=60 (C4) vel=100`.
+    /// Decoded on the receiving side, so the MIDI callback only copies bytes.
+    pub fn decoded(&self) -> String {
+        decode_midi(&self.data, self.c_octave)
+    }
+}
+
+/// Events queued for the receiver before input is dropped.
+pub const CHANNEL_CAPACITY: usize = 4096;
+
+/// Open input connections feeding one channel of [`MonitorEvent`]s.
+/// Dropping the monitor closes every input.
+pub struct MidiMonitor {
+    client_name: String,
+    c_octave: i8,
+    tx: SyncSender<MonitorEvent>,
+    dropped: Arc<AtomicU64>,
+    conns: HashMap<DeviceKey, MidiInputConnection<()>>,
+}
+
+impl MidiMonitor {
+    /// A monitor whose connections show up as `client_name` to other MIDI
+    /// software, and the receiver for its events. The channel holds
+    /// [`CHANNEL_CAPACITY`] events; the MIDI threads never wait on a slow
+    /// receiver but drop and count what doesn't fit, see [`Self::dropped`].
+    pub fn new(client_name: &str) -> (Self, Receiver<MonitorEvent>) {
+        let (tx, rx) = mpsc::sync_channel(CHANNEL_CAPACITY);
+        let monitor = Self {
+            client_name: client_name.to_string(),
+            c_octave: 4,
+            tx,
+            dropped: Arc::new(AtomicU64::new(0)),
+            conns: HashMap::new(),
+        };
+        (monitor, rx)
+    }
+
+    /// Messages dropped so far because the channel was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Octave number of middle C in decoded note names; 4 by default.
+    /// Applies to inputs opened afterwards.
+    pub fn set_c_octave(&mut self, c_octave: i8) {
+        self.c_octave = c_octave;
+    }
+
+    /// Opens an input from [`collect_devices`](crate::collect_devices). All
+    /// messages are passed on, SysEx, clock and active sensing included.
+    pub fn open(&mut self, dev: &DeviceItem) -> Result<()> {
+        if self.conns.contains_key(&dev.key) {
+            return Ok(());
+        }
+        let mut inp = MidiInput::new(&self.client_name).context("create MidiInput failed")?;
+        inp.ignore(midir::Ignore::None);
+        let tx = self.tx.clone();
+        let dropped = Arc::clone(&self.dropped);
+        let key = dev.key.clone();
+        let c_octave = self.c_octave;
+        let (conn, _) = connect_input(inp, dev, &self.client_name, move |stamp, data| {
+            let ev = MonitorEvent {
+                device: key.clone(),
+                stamp,
+                data: data.to_vec(),
+                c_octave,
+            };
+            if let Err(TrySendError::Full(_)) = tx.try_send(ev) {
+                dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        })?;
+        self.conns.insert(dev.key.clone(), conn);
+        Ok(())
+    }
+
+    /// Closes one input; false if it wasn't open.
+    pub fn close(&mut self, key: &DeviceKey) -> bool {
+        self.conns.remove(key).is_some()
+    }
+
+    pub fn open_inputs(&self) -> impl Iterator<Item = &DeviceKey> {
+        self.conns.keys()
+    }
+}
+
+/// Connects `callback` to the port `dev` names, found by name and instance
+/// with [`resolve_port`] since indices shift on hotplug. The callback gets
+/// `(stamp, message)` one message at a time, as [`restore_running_status`]
+/// does. Returns the connection and the port's current index.
+pub fn connect_input<F>(
+    inp: MidiInput,
+    dev: &DeviceItem,
+    port_name: &str,
+    callback: F,
+) -> Result<(MidiInputConnection<()>, usize)>
+where
+    F: FnMut(u64, &[u8]) + Send + 'static,
+{
+    if dev.key.kind != MidiKind::Input {
+        return Err(anyhow!("{} is not an input", dev.key.label()));
+    }
+    let ports = inp.ports();
+    let names: Vec<String> = ports
+        .iter()
+        .enumerate()
+        .map(|(i, p)| inp.port_name(p).unwrap_or_else(|_| format!("Input #{i}")))
+        .collect();
+    let index = resolve_port(&names, dev);
+    let port = ports
+        .get(index)
+        .with_context(|| format!("input is gone: {}", dev.key.label()))?;
+    if names[index] != dev.key.name {
+        return Err(anyhow!(
+            "Input #{index} is now {:?}; refresh the list",
+            names[index]
+        ));
+    }
+    let conn = inp
+        .connect(port, port_name, restore_running_status(callback), ())
+        .map_err(|e| anyhow!("Failed to open input: {}: {e}", dev.key.name))?;
+    Ok((conn, index))
+}
+
+/// Wraps `callback` as a midir input callback that hands it one complete
+/// message at a time: running status is restored and packets packing several
+/// messages are split, all with the packet's stamp. For connections made some
+/// other way, such as virtual ports.
+pub fn restore_running_status<F>(
+    mut callback: F,
+) -> impl FnMut(u64, &[u8], &mut ()) + Send + 'static
+where
+    F: FnMut(u64, &[u8]) + Send + 'static,
+{
+    let mut running = RunningStatus::default();
+    move |stamp, message, _| {
+        for message in split_messages(&running.complete(message)) {
+            callback(stamp, &message);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packed_packets_reach_the_callback_one_message_at_a_time() {
+        let seen = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut callback = {
+            let seen = std::sync::Arc::clone(&seen);
+            restore_running_status(move |stamp, message: &[u8]| {
+                seen.lock().unwrap().push((stamp, message.to_vec()));
+            })
+        };
+        callback(1, &[0x90, 0x3C, 0x40, 0x3E, 0x00], &mut ());
+        callback(2, &[0x40, 0x7F], &mut ());
+        callback(3, &[0xB0, 0x07, 0x64, 0xC0, 0x05], &mut ());
+        callback(4, &[0xF0, 0x7E, 0x7F], &mut ());
+        callback(5, &[0x06, 0x01, 0xF7], &mut ());
+        let expected: Vec<(u64, Vec<u8>)> = vec![
+            (1, vec![0x90, 0x3C, 0x40]),
+            (1, vec![0x90, 0x3E, 0x00]),
+            (2, vec![0x90, 0x40, 0x7F]),
+            (3, vec![0xB0, 0x07, 0x64]),
+            (3, vec![0xC0, 0x05]),
+            (4, vec![0xF0, 0x7E, 0x7F]),
+            (5, vec![0x06, 0x01, 0xF7]),
+        ];
+        assert_eq!(*seen.lock().unwrap(), expected);
+    }
+}
@@ -0,0 +1,272 @@
+//! Per-channel state for MPE (MIDI Polyphonic Expression) controllers.
+//!
+//! MPE gives each sounding note a channel of its own inside a zone, so pitch
+//! bend, channel pressure and CC 74 ("slide") apply to that note alone. A
+//! lower zone has its master on channel 1 and members from channel 2 up; an
+//! upper zone has its master on channel 16 and members from 15 down. Zones
+//! follow the MPE Configuration Message (RPN 6) and default to a lower zone
+//! of all 15 members, which is what most controllers send without asking.
+
+use crate::midi::{note_event, split_messages, NoteEvent};
+
+/// Members of the default lower zone.
+const DEFAULT_MEMBERS: u8 = 15;
+/// Pitch bend ranges in semitones that MPE assumes until RPN 0 says otherwise.
+const MEMBER_BEND_RANGE: u8 = 48;
+const MASTER_BEND_RANGE: u8 = 2;
+/// CC 74 and pressure rest at these before a controller sends any.
+const TIMBRE_CENTER: u8 = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Zone {
+    Lower,
+    Upper,
+}
+
+impl Zone {
+    pub fn name(self) -> &'static str {
+        match self {
+            Zone::Lower => "lower",
+            Zone::Upper => "upper",
+        }
+    }
+
+    /// 0-based master channel.
+    pub fn master(self) -> u8 {
+        match self {
+            Zone::Lower => 0,
+            Zone::Upper => 15,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Channel {
+    // (note, velocity) in the order they went down
+    notes: Vec<(u8, u8)>,
+    bend: i16,
+    pressure: u8,
+    timbre: u8,
+    bend_range: u8,
+    // (MSB, LSB) of the selected RPN; 127/127 is none
+    rpn: (u8, u8),
+}
+
+impl Channel {
+    fn new(bend_range: u8) -> Self {
+        Self {
+            notes: Vec::new(),
+            bend: 0,
+            pressure: 0,
+            timbre: TIMBRE_CENTER,
+            bend_range,
+            rpn: (127, 127),
+        }
+    }
+
+    fn bend_semitones(&self) -> f64 {
+        self.bend as f64 / 8192.0 * self.bend_range as f64
+    }
+}
+
+/// A sounding note with its channel's expression.
+#[derive(Clone, Debug)]
+pub struct ActiveNote {
+    /// 0-based
+    pub channel: u8,
+    pub zone: Option<Zone>,
+    pub note: u8,
+    pub velocity: u8,
+    /// Member bend plus the zone master's, in semitones
+    pub bend: f64,
+    pub pressure: u8,
+    pub timbre: u8,
+}
+
+#[derive(Clone, Debug)]
+pub struct MpeState {
+    channels: Vec<Channel>,
+    lower_members: u8,
+    upper_members: u8,
+}
+
+impl Default for MpeState {
+    fn default() -> Self {
+        let mut state = Self {
+            channels: Vec::new(),
+            lower_members: DEFAULT_MEMBERS,
+            upper_members: 0,
+        };
+        state.reset_bend_ranges();
+        state
+    }
+}
+
+impl MpeState {
+    /// Member channel counts of the lower and upper zone.
+    pub fn layout(&self) -> (u8, u8) {
+        (self.lower_members, self.upper_members)
+    }
+
+    /// The zone `channel` (0-based) belongs to, as master or member.
+    pub fn zone_of(&self, channel: u8) -> Option<Zone> {
+        let lower = self.lower_members;
+        let upper = self.upper_members;
+        if lower > 0 && channel <= lower {
+            Some(Zone::Lower)
+        } else if upper > 0 && channel >= 15 - upper {
+            Some(Zone::Upper)
+        } else {
+            None
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for message in split_messages(data) {
+            self.update_one(&message);
+        }
+    }
+
+    fn update_one(&mut self, data: &[u8]) {
+        let Some(&status) = data.first().filter(|s| (0x80..0xF0).contains(*s)) else {
+            return;
+        };
+        let ch = (status & 0x0F) as usize;
+        let d1 = data.get(1).copied().unwrap_or(0) & 0x7F;
+        let d2 = data.get(2).copied().unwrap_or(0) & 0x7F;
+        let state = &mut self.channels[ch];
+        match (note_event(data), status & 0xF0) {
+            (Some(NoteEvent::On { note, velocity, .. }), _) => {
+                state.notes.retain(|(n, _)| *n != note);
+                state.notes.push((note, velocity));
+            }
+            (Some(NoteEvent::Off { note, .. }), _) => state.notes.retain(|(n, _)| *n != note),
+            (_, 0xD0) => state.pressure = d1,
+            (_, 0xE0) => state.bend = ((d2 as i16) << 7 | d1 as i16) - 8192,
+            (_, 0xB0) => match d1 {
+                74 => state.timbre = d2,
+                101 => state.rpn.0 = d2,
+                100 => state.rpn.1 = d2,
+                // Selecting an NRPN deselects the RPN, so its Data Entry isn't ours
+                98 | 99 => state.rpn = (127, 127),
+                6 => self.data_entry(ch as u8, d2),
+                // All Sound Off / All Notes Off
+                120 | 123 => state.notes.clear(),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    /// Data Entry MSB for the channel's selected RPN.
+    fn data_entry(&mut self, channel: u8, value: u8) {
+        match self.channels[channel as usize].rpn {
+            (0, 0) => self.channels[channel as usize].bend_range = value,
+            // MPE Configuration Message, only meaningful on a master channel
+            (0, 6) if channel == 0 || channel == 15 => {
+                let members = value.min(15);
+                let (this, other) = if channel == 0 {
+                    (&mut self.lower_members, &mut self.upper_members)
+                } else {
+                    (&mut self.upper_members, &mut self.lower_members)
+                };
+                *this = members;
+                // A zone that grows into the other one shrinks that one; the
+                // 14 channels between the masters are all they can share
+                if members > 0 && members + *other > 14 {
+                    *other = 14u8.saturating_sub(members);
+                }
+                self.reset_bend_ranges();
+            }
+            _ => {}
+        }
+    }
+
+    fn reset_bend_ranges(&mut self) {
+        let old = std::mem::take(&mut self.channels);
+        self.channels = (0..16u8)
+            .map(|ch| {
+                let range = match self.zone_of(ch) {
+                    Some(zone) if zone.master() == ch => MASTER_BEND_RANGE,
+                    Some(_) => MEMBER_BEND_RANGE,
+                    None => MASTER_BEND_RANGE,
+                };
+                let mut state = Channel::new(range);
+                if let Some(prev) = old.get(ch as usize) {
+                    state.notes.clone_from(&prev.notes);
+                }
+                state
+            })
+            .collect();
+    }
+
+    /// Sounding notes by channel, then in the order they were played.
+    pub fn active_notes(&self) -> Vec<ActiveNote> {
+        let mut out = Vec::new();
+        for (ch, state) in self.channels.iter().enumerate() {
+            let channel = ch as u8;
+            let zone = self.zone_of(channel);
+            let master_bend = zone
+                .filter(|z| z.master() != channel)
+                .map_or(0.0, |z| self.channels[z.master() as usize].bend_semitones());
+            for &(note, velocity) in &state.notes {
+                out.push(ActiveNote {
+                    channel,
+                    zone,
+                    note,
+                    velocity,
+                    bend: state.bend_semitones() + master_bend,
+                    pressure: state.pressure,
+                    timbre: state.timbre,
+                });
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RPN 6 (MPE Configuration Message) on a master channel.
+    fn configure(state: &mut MpeState, master: u8, members: u8) {
+        for (cc, value) in [(101, 0), (100, 6), (6, members)] {
+            state.update(&[0xB0 | master, cc, value]);
+        }
+    }
+
+    #[test]
+    fn nrpn_data_entry_leaves_the_rpn_alone() {
+        let mut state = MpeState::default();
+        for (cc, value) in [(101, 0), (100, 6), (99, 1), (98, 2), (6, 3)] {
+            state.update(&[0xB0, cc, value]);
+        }
+        assert_eq!(state.layout(), (15, 0));
+    }
+
+    #[test]
+    fn disabling_a_zone_leaves_the_other_alone() {
+        let mut state = MpeState::default();
+        configure(&mut state, 15, 0);
+        assert_eq!(state.layout(), (15, 0));
+        configure(&mut state, 0, 0);
+        configure(&mut state, 15, 15);
+        configure(&mut state, 0, 0);
+        assert_eq!(state.layout(), (0, 15));
+    }
+
+    #[test]
+    fn a_growing_zone_shrinks_the_other_only_on_overlap() {
+        let mut state = MpeState::default();
+        configure(&mut state, 0, 7);
+        assert_eq!(state.layout(), (7, 0));
+        configure(&mut state, 15, 7);
+        assert_eq!(state.layout(), (7, 7));
+        configure(&mut state, 15, 10);
+        assert_eq!(state.layout(), (4, 10));
+        configure(&mut state, 0, 15);
+        assert_eq!(state.layout(), (15, 0));
+        assert_eq!(state.zone_of(15), Some(Zone::Lower));
+    }
+}
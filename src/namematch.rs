@@ -0,0 +1,175 @@
+//! Device name patterns for `auto_open` rules.
+//!
+//! A rule is a glob such as `nanoKEY*` (`*` any run, `?` one character,
+//! matched against the whole name) or a `/regex/`. Regexes support the
+//! common subset `. [abc] [^a-z] * + ? ^ $` and `\` escapes, and match
+//! anywhere in the name unless anchored; groups and `|` are not supported.
+
+use anyhow::{anyhow, bail, Result};
+
+#[derive(Clone, Debug)]
+enum Atom {
+    Any,
+    Char(char),
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Atom {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Atom::Any => true,
+            Atom::Char(x) => *x == c,
+            Atom::Class { negated, ranges } => {
+                ranges.iter().any(|(lo, hi)| (*lo..=*hi).contains(&c)) != *negated
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Repeat {
+    One,
+    // `?`, `*`, `+`
+    Optional,
+    Star,
+    Plus,
+}
+
+/// A compiled rule; keeps its source text for log messages.
+#[derive(Clone, Debug)]
+pub struct NamePattern {
+    source: String,
+    pieces: Vec<(Atom, Repeat)>,
+    anchor_start: bool,
+    anchor_end: bool,
+}
+
+impl NamePattern {
+    pub fn parse(rule: &str) -> Result<Self> {
+        let (pieces, anchor_start, anchor_end) =
+            match rule.strip_prefix('/').and_then(|r| r.strip_suffix('/')) {
+                Some(re) => parse_regex(re)?,
+                None => (parse_glob(rule), true, true),
+            };
+        Ok(Self {
+            source: rule.to_string(),
+            pieces,
+            anchor_start,
+            anchor_end,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        let chars: Vec<char> = name.chars().collect();
+        if self.anchor_start {
+            return self.match_here(&self.pieces, &chars);
+        }
+        (0..=chars.len()).any(|i| self.match_here(&self.pieces, &chars[i..]))
+    }
+
+    /// Backtracking match of `pieces` at the start of `text`.
+    fn match_here(&self, pieces: &[(Atom, Repeat)], text: &[char]) -> bool {
+        let Some(((atom, repeat), rest)) = pieces.split_first() else {
+            return !self.anchor_end || text.is_empty();
+        };
+        let (min, max) = match repeat {
+            Repeat::One => (1, 1),
+            Repeat::Optional => (0, 1),
+            Repeat::Star => (0, usize::MAX),
+            Repeat::Plus => (1, usize::MAX),
+        };
+        let run = text
+            .iter()
+            .take(max)
+            .take_while(|c| atom.matches(**c))
+            .count();
+        // Greedy: try the longest run first
+        (min..=run).rev().any(|n| self.match_here(rest, &text[n..]))
+    }
+}
+
+fn parse_glob(glob: &str) -> Vec<(Atom, Repeat)> {
+    glob.chars()
+        .map(|c| match c {
+            '*' => (Atom::Any, Repeat::Star),
+            '?' => (Atom::Any, Repeat::One),
+            c => (Atom::Char(c), Repeat::One),
+        })
+        .collect()
+}
+
+type Compiled = (Vec<(Atom, Repeat)>, bool, bool);
+
+fn parse_regex(re: &str) -> Result<Compiled> {
+    let mut chars = re.chars().peekable();
+    let anchor_start = chars.next_if_eq(&'^').is_some();
+    let mut pieces: Vec<(Atom, Repeat)> = Vec::new();
+    let mut anchor_end = false;
+    while let Some(c) = chars.next() {
+        let atom = match c {
+            '$' if chars.peek().is_none() => {
+                anchor_end = true;
+                break;
+            }
+            '.' => Atom::Any,
+            '\\' => Atom::Char(
+                chars
+                    .next()
+                    .ok_or_else(|| anyhow!("trailing \\ in /{re}/"))?,
+            ),
+            '[' => parse_class(&mut chars).ok_or_else(|| anyhow!("unclosed [ in /{re}/"))?,
+            '*' | '+' | '?' => {
+                let Some(last) = pieces.last_mut().filter(|(_, r)| matches!(r, Repeat::One)) else {
+                    bail!("nothing to repeat before {c} in /{re}/");
+                };
+                last.1 = match c {
+                    '*' => Repeat::Star,
+                    '+' => Repeat::Plus,
+                    _ => Repeat::Optional,
+                };
+                continue;
+            }
+            '(' | ')' | '|' | '{' => bail!("{c} is not supported in /{re}/"),
+            c => Atom::Char(c),
+        };
+        pieces.push((atom, Repeat::One));
+    }
+    Ok((pieces, anchor_start, anchor_end))
+}
+
+/// The rest of a `[...]` class after its `[`; `None` if it never closes.
+fn parse_class(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<Atom> {
+    let negated = chars.next_if_eq(&'^').is_some();
+    let mut ranges = Vec::new();
+    let mut first = true;
+    loop {
+        let c = match chars.next()? {
+            // A leading ] is literal
+            ']' if !first => return Some(Atom::Class { negated, ranges }),
+            '\\' => chars.next()?,
+            c => c,
+        };
+        first = false;
+        let hi = match chars.peek() {
+            Some('-') => {
+                chars.next();
+                match chars.next()? {
+                    ']' => {
+                        ranges.extend([(c, c), ('-', '-')]);
+                        return Some(Atom::Class { negated, ranges });
+                    }
+                    hi => hi,
+                }
+            }
+            _ => c,
+        };
+        ranges.push((c, hi));
+    }
+}
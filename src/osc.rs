@@ -0,0 +1,141 @@
+//! Forwarding of incoming MIDI to an OSC endpoint over UDP (`--osc-out`).
+//!
+//! Only the small part of OSC 1.0 needed here is encoded by hand: one
+//! message per packet, int32 arguments only.
+
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+
+use anyhow::{Context, Result};
+use midir_tui::midi::split_messages;
+use serde::{Deserialize, Serialize};
+
+/// OSC addresses per message type; `{ch}` is replaced by the MIDI channel
+/// (1-16), so `/midi/{ch}/note` sends channel 3 notes to `/midi/3/note`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OscConfig {
+    /// Args: channel, note, velocity (0 for Note Off)
+    pub note: String,
+    /// Args: channel, controller, value
+    pub cc: String,
+    /// Args: channel, bend (-8192..=8191)
+    pub pitch_bend: String,
+}
+
+impl Default for OscConfig {
+    fn default() -> Self {
+        Self {
+            note: "/midi/note".to_string(),
+            cc: "/midi/cc".to_string(),
+            pitch_bend: "/midi/pitchbend".to_string(),
+        }
+    }
+}
+
+/// UDP sender of OSC messages translated from MIDI.
+pub struct OscSender {
+    socket: UdpSocket,
+    target: SocketAddr,
+    config: OscConfig,
+}
+
+impl OscSender {
+    /// Binds an ephemeral local port for sending to `target` (`host:port`).
+    /// The socket stays unconnected so a receiver that isn't up yet doesn't
+    /// turn into ECONNREFUSED on later sends.
+    pub fn connect(target: &str, config: OscConfig) -> Result<Self> {
+        let addr = target
+            .to_socket_addrs()
+            .with_context(|| format!("bad OSC target: {target}"))?
+            .next()
+            .with_context(|| format!("OSC target did not resolve: {target}"))?;
+        let local = if addr.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let socket = UdpSocket::bind(local).context("bind OSC socket failed")?;
+        Ok(Self {
+            socket,
+            target: addr,
+            config,
+        })
+    }
+
+    /// Sends one OSC message per note, CC or pitch bend in the packet; other
+    /// messages are skipped.
+    pub fn send_midi(&self, data: &[u8]) -> Result<()> {
+        for message in split_messages(data) {
+            let Some((address, args)) = self.translate(&message) else {
+                continue;
+            };
+            self.socket
+                .send_to(&packet(&address, &args), self.target)
+                .context("OSC send failed")?;
+        }
+        Ok(())
+    }
+
+    /// Address and args for one complete message; truncated ones are skipped.
+    fn translate(&self, message: &[u8]) -> Option<(String, Vec<i32>)> {
+        let &[status, d1, d2] = message else {
+            return None;
+        };
+        let ch = (status & 0x0F) as i32 + 1;
+        let (d1, d2) = (d1 as i32, d2 as i32);
+        let (template, args) = match status & 0xF0 {
+            0x80 => (&self.config.note, vec![ch, d1, 0]),
+            0x90 => (&self.config.note, vec![ch, d1, d2]),
+            0xB0 => (&self.config.cc, vec![ch, d1, d2]),
+            0xE0 => (&self.config.pitch_bend, vec![ch, (d2 << 7 | d1) - 8192]),
+            _ => return None,
+        };
+        Some((template.replace("{ch}", &ch.to_string()), args))
+    }
+}
+
+/// Encodes one OSC message with int32 arguments.
+fn packet(address: &str, args: &[i32]) -> Vec<u8> {
+    let mut out = Vec::new();
+    push_padded(&mut out, address.as_bytes());
+    let tags: String = std::iter::once(',')
+        .chain(args.iter().map(|_| 'i'))
+        .collect();
+    push_padded(&mut out, tags.as_bytes());
+    for arg in args {
+        out.extend_from_slice(&arg.to_be_bytes());
+    }
+    out
+}
+
+/// OSC strings are NUL-terminated and padded to a multiple of 4 bytes.
+fn push_padded(out: &mut Vec<u8>, s: &[u8]) {
+    out.extend_from_slice(s);
+    let pad = 4 - s.len() % 4;
+    out.extend(std::iter::repeat_n(0, pad));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_complete_messages_are_translated() {
+        let config = OscConfig {
+            note: "/midi/{ch}/note".to_string(),
+            ..OscConfig::default()
+        };
+        let osc = OscSender::connect("127.0.0.1:9", config).unwrap();
+        assert_eq!(
+            osc.translate(&[0x92, 60, 100]),
+            Some(("/midi/3/note".to_string(), vec![3, 60, 100]))
+        );
+        assert_eq!(
+            osc.translate(&[0xE0, 0x00, 0x40]),
+            Some(("/midi/pitchbend".to_string(), vec![1, 0]))
+        );
+        assert_eq!(osc.translate(&[0x90, 60]), None);
+        assert_eq!(osc.translate(&[0xB0, 7]), None);
+        assert_eq!(osc.translate(&[0x90, 60, 100, 62, 100]), None);
+    }
+}
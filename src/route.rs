@@ -0,0 +1,113 @@
+//! Thru routes from an input to an output, and what they do to the
+//! messages they forward.
+
+use serde::Serialize;
+
+use crate::{
+    devices::DeviceKey,
+    midi::{apply_velocity_curve, split_messages, VelocityCurve},
+};
+
+/// A thru route from an input to an output, optionally transposing notes
+/// and moving channel messages to another channel on the way.
+#[derive(Clone, Debug, Serialize)]
+pub struct Route {
+    pub src: DeviceKey,
+    pub dst: DeviceKey,
+    /// Semitones added to note numbers; results clamp to 0-127
+    pub transpose: i8,
+    /// Channel (0-15) channel messages are rewritten to
+    pub channel_map: Option<u8>,
+}
+
+impl Route {
+    /// A route that forwards messages unchanged.
+    pub fn new(src: DeviceKey, dst: DeviceKey) -> Self {
+        Self {
+            src,
+            dst,
+            transpose: 0,
+            channel_map: None,
+        }
+    }
+
+    /// Whether messages pass unchanged, so the callback can skip [`Self::process`].
+    pub fn is_plain(&self, curve: VelocityCurve) -> bool {
+        self.transpose == 0 && self.channel_map.is_none() && curve == VelocityCurve::Linear
+    }
+
+    /// The packet as forwarded, each of its messages processed in turn.
+    pub fn process(&self, packet: &[u8], curve: VelocityCurve) -> Vec<u8> {
+        let mut out = Vec::with_capacity(packet.len());
+        for message in split_messages(packet) {
+            out.extend(self.process_one(&message, curve));
+        }
+        out
+    }
+
+    /// One message as forwarded; system messages pass unchanged.
+    fn process_one(&self, message: &[u8], curve: VelocityCurve) -> Vec<u8> {
+        let mut out = message.to_vec();
+        let Some(status) = out.first().copied().filter(|s| (0x80..0xF0).contains(s)) else {
+            return out;
+        };
+        if let Some(ch) = self.channel_map {
+            out[0] = status & 0xF0 | ch & 0x0F;
+        }
+        // Note Off, Note On, poly aftertouch
+        if matches!(status & 0xF0, 0x80 | 0x90 | 0xA0) {
+            if let Some(note) = out.get_mut(1) {
+                *note = (*note as i16 + self.transpose as i16).clamp(0, 127) as u8;
+            }
+        }
+        if status & 0xF0 == 0x90 {
+            if let Some(vel) = out.get_mut(2) {
+                *vel = apply_velocity_curve(*vel, curve);
+            }
+        }
+        out
+    }
+
+    /// ` (+12, → ch 3)` for routes that change messages.
+    pub fn describe_processing(&self) -> String {
+        let mut parts = Vec::new();
+        if self.transpose != 0 {
+            parts.push(format!("{:+}", self.transpose));
+        }
+        if let Some(ch) = self.channel_map {
+            parts.push(format!("→ ch {}", ch + 1));
+        }
+        if parts.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", parts.join(", "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::MidiKind;
+
+    #[test]
+    fn routes_process_every_message_of_a_packet() {
+        let mut route = Route::new(key(MidiKind::Input), key(MidiKind::Output));
+        route.transpose = 12;
+        route.channel_map = Some(2);
+        assert_eq!(
+            route.process(&[0x90, 60, 100, 64, 0, 0xB0, 7, 90], VelocityCurve::Linear),
+            [0x92, 72, 100, 0x92, 76, 0, 0xB2, 7, 90]
+        );
+        let fixed = route.process(&[0x90, 60, 64, 64, 30], VelocityCurve::Fixed(100));
+        assert_eq!((fixed[2], fixed[5]), (100, 100));
+    }
+
+    fn key(kind: MidiKind) -> DeviceKey {
+        DeviceKey {
+            name: "Test Port".to_string(),
+            kind,
+            instance: 0,
+        }
+    }
+}
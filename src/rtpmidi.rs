@@ -0,0 +1,239 @@
+//! Sending side of an RTP-MIDI (AppleMIDI) network session (`--rtpmidi`).
+//!
+//! We act as session initiator towards a remote participant such as macOS'
+//! Network MIDI: invite on its control port and on the data port next to it,
+//! then stream each message as its own RTP-MIDI packet. The recovery journal
+//! is left out, which receivers accept but which means lost packets stay
+//! lost. Clock sync runs on a background thread for the whole session.
+
+use std::{
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::{anyhow, Context, Result};
+
+const PROTOCOL_VERSION: u32 = 2;
+/// RTP payload type used by Apple's implementation.
+const PAYLOAD_TYPE: u8 = 0x61;
+const INVITE_TRIES: usize = 3;
+const INVITE_TIMEOUT: Duration = Duration::from_millis(700);
+/// Initiators are expected to resync clocks regularly or get dropped.
+const SYNC_INTERVAL: Duration = Duration::from_secs(10);
+
+/// An accepted session with a remote participant; dropping it says goodbye.
+pub struct Session {
+    control: UdpSocket,
+    data: UdpSocket,
+    remote_control: SocketAddr,
+    remote_data: SocketAddr,
+    token: u32,
+    ssrc: u32,
+    seq: u16,
+    start: Instant,
+    // Set by the sync thread when the remote ends the session
+    ended: Arc<AtomicBool>,
+    stop: Arc<AtomicBool>,
+    sync: Option<thread::JoinHandle<()>>,
+}
+
+impl Session {
+    /// Invites `target` (`host:port` of its control port) under `name`.
+    pub fn connect(target: &str, name: &str) -> Result<Self> {
+        let remote_control = target
+            .to_socket_addrs()
+            .with_context(|| format!("bad RTP-MIDI target: {target}"))?
+            .next()
+            .with_context(|| format!("RTP-MIDI target did not resolve: {target}"))?;
+        let mut remote_data = remote_control;
+        remote_data.set_port(remote_control.port().wrapping_add(1));
+
+        let local = if remote_control.is_ipv4() {
+            "0.0.0.0"
+        } else {
+            "[::]"
+        };
+        let control = UdpSocket::bind(format!("{local}:0")).context("bind control socket")?;
+        let mut data = None;
+        // The data port must be the control port plus one
+        let control_port = control.local_addr()?.port();
+        if let Some(port) = control_port.checked_add(1) {
+            data = UdpSocket::bind(format!("{local}:{port}")).ok();
+        }
+        let data = data.context("data port next to the control port is taken; retry")?;
+
+        let seed = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.subsec_nanos() ^ d.as_secs() as u32)
+            .unwrap_or(0x5EED);
+        let (token, ssrc) = (seed, seed.rotate_left(16) ^ 0x4D49_4449);
+
+        let invite = command(b"IN", &[PROTOCOL_VERSION, token, ssrc], Some(name));
+        invite_on(&control, remote_control, &invite, token).context("control port")?;
+        invite_on(&data, remote_data, &invite, token).context("data port")?;
+
+        let start = Instant::now();
+        let ended = Arc::new(AtomicBool::new(false));
+        let stop = Arc::new(AtomicBool::new(false));
+        let sync = {
+            let socket = data.try_clone().context("clone data socket")?;
+            let (ended, stop) = (Arc::clone(&ended), Arc::clone(&stop));
+            thread::spawn(move || sync_loop(&socket, remote_data, ssrc, start, &ended, &stop))
+        };
+        Ok(Self {
+            control,
+            data,
+            remote_control,
+            remote_data,
+            token,
+            ssrc,
+            seq: 0,
+            start,
+            ended,
+            stop,
+            sync: Some(sync),
+        })
+    }
+
+    /// Sends one complete MIDI message (or SysEx) as an RTP-MIDI packet.
+    pub fn send(&mut self, message: &[u8]) -> Result<()> {
+        if self.ended.load(Ordering::Relaxed) {
+            return Err(anyhow!("remote ended the RTP-MIDI session"));
+        }
+        if message.len() > 0x0FFF {
+            return Err(anyhow!("message too long for one RTP-MIDI packet"));
+        }
+        let mut packet = Vec::with_capacity(14 + message.len());
+        packet.extend_from_slice(&[0x80, PAYLOAD_TYPE]);
+        packet.extend_from_slice(&self.seq.to_be_bytes());
+        packet.extend_from_slice(&(timestamp(self.start) as u32).to_be_bytes());
+        packet.extend_from_slice(&self.ssrc.to_be_bytes());
+        // MIDI command section header: no journal, no leading delta time
+        let len = message.len();
+        if len <= 0x0F {
+            packet.push(len as u8);
+        } else {
+            packet.extend_from_slice(&[0x80 | (len >> 8) as u8, len as u8]);
+        }
+        packet.extend_from_slice(message);
+        self.seq = self.seq.wrapping_add(1);
+        self.data
+            .send_to(&packet, self.remote_data)
+            .context("RTP-MIDI send failed")?;
+        Ok(())
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        let bye = command(b"BY", &[PROTOCOL_VERSION, self.token, self.ssrc], None);
+        let _ = self.control.send_to(&bye, self.remote_control);
+        if let Some(sync) = self.sync.take() {
+            let _ = sync.join();
+        }
+    }
+}
+
+/// Session time in the protocol's 100 µs units.
+fn timestamp(start: Instant) -> u64 {
+    (start.elapsed().as_micros() / 100) as u64
+}
+
+/// An AppleMIDI command: 0xFFFF, two letters, big-endian words, optional
+/// NUL-terminated name.
+fn command(name: &[u8; 2], words: &[u32], text: Option<&str>) -> Vec<u8> {
+    let mut out = vec![0xFF, 0xFF, name[0], name[1]];
+    for w in words {
+        out.extend_from_slice(&w.to_be_bytes());
+    }
+    if let Some(text) = text {
+        out.extend_from_slice(text.as_bytes());
+        out.push(0);
+    }
+    out
+}
+
+/// Sends the invitation until the remote answers OK or NO for our token.
+fn invite_on(socket: &UdpSocket, remote: SocketAddr, invite: &[u8], token: u32) -> Result<()> {
+    socket.set_read_timeout(Some(INVITE_TIMEOUT))?;
+    let mut buf = [0u8; 512];
+    for _ in 0..INVITE_TRIES {
+        socket.send_to(invite, remote).context("send invitation")?;
+        let deadline = Instant::now() + INVITE_TIMEOUT;
+        while Instant::now() < deadline {
+            let Ok((n, from)) = socket.recv_from(&mut buf) else {
+                break;
+            };
+            let reply = &buf[..n];
+            let for_us = from.ip() == remote.ip()
+                && reply.len() >= 12
+                && reply[8..12] == token.to_be_bytes();
+            match reply.get(..4) {
+                Some([0xFF, 0xFF, b'O', b'K']) if for_us => return Ok(()),
+                Some([0xFF, 0xFF, b'N', b'O']) if for_us => {
+                    return Err(anyhow!("invitation declined by {remote}"))
+                }
+                _ => {}
+            }
+        }
+    }
+    Err(anyhow!("no answer to the invitation from {remote}"))
+}
+
+/// Answers and initiates clock sync (CK) on the data port, and notices BY.
+fn sync_loop(
+    socket: &UdpSocket,
+    remote: SocketAddr,
+    ssrc: u32,
+    start: Instant,
+    ended: &AtomicBool,
+    stop: &AtomicBool,
+) {
+    let _ = socket.set_read_timeout(Some(Duration::from_millis(200)));
+    let ck = |count: u8, stamps: [u64; 3]| {
+        let mut out = command(b"CK", &[ssrc], None);
+        out.extend_from_slice(&[count, 0, 0, 0]);
+        for s in stamps {
+            out.extend_from_slice(&s.to_be_bytes());
+        }
+        out
+    };
+    let mut last_sync: Option<Instant> = None;
+    let mut buf = [0u8; 512];
+    while !stop.load(Ordering::Relaxed) {
+        if last_sync.is_none_or(|t| t.elapsed() >= SYNC_INTERVAL) {
+            let _ = socket.send_to(&ck(0, [timestamp(start), 0, 0]), remote);
+            last_sync = Some(Instant::now());
+        }
+        let Ok((n, _)) = socket.recv_from(&mut buf) else {
+            continue;
+        };
+        let packet = &buf[..n];
+        match packet.get(..4) {
+            Some([0xFF, 0xFF, b'C', b'K']) if n >= 36 => {
+                let stamp = |i: usize| {
+                    let mut b = [0u8; 8];
+                    b.copy_from_slice(&packet[12 + i * 8..20 + i * 8]);
+                    u64::from_be_bytes(b)
+                };
+                let reply = match packet[8] {
+                    0 => ck(1, [stamp(0), timestamp(start), 0]),
+                    1 => ck(2, [stamp(0), stamp(1), timestamp(start)]),
+                    _ => continue,
+                };
+                let _ = socket.send_to(&reply, remote);
+            }
+            Some([0xFF, 0xFF, b'B', b'Y']) => {
+                ended.store(true, Ordering::Relaxed);
+                return;
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,391 @@
+//! Standard MIDI File (SMF) reading and writing.
+
+use std::{collections::HashMap, fs, path::Path};
+
+use crate::midi::{note_event, split_messages, NoteEvent};
+use anyhow::{bail, Context, Result};
+
+/// Ticks per quarter note in files we write.
+pub const TICKS_PER_QUARTER: u16 = 480;
+/// Tempo written to the file, in microseconds per quarter note (120 BPM).
+pub const DEFAULT_TEMPO_US: u32 = 500_000;
+
+/// Writes a format-0 file from `(microsecond timestamp, message)` pairs.
+/// Packed packets are split into their messages. A SysEx that arrives in
+/// chunks is written as an F0 event plus F7 continuation escapes, and one left
+/// unterminated is closed with an escaped F7. System common and realtime
+/// messages have no SMF encoding and are skipped.
+pub fn write_smf(path: &Path, events: &[(u64, Vec<u8>)]) -> Result<()> {
+    let track = encode_track(events);
+
+    let mut out = Vec::with_capacity(22 + track.len());
+    out.extend_from_slice(b"MThd");
+    out.extend_from_slice(&6u32.to_be_bytes());
+    out.extend_from_slice(&0u16.to_be_bytes()); // format 0
+    out.extend_from_slice(&1u16.to_be_bytes()); // one track
+    out.extend_from_slice(&TICKS_PER_QUARTER.to_be_bytes());
+    out.extend_from_slice(b"MTrk");
+    out.extend_from_slice(&(track.len() as u32).to_be_bytes());
+    out.extend_from_slice(&track);
+
+    fs::write(path, out).with_context(|| format!("write {}", path.display()))
+}
+
+fn encode_track(events: &[(u64, Vec<u8>)]) -> Vec<u8> {
+    let mut track = Vec::new();
+
+    // Tempo meta event so the tick timing below is unambiguous
+    write_vlq(&mut track, 0);
+    track.extend_from_slice(&[0xFF, 0x51, 0x03]);
+    track.extend_from_slice(&DEFAULT_TEMPO_US.to_be_bytes()[1..]);
+
+    let start = events.first().map(|(t, _)| *t).unwrap_or(0);
+    let mut last_tick = 0u64;
+    // Inside a SysEx whose closing F7 has not been written yet
+    let mut in_sysex = false;
+    for (stamp, packet) in events {
+        for msg in split_messages(packet) {
+            let Some(&first) = msg.first() else {
+                continue;
+            };
+            let continues = first < 0x80 && in_sysex;
+            if !continues && !(0x80..=0xF0).contains(&first) {
+                continue;
+            }
+            if in_sysex && !continues {
+                close_sysex(&mut track);
+                in_sysex = false;
+            }
+            let tick = us_to_ticks(stamp.saturating_sub(start));
+            write_vlq(&mut track, tick.saturating_sub(last_tick) as u32);
+            last_tick = last_tick.max(tick);
+            if first == 0xF0 {
+                // F0 <length> <bytes after F0, including the closing F7>
+                track.push(0xF0);
+                write_vlq(&mut track, (msg.len() - 1) as u32);
+                track.extend_from_slice(&msg[1..]);
+                in_sysex = msg.last() != Some(&0xF7);
+            } else if continues {
+                // F7 <length> <continuation bytes>: an escape, no F7 implied
+                track.push(0xF7);
+                write_vlq(&mut track, msg.len() as u32);
+                track.extend_from_slice(&msg);
+                in_sysex = msg.last() != Some(&0xF7);
+            } else {
+                track.extend_from_slice(&msg);
+            }
+        }
+    }
+    if in_sysex {
+        close_sysex(&mut track);
+    }
+
+    // End of track
+    write_vlq(&mut track, 0);
+    track.extend_from_slice(&[0xFF, 0x2F, 0x00]);
+    track
+}
+
+/// Escaped lone F7, ending a SysEx whose last chunk never came.
+fn close_sysex(track: &mut Vec<u8>) {
+    write_vlq(track, 0);
+    track.extend_from_slice(&[0xF7, 0x01, 0xF7]);
+}
+
+fn us_to_ticks(us: u64) -> u64 {
+    us * TICKS_PER_QUARTER as u64 / DEFAULT_TEMPO_US as u64
+}
+
+/// Variable-length quantity: 7 bits per byte, high bit set on all but the last.
+fn write_vlq(out: &mut Vec<u8>, mut value: u32) {
+    let mut buf = [0u8; 5];
+    let mut i = buf.len() - 1;
+    buf[i] = (value & 0x7F) as u8;
+    value >>= 7;
+    while value > 0 {
+        i -= 1;
+        buf[i] = (value & 0x7F) as u8 | 0x80;
+        value >>= 7;
+    }
+    out.extend_from_slice(&buf[i..]);
+}
+
+/// Reads a format 0 or 1 file into `(microsecond time, message)` pairs,
+/// merging all tracks and applying the tempo map. Meta events are consumed.
+pub fn read_smf(path: &Path) -> Result<Vec<(u64, Vec<u8>)>> {
+    let bytes = fs::read(path).with_context(|| format!("read {}", path.display()))?;
+    parse_smf(&bytes).with_context(|| format!("parse {}", path.display()))
+}
+
+/// Re-times `(microsecond time, message)` pairs in ticks of
+/// [`TICKS_PER_QUARTER`] at a steady `bpm`, counted from the first event.
+pub fn ticks_at_bpm(events: &[(u64, Vec<u8>)], bpm: f64) -> Vec<(u64, Vec<u8>)> {
+    let start = events.first().map(|(t, _)| *t).unwrap_or(0);
+    let ticks_per_us = bpm * TICKS_PER_QUARTER as f64 / 60_000_000.0;
+    events
+        .iter()
+        .map(|(t, msg)| {
+            let tick = (t.saturating_sub(start) as f64 * ticks_per_us).round() as u64;
+            (tick, msg.clone())
+        })
+        .collect()
+}
+
+/// Snaps each Note On to the nearest multiple of `grid_ticks` and moves its
+/// Note Off by the same amount, so notes keep their length. Everything else
+/// keeps its time; the result is in time order, ties in input order.
+pub fn quantize(events: &[(u64, Vec<u8>)], grid_ticks: u64) -> Vec<(u64, Vec<u8>)> {
+    if grid_ticks == 0 {
+        return events.to_vec();
+    }
+    // (shift, snapped start) per sounding (channel, note)
+    let mut sounding: HashMap<(u8, u8), (i64, u64)> = HashMap::new();
+    let mut out: Vec<(u64, Vec<u8>)> = events
+        .iter()
+        .map(|(t, msg)| {
+            let t = *t;
+            let at = match note_event(msg) {
+                Some(NoteEvent::On { channel, note, .. }) => {
+                    let snapped = (t + grid_ticks / 2) / grid_ticks * grid_ticks;
+                    sounding.insert((channel, note), (snapped as i64 - t as i64, snapped));
+                    snapped
+                }
+                Some(NoteEvent::Off { channel, note, .. }) => {
+                    match sounding.remove(&(channel, note)) {
+                        // Never before its own start
+                        Some((shift, start)) => ((t as i64 + shift).max(0) as u64).max(start),
+                        None => t,
+                    }
+                }
+                None => t,
+            };
+            (at, msg.clone())
+        })
+        .collect();
+    out.sort_by_key(|(t, _)| *t);
+    out
+}
+
+enum TrackEvent {
+    Message(Vec<u8>),
+    Tempo(u32),
+}
+
+fn parse_smf(bytes: &[u8]) -> Result<Vec<(u64, Vec<u8>)>> {
+    let mut r = Reader { bytes, pos: 0 };
+    if r.take(4)? != b"MThd" {
+        bail!("not a MIDI file (missing MThd)");
+    }
+    let header_len = r.u32()? as usize;
+    let header = r.take(header_len)?;
+    if header.len() < 6 {
+        bail!("short MThd header");
+    }
+    let format = u16::from_be_bytes([header[0], header[1]]);
+    let division = u16::from_be_bytes([header[4], header[5]]);
+    if format > 1 {
+        bail!("unsupported SMF format {format}");
+    }
+
+    // (tick, track, sequence, event); the sort keeps in-track order stable
+    let mut events: Vec<(u64, usize, usize, TrackEvent)> = Vec::new();
+    let mut track = 0;
+    while r.pos < bytes.len() {
+        let id = r.take(4)?;
+        let len = r.u32()? as usize;
+        let chunk = r.take(len)?;
+        if id == b"MTrk" {
+            parse_track(chunk, track, &mut events)?;
+            track += 1;
+        }
+    }
+    events.sort_by_key(|(tick, track, seq, _)| (*tick, *track, *seq));
+
+    let mut out = Vec::new();
+    let mut tempo = DEFAULT_TEMPO_US as u64;
+    let (mut last_tick, mut us) = (0u64, 0u64);
+    for (tick, _, _, ev) in events {
+        us += ticks_to_us(tick - last_tick, division, tempo);
+        last_tick = tick;
+        match ev {
+            TrackEvent::Tempo(t) => tempo = t as u64,
+            TrackEvent::Message(m) => out.push((us, m)),
+        }
+    }
+    Ok(out)
+}
+
+fn ticks_to_us(ticks: u64, division: u16, tempo_us: u64) -> u64 {
+    if division & 0x8000 != 0 {
+        // SMPTE: -frames per second in the high byte, ticks per frame in the low
+        let fps = (-((division >> 8) as i8) as i64).max(1) as u64;
+        let per_frame = (division & 0xFF).max(1) as u64;
+        ticks * 1_000_000 / (fps * per_frame)
+    } else {
+        ticks * tempo_us / (division.max(1) as u64)
+    }
+}
+
+fn parse_track(
+    chunk: &[u8],
+    track: usize,
+    events: &mut Vec<(u64, usize, usize, TrackEvent)>,
+) -> Result<()> {
+    let mut r = Reader {
+        bytes: chunk,
+        pos: 0,
+    };
+    let mut tick = 0u64;
+    let mut running: Option<u8> = None;
+    let mut seq = 0;
+    while r.pos < chunk.len() {
+        tick += r.vlq()? as u64;
+        let mut status = r.peek()?;
+        if status & 0x80 != 0 {
+            r.pos += 1;
+        } else {
+            status = running.context("data byte without running status")?;
+        }
+        let ev = match status {
+            0xFF => {
+                let kind = r.byte()?;
+                let len = r.vlq()? as usize;
+                let data = r.take(len)?;
+                match (kind, data) {
+                    (0x2F, _) => break,
+                    (0x51, &[a, b, c]) => Some(TrackEvent::Tempo(u32::from_be_bytes([0, a, b, c]))),
+                    _ => None,
+                }
+            }
+            0xF0 | 0xF7 => {
+                let len = r.vlq()? as usize;
+                let data = r.take(len)?;
+                let mut msg = Vec::with_capacity(len + 1);
+                if status == 0xF0 {
+                    msg.push(0xF0);
+                }
+                msg.extend_from_slice(data);
+                running = None;
+                Some(TrackEvent::Message(msg))
+            }
+            0x80..=0xEF => {
+                running = Some(status);
+                let n = if matches!(status & 0xF0, 0xC0 | 0xD0) {
+                    1
+                } else {
+                    2
+                };
+                let mut msg = vec![status];
+                msg.extend_from_slice(r.take(n)?);
+                Some(TrackEvent::Message(msg))
+            }
+            _ => bail!("unexpected status byte {status:02X} in track {track}"),
+        };
+        if let Some(ev) = ev {
+            events.push((tick, track, seq, ev));
+            seq += 1;
+        }
+    }
+    Ok(())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        let slice = self
+            .bytes
+            .get(self.pos..self.pos + n)
+            .context("unexpected end of file")?;
+        self.pos += n;
+        Ok(slice)
+    }
+
+    fn peek(&self) -> Result<u8> {
+        self.bytes
+            .get(self.pos)
+            .copied()
+            .context("unexpected end of file")
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let b = self.take(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn vlq(&mut self) -> Result<u32> {
+        let mut value = 0u32;
+        for _ in 0..4 {
+            let b = self.byte()?;
+            value = (value << 7) | (b & 0x7F) as u32;
+            if b & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        bail!("variable-length quantity too long")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packed_and_chunked_messages_round_trip() {
+        let events = vec![
+            (0, vec![0x90, 0x3C, 0x40, 0x3E, 0x00]),
+            (1_000, vec![0xF0, 0x7E, 0x7F]),
+            (1_000, vec![0x06, 0x01, 0xF7]),
+            (2_000, vec![0xF0, 0x43, 0x10]),
+            (3_000, vec![0x80, 0x3C, 0x00]),
+            (4_000, vec![0xF0, 0x41]),
+        ];
+        let parsed: Vec<Vec<u8>> = parse_smf(&smf_bytes(&events))
+            .unwrap()
+            .into_iter()
+            .map(|(_, m)| m)
+            .collect();
+        assert_eq!(
+            parsed,
+            vec![
+                vec![0x90, 0x3C, 0x40],
+                vec![0x90, 0x3E, 0x00],
+                vec![0xF0, 0x7E, 0x7F],
+                vec![0x06, 0x01, 0xF7],
+                vec![0xF0, 0x43, 0x10],
+                // The unterminated SysEx is closed before the next event
+                vec![0xF7],
+                vec![0x80, 0x3C, 0x00],
+                vec![0xF0, 0x41],
+                vec![0xF7],
+            ]
+        );
+    }
+
+    #[test]
+    fn stray_data_bytes_are_not_written() {
+        let events = vec![(0, vec![0x06, 0x01, 0xF7]), (0, vec![0xF8])];
+        let parsed = parse_smf(&smf_bytes(&events)).unwrap();
+        assert!(parsed.is_empty());
+    }
+
+    fn smf_bytes(events: &[(u64, Vec<u8>)]) -> Vec<u8> {
+        let track = encode_track(events);
+        let mut out = Vec::new();
+        out.extend_from_slice(b"MThd");
+        out.extend_from_slice(&6u32.to_be_bytes());
+        out.extend_from_slice(&[0, 0, 0, 1]);
+        out.extend_from_slice(&TICKS_PER_QUARTER.to_be_bytes());
+        out.extend_from_slice(b"MTrk");
+        out.extend_from_slice(&(track.len() as u32).to_be_bytes());
+        out.extend_from_slice(&track);
+        out
+    }
+}
@@ -0,0 +1,117 @@
+//! Color palettes for the UI, picked by name in `config.json`.
+
+use ratatui::style::Color;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThemeName {
+    #[default]
+    Dark,
+    Light,
+    HighContrast,
+}
+
+impl ThemeName {
+    pub fn next(self) -> Self {
+        match self {
+            ThemeName::Dark => ThemeName::Light,
+            ThemeName::Light => ThemeName::HighContrast,
+            ThemeName::HighContrast => ThemeName::Dark,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ThemeName::Dark => "dark",
+            ThemeName::Light => "light",
+            ThemeName::HighContrast => "high_contrast",
+        }
+    }
+
+    pub fn palette(self) -> Theme {
+        match self {
+            ThemeName::Dark => Theme {
+                label: Color::Yellow,
+                accent: Color::Cyan,
+                muted: Color::DarkGray,
+                alert: Color::Red,
+                ok: Color::Green,
+                marker: Color::Magenta,
+                selection_bg: Color::Blue,
+                selection_fg: Color::White,
+                key_idle: Color::Gray,
+                devices: [
+                    Color::Cyan,
+                    Color::Green,
+                    Color::Yellow,
+                    Color::Magenta,
+                    Color::LightBlue,
+                    Color::LightRed,
+                ],
+            },
+            // Named colors are often pale on white backgrounds, so use RGB
+            ThemeName::Light => Theme {
+                label: Color::Rgb(0, 0, 160),
+                accent: Color::Rgb(0, 110, 160),
+                muted: Color::Rgb(120, 120, 120),
+                alert: Color::Rgb(190, 0, 0),
+                ok: Color::Rgb(0, 130, 0),
+                marker: Color::Rgb(150, 0, 150),
+                selection_bg: Color::Rgb(0, 90, 200),
+                selection_fg: Color::White,
+                key_idle: Color::Rgb(80, 80, 80),
+                devices: [
+                    Color::Rgb(0, 110, 160),
+                    Color::Rgb(0, 130, 0),
+                    Color::Rgb(160, 90, 0),
+                    Color::Rgb(150, 0, 150),
+                    Color::Rgb(40, 40, 200),
+                    Color::Rgb(190, 0, 0),
+                ],
+            },
+            ThemeName::HighContrast => Theme {
+                label: Color::LightYellow,
+                accent: Color::LightCyan,
+                muted: Color::White,
+                alert: Color::LightRed,
+                ok: Color::LightGreen,
+                marker: Color::LightMagenta,
+                selection_bg: Color::White,
+                selection_fg: Color::Black,
+                key_idle: Color::White,
+                devices: [
+                    Color::LightCyan,
+                    Color::LightGreen,
+                    Color::LightYellow,
+                    Color::LightMagenta,
+                    Color::LightBlue,
+                    Color::LightRed,
+                ],
+            },
+        }
+    }
+}
+
+/// Colors by role rather than by hue.
+#[derive(Clone, Copy, Debug)]
+pub struct Theme {
+    /// Field labels and kind tags
+    pub label: Color,
+    /// Focused borders, modals and live markers
+    pub accent: Color,
+    /// Unfocused borders, hints and placeholders
+    pub muted: Color,
+    /// Alert flash and closed ports
+    pub alert: Color,
+    /// Open ports and active routes
+    pub ok: Color,
+    /// Short-lived notices such as a changed port index
+    pub marker: Color,
+    pub selection_bg: Color,
+    pub selection_fg: Color,
+    /// White keys of the held-notes strip when not sounding
+    pub key_idle: Color,
+    /// Log colors for telling open inputs apart, picked by name hash
+    pub devices: [Color; 6],
+}